@@ -2,6 +2,7 @@
 
 use circuit::BCircuit;
 use quine_mccluskey::qm_simplify_many;
+use signal::Signal;
 use table::Table;
 use types::CLOCK_PIN;
 
@@ -9,6 +10,7 @@ mod circuit;
 mod clock_manager;
 mod components;
 mod quine_mccluskey;
+mod signal;
 mod table;
 mod types;
 mod utils;
@@ -41,15 +43,15 @@ fn aoc24_24() {
             let lab = &toks[0][0..toks[0].len() - 1];
             let initval = toks[1].parse::<i32>().unwrap();
             // println!("{} {}", lab, initval);
-            c.add_input(lab, if initval == 1 { true } else { false });
+            c.add_input(lab, Signal::from(initval == 1));
         }
     }
     c.compile();
-    c.power_on();
+    c.power_on().unwrap();
     let mut num = 0;
     for id in &c.outputs {
         let comp = c.get_component(id).unwrap().borrow();
-        let val = if comp.state { 1_u64 } else { 0 };
+        let val = if comp.state.is_high() { 1_u64 } else { 0 };
         let bitposn = comp.label[1..].parse::<i32>().unwrap();
         num = num | (val << bitposn);
     }
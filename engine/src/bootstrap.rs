@@ -1,93 +1,410 @@
-use crate::{circuit::BCircuit, components::ComponentDefParams, types::CompType};
+use crate::{
+    bus::resolve_bus,
+    circuit::BCircuit,
+    clock_manager::Trigger,
+    components::{reducer, ComponentDefParams},
+    memory::{ram_access, rom_access, MemoryDef},
+    pattern::Lane,
+    signal::{Signal, Word},
+    types::{CompType, PIN},
+};
 
 pub(crate) fn bootstrap_ckt(c: &mut BCircuit) {
     c.define_gate(ComponentDefParams {
         name: "Input".into(),
         label: String::new(),
         comp_type: CompType::Input,
-        eval: |_, old| old,
+        eval: reducer(|_, old| old),
         default_inputs: 0,
         symbol: "".into(),
+        ..Default::default()
     });
     c.define_gate(ComponentDefParams {
         name: "NAND".into(),
         label: String::new(),
         comp_type: CompType::Combinational,
-        eval: |v, _| {
-            return !(v[1] && v[2]);
-            // return !(v.iter().fold(true, |a, b| a && *b));
-        },
+        eval: reducer(|v, _| !fold_pins(v, Word::and)),
+        pattern_eval: Some(|p, out| {
+            fold_lanes(p, out, Lane::and);
+            for o in out.iter_mut() {
+                *o = !*o;
+            }
+        }),
         default_inputs: 2,
         symbol: "!.".into(),
+        variadic: true,
+        ..Default::default()
     });
 
     c.define_gate(ComponentDefParams {
         name: "AND".into(),
         label: String::new(),
         comp_type: CompType::Combinational,
-        eval: |v, _| {
-            return v[1] && v[2];
-            // return v.iter().fold(true, |a, b| a && *b);
-        },
+        eval: reducer(|v, _| fold_pins(v, Word::and)),
+        pattern_eval: Some(|p, out| fold_lanes(p, out, Lane::and)),
         default_inputs: 2,
         symbol: ".".into(),
+        variadic: true,
+        ..Default::default()
     });
 
     c.define_gate(ComponentDefParams {
         name: "OR".into(),
         label: String::new(),
         comp_type: CompType::Combinational,
-        eval: |v, _| {
-            return v[1] || v[2];
-            // return v.iter().fold(false, |a, b| a || *b);
-        },
+        eval: reducer(|v, _| fold_pins(v, Word::or)),
+        pattern_eval: Some(|p, out| fold_lanes(p, out, Lane::or)),
         default_inputs: 2,
         symbol: "+".into(),
+        variadic: true,
+        ..Default::default()
     });
 
     c.define_gate(ComponentDefParams {
         name: "XOR".into(),
         label: String::new(),
         comp_type: CompType::Combinational,
-        eval: |v, _| {
-            return v[1] != v[2];
-        },
+        eval: reducer(|v, _| fold_pins(v, Word::xor)),
+        pattern_eval: Some(|p, out| fold_lanes(p, out, Lane::xor)),
         default_inputs: 2,
         symbol: "*".into(),
-    }); 
+        variadic: true,
+        ..Default::default()
+    });
 
     c.define_gate(ComponentDefParams {
         name: "NOT".into(),
         label: String::new(),
         comp_type: CompType::Combinational,
-        eval: |v, _| {
+        eval: reducer(|v, _| {
             return !v[1];
-        },
+        }),
+        pattern_eval: Some(|p, out| {
+            for (i, o) in out.iter_mut().enumerate() {
+                *o = !p[1][i];
+            }
+        }),
         default_inputs: 1,
         symbol: "!".into(),
+        ..Default::default()
     });
 
     c.define_gate(ComponentDefParams {
         name: "BFR".into(), // buffer
         label: String::new(),
         comp_type: CompType::Combinational,
-        eval: |v, _| {
-            return v[1];
-        },
+        eval: reducer(|v, _| {
+            return v[1].read();
+        }),
+        pattern_eval: Some(read_pin_1),
         default_inputs: 1,
         symbol: "".into(),
+        ..Default::default()
     });
 
+    // flip-flops, the N variants are triggered by the falling edge
+    for (trigger, suffix) in [(Trigger::RisingEdge, ""), (Trigger::FallingEdge, "N")] {
+        define_flip_flop(c, &format!("D{}", suffix), trigger, &["D"], |d, _, _| {
+            d.read()
+        });
+        define_flip_flop(c, &format!("T{}", suffix), trigger, &["T"], |t, _, q| {
+            complete(
+                t,
+                Signal::Zero,
+                q,
+                |t, _, q| if t == Signal::One { !q } else { q },
+            )
+        });
+        define_flip_flop(c, &format!("SR{}", suffix), trigger, &["S", "R"], sr_next);
+        define_flip_flop(c, &format!("JK{}", suffix), trigger, &["J", "K"], jk_next);
+    }
+    // latches, transparent while the clock pin (enable) is high
+    define_flip_flop(c, "DLATCH", Trigger::High, &["D"], |d, _, _| d.read());
+    define_flip_flop(c, "SRLATCH", Trigger::High, &["S", "R"], sr_next);
+
+    define_registers(c);
+    define_memories(c);
+
     c.define_gate(ComponentDefParams {
-        name: "JK".into(),
+        name: "TRI".into(), // tri-state buffer, pin 1 data, pin 2 enable
         label: String::new(),
-        comp_type: CompType::Sequential,
-        eval: |v, q| {
-            let j = v[1];
-            let k = v[2];
-            (j && !q) || (!k && q)
-        },
+        comp_type: CompType::Combinational,
+        eval: reducer(|v, _| match v[2].bit(0).read() {
+            Signal::Zero => Word::hiz(v[1].width()),
+            Signal::One => v[1].read(),
+            _ => Word::unknown(v[1].width()),
+        }),
+        pattern_eval: Some(|p, out| {
+            for (i, o) in out.iter_mut().enumerate() {
+                *o = p[1][i].tri(p[2][0]);
+            }
+        }),
+        default_inputs: 2,
+        symbol: "/".into(),
+        control_pins: vec![2],
+        ..Default::default()
+    });
+
+    c.define_gate(ComponentDefParams {
+        name: "BUS".into(), // wired net, every input pin is a driver
+        label: String::new(),
+        comp_type: CompType::Combinational,
+        eval: reducer(|v, old| resolve_bus(&v[1..], old.width())),
+        pattern_eval: Some(|p, out| {
+            for (i, o) in out.iter_mut().enumerate() {
+                *o = p[1..]
+                    .iter()
+                    .fold(Lane::splat(Signal::Z), |a, d| a.resolve(d[i]));
+            }
+        }),
+        default_inputs: 1,
+        symbol: "|".into(),
+        multi_driver: true,
+        ..Default::default()
+    });
+
+    c.define_gate(ComponentDefParams {
+        name: "SPLIT".into(), // one bit of a bus, see `BCircuit::add_splitter`
+        label: String::new(),
+        comp_type: CompType::Combinational,
+        eval: reducer(|v, _| v[1].read()),
+        pattern_eval: Some(read_pin_1),
+        default_inputs: 1,
+        symbol: "".into(),
+        ..Default::default()
+    });
+
+    c.define_gate(ComponentDefParams {
+        name: "MERGE".into(), // pin i + 1 drives bit i, see `BCircuit::add_merger`
+        label: String::new(),
+        comp_type: CompType::Combinational,
+        eval: reducer(|v, old| Word::from_fn(old.width(), |i| v[i + 1].bit(0).read())),
+        pattern_eval: Some(|p, out| {
+            for (i, o) in out.iter_mut().enumerate() {
+                *o = p[i + 1][0].read();
+            }
+        }),
         default_inputs: 2,
-        symbol: "JK".into(),
+        symbol: "#".into(),
+        width: 2,
+        control_pins: vec![1, 2],
+        ..Default::default()
+    });
+
+    c.define_gate(ComponentDefParams {
+        name: "CONCAT".into(), // pins joined from the lsb up, see `BCircuit::flatten`
+        label: String::new(),
+        comp_type: CompType::Combinational,
+        eval: reducer(|v, _| Word::concat(&v[1..])),
+        pattern_eval: Some(|p, out| {
+            for (o, l) in out.iter_mut().zip(p[1..].concat()) {
+                *o = l;
+            }
+        }),
+        default_inputs: 1,
+        symbol: "#".into(),
+        ..Default::default()
+    });
+}
+
+// a clocked component with the `data` pins, followed by the asynchronous
+// preset and clear pins. `next` gives the next value of each bit from
+// that bit of pins 1 and 2 and of the state.
+fn define_flip_flop(
+    c: &mut BCircuit,
+    name: &str,
+    trigger: Trigger,
+    data: &[&str],
+    next: fn(Signal, Signal, Signal) -> Signal,
+) {
+    let n = data.len() as u16;
+    let (pre, clr) = (n as PIN + 1, n as PIN + 2);
+    let clk = if trigger.is_edge() { "CLK" } else { "EN" };
+    c.define_gate(ComponentDefParams {
+        name: name.into(),
+        label: String::new(),
+        comp_type: CompType::Sequential,
+        eval: reducer(move |v, q| {
+            Word::from_fn(q.width(), |i| {
+                let b = if n > 1 { v[2].bit(i) } else { Signal::Zero };
+                next(v[1].bit(i), b, q.bit(i))
+            })
+        }),
+        default_inputs: n + 2,
+        symbol: name.into(),
+        control_pins: vec![pre, clr],
+        trigger,
+        async_pins: vec![(pre, Signal::One), (clr, Signal::Zero)],
+        pin_names: [&[clk], data, &["PRE", "CLR"]]
+            .concat()
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+        ..Default::default()
     });
 }
+
+// multi bit registers, 4 bits wide unless added with
+// `BCircuit::add_component_with_width`. Their state is the stored
+// value, the `D` pins are as wide and the others are single bits. All
+// are triggered by the rising edge and have an asynchronous clear.
+// Shift registers shift towards the msb, which is the serial output,
+// see `BCircuit::add_siso` for serial in, serial out registers.
+fn define_registers(c: &mut BCircuit) {
+    // loads D while EN is high
+    define_register(c, "REG", &["D", "EN"], |v, q| {
+        Word::mux(v[2].bit(0), q, v[1])
+    });
+    // counts while EN is high, up if UP is high and down otherwise.
+    // LOAD loads D instead, regardless of EN.
+    define_register(c, "COUNTER", &["D", "LOAD", "EN", "UP"], |v, q| {
+        let step = Word::mux(v[4].bit(0), add(q, u64::MAX), add(q, 1));
+        Word::mux(v[2].bit(0), Word::mux(v[3].bit(0), q, step), v[1])
+    });
+    // shifts SI in while EN is high
+    define_register(c, "SIPO", &["SI", "EN"], |v, q| {
+        Word::mux(v[2].bit(0), q, shift_in(q, v[1].bit(0)))
+    });
+    // loads D while LOAD is high, shifts SI in otherwise
+    define_register(c, "PISO", &["D", "LOAD", "SI"], |v, q| {
+        Word::mux(v[2].bit(0), shift_in(q, v[3].bit(0)), v[1])
+    });
+}
+
+// 8 bit wide memories with 16 words unless added with
+// `BCircuit::add_memory`, see `memory`
+fn define_memories(c: &mut BCircuit) {
+    let names = |n: &[&str]| n.iter().map(|s| s.to_string()).collect();
+    c.define_gate(ComponentDefParams {
+        name: "ROM".into(),
+        label: String::new(),
+        comp_type: CompType::Combinational,
+        default_inputs: 1,
+        symbol: "ROM".into(),
+        width: 8,
+        pin_names: names(&["", "ADDR"]),
+        memory: Some(MemoryDef {
+            access: rom_access,
+            addr_pin: 1,
+            read_only: true,
+        }),
+        ..Default::default()
+    });
+    for (name, comp_type, clk) in [
+        ("RAM", CompType::Sequential, "CLK"),
+        ("ARAM", CompType::Combinational, ""),
+    ] {
+        c.define_gate(ComponentDefParams {
+            name: name.into(),
+            label: String::new(),
+            comp_type,
+            default_inputs: 3,
+            symbol: name.into(),
+            width: 8,
+            control_pins: vec![3],
+            pin_names: names(&[clk, "ADDR", "DIN", "WE"]),
+            memory: Some(MemoryDef {
+                access: ram_access,
+                addr_pin: 1,
+                read_only: false,
+            }),
+            ..Default::default()
+        });
+    }
+}
+
+// a register with the `data` pins and an asynchronous clear. Pins
+// other than D are control pins.
+fn define_register(c: &mut BCircuit, name: &str, data: &[&str], eval: fn(&[Word], Word) -> Word) {
+    let clr = data.len() + 1;
+    c.define_gate(ComponentDefParams {
+        name: name.into(),
+        label: String::new(),
+        comp_type: CompType::Sequential,
+        eval: reducer(eval),
+        default_inputs: clr as u16,
+        symbol: name.into(),
+        width: 4,
+        control_pins: (1..=clr)
+            .filter(|p| data.get(p - 1) != Some(&"D"))
+            .collect(),
+        async_pins: vec![(clr, Signal::Zero)],
+        pin_names: [&["CLK"], data, &["CLR"]]
+            .concat()
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+        ..Default::default()
+    });
+}
+
+// `q + n`, wrapping around
+fn add(q: Word, n: u64) -> Word {
+    match q.to_u64() {
+        Some(v) => Word::from_u64(v.wrapping_add(n), q.width()),
+        None => Word::unknown(q.width()),
+    }
+}
+
+// `q` shifted towards the msb, `si` entering at the lsb
+fn shift_in(q: Word, si: Signal) -> Word {
+    if q.width() == 1 {
+        return Word::from(si.read());
+    }
+    Word::concat(&[Word::from(si.read()), q.slice(0, q.width() - 1)])
+}
+
+// `f` if the unknown one of `a` and `b` doesn't matter, X otherwise
+fn complete(a: Signal, b: Signal, q: Signal, f: fn(Signal, Signal, Signal) -> Signal) -> Signal {
+    let values = |s: Signal| match s.read() {
+        Signal::X => vec![Signal::Zero, Signal::One],
+        s => vec![s],
+    };
+    let mut outs = values(a)
+        .into_iter()
+        .flat_map(|a| values(b).into_iter().map(move |b| f(a, b, q)));
+    let first = outs.next().unwrap();
+    if outs.all(|o| o == first) {
+        first
+    } else {
+        Signal::X
+    }
+}
+
+// S = R = 1 is not allowed and gives X
+fn sr_next(s: Signal, r: Signal, q: Signal) -> Signal {
+    complete(s, r, q, |s, r, q| match (s, r) {
+        (Signal::Zero, Signal::Zero) => q,
+        (Signal::One, Signal::Zero) => Signal::One,
+        (Signal::Zero, Signal::One) => Signal::Zero,
+        _ => Signal::X,
+    })
+}
+
+fn jk_next(j: Signal, k: Signal, q: Signal) -> Signal {
+    match (j.read(), k.read()) {
+        (Signal::Zero, Signal::Zero) => q,
+        (Signal::One, Signal::Zero) => Signal::One,
+        (Signal::Zero, Signal::One) => Signal::Zero,
+        (Signal::One, Signal::One) => !q,
+        // hold if both outcomes agree, e.g. J=X K=0 with Q=1
+        (j, k) => (j.and(!q)).or((!k).and(q)),
+    }
+}
+
+// reduces the data pins of a variadic gate with `f`
+fn fold_pins(v: &[Word], f: fn(&Word, Word) -> Word) -> Word {
+    v[2..].iter().fold(v[1].read(), |a, b| f(&a, *b))
+}
+
+fn fold_lanes(p: &[&[Lane]], out: &mut [Lane], f: fn(Lane, Lane) -> Lane) {
+    for (i, o) in out.iter_mut().enumerate() {
+        *o = p[2..].iter().fold(p[1][i].read(), |a, b| f(a, b[i]));
+    }
+}
+
+fn read_pin_1(p: &[&[Lane]], out: &mut [Lane]) {
+    for (i, o) in out.iter_mut().enumerate() {
+        *o = p[1][i].read();
+    }
+}
@@ -0,0 +1,229 @@
+// Shared nets driven by more than one component, typically through
+// tri-state buffers. A net is a component whose definition sets
+// `multi_driver`: every input pin is a driver and its output is the
+// resolved value of all drivers.
+//
+// Resolution rules:
+// - drivers that are `Z` are ignored, if all of them are `Z` the net
+//   floats (`Z`).
+// - if the remaining drivers agree on 0 or 1, that is the net value.
+// - otherwise (a driver is `X`, or both 0 and 1 are driven) the net is
+//   `X`. The latter case is a contention and is reported by
+//   `BCircuit::bus_contentions`.
+
+use std::fmt;
+
+use crate::{
+    circuit::BCircuit,
+    signal::{Signal, Word},
+    types::{ID, NULL, PIN},
+};
+
+pub fn resolve_drivers(drivers: &[Signal]) -> Signal {
+    let mut res = Signal::Z;
+    for d in drivers {
+        res = match (res, *d) {
+            (r, Signal::Z) => r,
+            (Signal::Z, d) => d,
+            (r, d) if r == d => r,
+            _ => Signal::X,
+        };
+    }
+    res
+}
+
+// bitwise `resolve_drivers` over bus drivers of `width` bits
+pub fn resolve_bus(drivers: &[Word], width: u8) -> Word {
+    Word::from_fn(width, |i| {
+        resolve_drivers(&drivers.iter().map(|d| d.bit(i)).collect::<Vec<Signal>>())
+    })
+}
+
+// Two or more drivers of `bus` actively drive opposite values.
+#[derive(Debug, PartialEq)]
+pub struct Contention {
+    pub bus: ID,
+    pub label: String,
+    // (driver, value) of every driver that is not fully floating
+    pub drivers: Vec<(ID, Word)>,
+}
+
+impl fmt::Display for Contention {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bus contention on id_{}", self.bus)?;
+        if !self.label.is_empty() {
+            write!(f, " ({})", self.label)?;
+        }
+        write!(f, ", drivers:")?;
+        for (id, val) in &self.drivers {
+            write!(f, " id_{}={}", id, val)?;
+        }
+        Ok(())
+    }
+}
+
+impl BCircuit {
+    // connects `emitter` as an additional driver of the multi driver
+    // component `bus`, returning the pin it was attached to.
+    pub fn connect_driver(&mut self, bus: ID, emitter: ID) -> Result<PIN, String> {
+        let pin = match self.components().get(&bus) {
+            Some(b) if b.borrow().multi_driver => b.borrow().free_driver_pin(),
+            Some(_) => return Err(format!("id_{} does not accept multiple drivers", bus)),
+            None => return Err(format!("No receiver with id {}", bus)),
+        };
+        self.connect(bus, pin, emitter)?;
+        Ok(pin)
+    }
+
+    pub fn disconnect_driver(&mut self, bus: ID, emitter: ID) -> Result<(), String> {
+        let pin = match self.components().get(&bus) {
+            Some(b) => b
+                .borrow()
+                .input_pin_sources
+                .iter()
+                .skip(1)
+                .position(|src| *src == emitter)
+                .map(|p| p + 1),
+            None => return Err(format!("No receiver with id {}", bus)),
+        };
+        match pin {
+            Some(pin) => self.disconnect(bus, pin, emitter),
+            None => Err(format!("id_{} does not drive id_{}", emitter, bus)),
+        }
+    }
+
+    pub fn bus_contentions(&self) -> Vec<Contention> {
+        let mut res = Vec::new();
+        for (id, c) in self.components() {
+            let c = c.borrow();
+            if !c.multi_driver {
+                continue;
+            }
+            let drivers = (1..c.num_inputs())
+                .filter(|pin| c.input_pin_sources[*pin] != NULL)
+                .map(|pin| (c.input_pin_sources[pin], c.pin_value(pin)))
+                .filter(|(_, val)| *val != Word::hiz(val.width()))
+                .collect::<Vec<(ID, Word)>>();
+            let driven_high = drivers.iter().fold(0, |a, d| a | d.1.ones());
+            let driven_low = drivers.iter().fold(0, |a, d| a | d.1.zeros());
+            if driven_high & driven_low != 0 {
+                res.push(Contention {
+                    bus: *id,
+                    label: c.label.clone(),
+                    drivers,
+                });
+            }
+        }
+        res.sort_by_key(|c| c.bus);
+        res
+    }
+
+    // in strict mode, operations that leave a bus in contention return
+    // an error describing it. The circuit state is still updated.
+    pub fn set_strict_buses(&mut self, strict: bool) {
+        self.strict_buses = strict;
+    }
+
+    pub(crate) fn check_buses(&self) -> Result<(), String> {
+        if !self.strict_buses {
+            return Ok(());
+        }
+        let errs = self.bus_contentions();
+        if errs.is_empty() {
+            return Ok(());
+        }
+        Err(errs
+            .iter()
+            .map(|c| c.to_string())
+            .collect::<Vec<String>>()
+            .join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        circuit::BCircuit,
+        signal::Signal::{self, One, Zero, X, Z},
+    };
+
+    use super::resolve_drivers;
+
+    #[test]
+    fn resolution() {
+        assert_eq!(resolve_drivers(&[]), Z);
+        assert_eq!(resolve_drivers(&[Z, Z]), Z);
+        assert_eq!(resolve_drivers(&[Z, One, Z]), One);
+        assert_eq!(resolve_drivers(&[Zero, Zero]), Zero);
+        assert_eq!(resolve_drivers(&[Zero, One]), X);
+        assert_eq!(resolve_drivers(&[X, Z]), X);
+    }
+
+    fn shared_bus() -> (BCircuit, [i32; 5]) {
+        let mut c = BCircuit::new();
+        c.power_on().unwrap();
+        let a = c.add_input("A", One);
+        let b = c.add_input("B", Zero);
+        let ea = c.add_input("EA", Zero);
+        let eb = c.add_input("EB", Zero);
+        let ta = c.add_component("TRI", "").unwrap();
+        let tb = c.add_component("TRI", "").unwrap();
+        let bus = c.add_component("BUS", "D0").unwrap();
+        c.connect(ta, 1, a).unwrap();
+        c.connect(ta, 2, ea).unwrap();
+        c.connect(tb, 1, b).unwrap();
+        c.connect(tb, 2, eb).unwrap();
+        assert_eq!(c.connect_driver(bus, ta).unwrap(), 1);
+        assert_eq!(c.connect_driver(bus, tb).unwrap(), 2);
+        (c, [ea, eb, ta, tb, bus])
+    }
+
+    #[test]
+    fn tri_state_bus() {
+        let (mut c, [ea, eb, _, tb, bus]) = shared_bus();
+        let st = |c: &BCircuit| c.state(bus).unwrap();
+        assert_eq!(st(&c), Z);
+        c.set_component_state(ea, One).unwrap();
+        assert_eq!(st(&c), One);
+        c.set_component_state(ea, Zero).unwrap();
+        c.set_component_state(eb, One).unwrap();
+        assert_eq!(st(&c), Zero);
+        assert!(c.bus_contentions().is_empty());
+
+        c.set_component_state(ea, One).unwrap();
+        assert_eq!(st(&c), X);
+        let cont = c.bus_contentions();
+        assert_eq!(cont.len(), 1);
+        assert_eq!(cont[0].bus, bus);
+        assert_eq!(cont[0].drivers.len(), 2);
+
+        c.disconnect_driver(bus, tb).unwrap();
+        assert_eq!(st(&c), One);
+        assert!(c.disconnect_driver(bus, tb).is_err());
+        // freed pins are reused
+        assert_eq!(c.connect_driver(bus, tb).unwrap(), 2);
+    }
+
+    #[test]
+    fn strict_buses() {
+        let (mut c, [ea, eb, ..]) = shared_bus();
+        c.set_strict_buses(true);
+        c.set_component_state(ea, One).unwrap();
+        let err = c.set_component_state(eb, One).unwrap_err();
+        assert!(err.contains("D0"));
+        assert!(c.set_component_state(ea, Zero).is_ok());
+        assert_eq!(c.state(ea).unwrap(), Signal::Zero);
+    }
+
+    #[test]
+    fn bus_round_trips_through_netlist() {
+        let (c, [.., bus]) = shared_bus();
+        let mut d = BCircuit::new();
+        d.load_netlist(&c.to_netlist().unwrap()).unwrap();
+        assert_eq!(d.to_netlist(), c.to_netlist());
+        assert_eq!(
+            d.get_component(&bus).unwrap().borrow().num_inputs(),
+            c.get_component(&bus).unwrap().borrow().num_inputs()
+        );
+    }
+}
@@ -1,30 +1,49 @@
 use crate::{
     bootstrap::bootstrap_ckt,
+    clocks::Clock,
     components::{
         power_on_component, set_expressions, update_component_state, ComponentDefParams, Gate,
     },
-    table::{bitwise_counter, Table},
+    pattern::PATTERNS,
+    signal::{Word, MAX_WIDTH},
+    table::{counter_row, Table},
+    timing::Timeline,
     types::{CompType, ComponentActor, ID, NULL, PIN},
+    waveform::Recording,
 };
 use std::{
     cell::RefCell,
-    collections::{hash_map::Values, HashMap, HashSet, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
 };
 
+// rows `gen_bus_table` and `gen_truth_table` build at most. Pattern
+// simulation runs 64 rows at a time, so past 22 inputs the table itself,
+// a vector per row, is what doesn't fit in memory. Wider circuits can be
+// compared with `check_equivalence`, which keeps no rows.
+pub const MAX_TRUTH_TABLE_ROWS: u64 = 1 << 22;
+// the same for circuits that can't be levelized, which are settled and
+// clocked once per row
+pub const MAX_SERIAL_TABLE_ROWS: u64 = 1 << 16;
+
 pub struct BCircuit {
     pub component_definitions: HashMap<String, ComponentDefParams>,
     // todo: Maybe using RC<Refcell<>> will ease connection management
     // since then each gate would only hold a reference to neighbouring
     // gates instead of separately storing state, expr, src.
-    components: HashMap<ID, RefCell<Gate>>,
+    pub(crate) components: HashMap<ID, RefCell<Gate>>,
 
     // actual input components reside in the `components`
-    inputs: HashMap<String, ID>,
+    pub(crate) inputs: HashMap<String, ID>,
     pub outputs: HashSet<ID>,
-    last_id: ID,
+    pub(crate) last_id: ID,
     pub exec_queue: VecDeque<ID>,
-    active: bool,
-    clk: Option<ID>,
+    pub(crate) active: bool,
+    pub(crate) clocks: Vec<Clock>,
+    pub(crate) strict_buses: bool,
+    pub(crate) timeline: Timeline,
+    // evaluations after which a zero delay update is considered unstable
+    pub(crate) max_iters: usize,
+    pub(crate) recording: Option<Recording>,
 }
 
 impl BCircuit {
@@ -38,7 +57,11 @@ impl BCircuit {
             last_id: 0,
             exec_queue: VecDeque::new(),
             active: false,
-            clk: None,
+            clocks: Vec::new(),
+            strict_buses: false,
+            timeline: Timeline::default(),
+            max_iters: 5000,
+            recording: None,
         };
         bootstrap_ckt(&mut c);
 
@@ -50,7 +73,9 @@ impl BCircuit {
         self.outputs.clear();
         self.last_id = 0;
         self.exec_queue.clear();
-        self.clk = None;
+        self.clocks.clear();
+        self.recording = None;
+        self.clear_timeline();
     }
     pub fn components(&self) -> &HashMap<ID, RefCell<Gate>> {
         return &self.components;
@@ -60,65 +85,177 @@ impl BCircuit {
         match c {
             Some(c) => {
                 c.borrow_mut().label.replace_range(std::ops::RangeFull, lab);
-                self.graph_act(set_expressions, &vec![id]);
-                Ok(())
+                self.graph_act(set_expressions, &vec![id])
             }
             None => Err(format!("Component with id_{} not found", id)),
         }
     }
+    // makes the input `id` a clock named by its label, with a period of
+    // 2 that starts low. See `add_clock` for other waveforms.
     pub fn clock(&mut self, id: ID) {
-        self.clk = Some(id)
-    }
-    pub fn get_clk_id(&self) -> Option<ID> {
-        return self.clk;
-    }
-    pub fn pulse_clock(&mut self) {
-        if self.clk.is_none() {
+        if self.is_clock(id) {
             return;
         }
-        let clk = self.clk.unwrap();
-        let curr_state = self.state(clk).unwrap();
-        self.set_component_state(clk, !curr_state).unwrap();
-        self.set_component_state(clk, curr_state).unwrap();
+        if let Some(c) = self.components.get(&id) {
+            self.clocks.push(Clock {
+                name: c.borrow().label.clone(),
+                id,
+                period: 2,
+                phase: 1,
+                high: 1,
+            });
+        }
+    }
+    // the first clock
+    pub fn get_clk_id(&self) -> Option<ID> {
+        return self.clocks.first().map(|c| c.id);
     }
-    pub fn refresh(&mut self) {
-        self.graph_act(update_component_state, &self.all_inputs_and_states());
+    pub fn refresh(&mut self) -> Result<(), String> {
+        self.graph_act(update_component_state, &self.all_inputs_and_states())
     }
-    pub fn power_on(&mut self) {
+    pub fn power_on(&mut self) -> Result<(), String> {
         self.active = true;
-        self.graph_act(power_on_component, &self.all_inputs_and_states());
         println!("POWER ON");
+        self.graph_act(power_on_component, &self.all_inputs_and_states())
+    }
+    // clears every flip-flop, latch and register to 0, as they power on
+    // unknown, and settles the circuit. Memories keep their contents.
+    pub fn reset_states(&mut self) -> Result<(), String> {
+        if !self.active {
+            return Err("Power on the circuit first!".into());
+        }
+        let mut childs = Vec::new();
+        for id in self.all_inputs_and_states() {
+            let width = {
+                let c = self.components[&id].borrow();
+                if c.comp_type == CompType::Input || c.memory().is_some() {
+                    continue;
+                }
+                c.width()
+            };
+            childs.extend(self.drive(id, Word::from_u64(0, width))?);
+        }
+        self.graph_act(update_component_state, &childs)
+    }
+    // number of component evaluations a single update may take before
+    // the circuit is reported as oscillating
+    pub fn set_iteration_limit(&mut self, max_iters: usize) {
+        self.max_iters = max_iters;
     }
 
-    fn graph_act(&mut self, runnable: ComponentActor, inits: &Vec<ID>) {
+    pub(crate) fn graph_act(
+        &mut self,
+        runnable: ComponentActor,
+        inits: &Vec<ID>,
+    ) -> Result<(), String> {
         // traverse in breadth-first fashion, starting from received `inits`
         // and calls the specified function until queue vacates.
         for k in inits {
             self.exec_queue.push_back(*k);
         }
 
+        // once the budget is spent, keep going for a few more rounds
+        // to see which components keep changing.
+        let window = 2 * self.components.len() + 16;
+        let mut unstable = HashSet::new();
         let mut iters = 0;
-        // todo: puede ser mejor que esto.
-        let max_iters = 5000;
-
-        while !self.exec_queue.is_empty() && iters < max_iters {
-            let id = self.exec_queue.pop_front().unwrap();
+        while iters < self.max_iters + window {
+            let id = match self.exec_queue.pop_front() {
+                Some(id) => id,
+                None => {
+                    self.record_changes();
+                    return Ok(());
+                }
+            };
             let mut k = self.components.get(&id).unwrap().borrow_mut();
+            let before = k.state;
             runnable(&mut k, &self.components, &mut self.exec_queue);
+            if iters >= self.max_iters && k.state != before {
+                unstable.insert(id);
+            }
             iters += 1;
         }
+        self.exec_queue.clear();
+        if unstable.is_empty() {
+            // only expressions of a feedback loop kept growing
+            self.record_changes();
+            return Ok(());
+        }
+        let err = format!(
+            "Circuit did not settle within {} iterations, oscillating: {}",
+            self.max_iters,
+            self.describe_loop(&unstable)
+        );
+        // rather than leaving the loop wherever it stopped, its outputs
+        // become unknown, which usually is stable.
+        let mut childs = Vec::new();
+        for id in &unstable {
+            let w = self.components.get(id).unwrap().borrow().width();
+            childs.extend(self.drive(*id, Word::unknown(w))?);
+        }
+        let _ = self.graph_act(update_component_state, &childs);
+        Err(err)
+    }
+    // the components of `ids` that lie on a cycle through `ids`, or all
+    // of them if there is no such cycle.
+    pub(crate) fn describe_loop(&self, ids: &HashSet<ID>) -> String {
+        let on_cycle = |start: ID| {
+            let mut seen = HashSet::new();
+            let mut stack = vec![start];
+            while let Some(id) = stack.pop() {
+                let c = self.components.get(&id).unwrap().borrow();
+                for (next, _) in c.get_output_receivers() {
+                    if *next == start {
+                        return true;
+                    }
+                    if ids.contains(next) && seen.insert(*next) {
+                        stack.push(*next);
+                    }
+                }
+            }
+            false
+        };
+        let mut members = ids
+            .iter()
+            .cloned()
+            .filter(|id| on_cycle(*id))
+            .collect::<Vec<ID>>();
+        if members.is_empty() {
+            members = ids.iter().cloned().collect();
+        }
+        members.sort();
+        members
+            .iter()
+            .map(|id| self.describe(*id))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+    // `id_<id> <type>`, followed by the label if there is one
+    pub(crate) fn describe(&self, id: ID) -> String {
+        let c = self.components.get(&id).unwrap().borrow();
+        if c.label.is_empty() {
+            format!("id_{} {}", id, c.name)
+        } else {
+            format!("id_{} {} ({})", id, c.name, c.label)
+        }
     }
     pub fn define_gate(&mut self, p: ComponentDefParams) {
         self.component_definitions
             .insert(String::from(p.name.clone()), ComponentDefParams::from(p));
     }
 
-    fn make_component(&mut self, typ: &str, label: &str) -> Result<Gate, String> {
+    pub(crate) fn make_component(&mut self, typ: &str, label: &str) -> Result<Gate, String> {
         let def = self.component_definitions.get(typ);
         if def.is_none() {
             return Err(format!("Component type not defined {}", typ));
         }
         let mut def = def.unwrap().clone();
+        if def.subcircuit.as_ref().is_some_and(|s| !s.is_black_box()) {
+            return Err(format!(
+                "{} has clocked components or loops, add it with add_flattened",
+                typ
+            ));
+        }
         def.label = label.to_string();
         return Ok(Gate::from_params(def));
     }
@@ -134,6 +271,85 @@ impl BCircuit {
             Err(e) => Err(e),
         }
     }
+    // adds a component whose output and data pins are `width` bits wide
+    pub fn add_component_with_width(
+        &mut self,
+        typ: &str,
+        label: &str,
+        width: u8,
+    ) -> Result<ID, String> {
+        if width == 0 || width > MAX_WIDTH {
+            return Err(format!(
+                "Bus width must be between 1 and {}, got {}",
+                MAX_WIDTH, width
+            ));
+        }
+        let mut comp = self.make_component(typ, label)?;
+        comp.set_width(width);
+        let id = self.new_id();
+        comp.id = id;
+        self.components.insert(id, RefCell::new(comp));
+        Ok(id)
+    }
+    // adds a variadic gate, e.g. a 5 input AND, with `n` data pins
+    pub fn add_component_with_inputs(
+        &mut self,
+        typ: &str,
+        label: &str,
+        n: usize,
+    ) -> Result<ID, String> {
+        let min = match self.component_definitions.get(typ) {
+            Some(d) if d.variadic => d.default_inputs as usize,
+            Some(_) => return Err(format!("{} has a fixed number of inputs", typ)),
+            None => return Err(format!("Component type not defined {}", typ)),
+        };
+        if n < min {
+            return Err(format!("{} needs at least {} inputs, got {}", typ, min, n));
+        }
+        let mut comp = self.make_component(typ, label)?;
+        comp.add_input_pins(n - min);
+        let id = self.new_id();
+        comp.id = id;
+        self.components.insert(id, RefCell::new(comp));
+        Ok(id)
+    }
+    // breaks `bus` into single bit components, returned least
+    // significant bit first. Bit i is labelled `<label>[i]`.
+    pub fn add_splitter(&mut self, label: &str, bus: ID) -> Result<Vec<ID>, String> {
+        let width = match self.components.get(&bus) {
+            Some(b) => b.borrow().width(),
+            None => return Err(format!("No emitter with id {}", bus)),
+        };
+        let mut bits = Vec::new();
+        for i in 0..width {
+            let id = self.add_component("SPLIT", &format!("{}[{}]", label, i))?;
+            self.connect_slice(id, 1, bus, i)?;
+            bits.push(id);
+        }
+        Ok(bits)
+    }
+    // adds a serial in, serial out shift register of `width` bits: a
+    // SIPO register labelled `label`, whose pins are connected as usual,
+    // and a single bit `<label>.SO` reading its msb. Returns both.
+    pub fn add_siso(&mut self, label: &str, width: u8) -> Result<(ID, ID), String> {
+        let reg = self.add_component_with_width("SIPO", label, width)?;
+        let so = self.add_component("SPLIT", &format!("{}.SO", label))?;
+        self.connect_slice(so, 1, reg, width - 1)?;
+        Ok((reg, so))
+    }
+    // adds a component joining `width` single bit pins into a bus,
+    // pin i + 1 drives bit i.
+    pub fn add_merger(&mut self, label: &str, width: u8) -> Result<ID, String> {
+        if width < 2 {
+            return Err(format!("A merger needs at least 2 bits, got {}", width));
+        }
+        let id = self.add_component_with_width("MERGE", label, width)?;
+        let mut m = self.components.get(&id).unwrap().borrow_mut();
+        let extra = width as usize + 1 - m.num_inputs();
+        m.add_input_pins(extra);
+        m.set_pin_widths(&vec![1; width as usize + 1]);
+        Ok(id)
+    }
     pub fn remove_component(&mut self, id: ID) -> Result<(), String> {
         if !self.components.contains_key(&id) {
             return Err(format!("Component with id {} not found", id));
@@ -175,15 +391,16 @@ impl BCircuit {
             }
         }
         self.components.remove(&id);
+        self.clocks.retain(|c| c.id != id);
         self.graph_act(
             update_component_state,
             &orlist.iter().map(|a| a.0).collect(),
-        );
-        Ok(())
+        )?;
+        self.check_buses()
     }
-    pub fn add_input(&mut self, label: &str, init_val: bool) -> ID {
+    pub fn add_input(&mut self, label: &str, init_val: impl Into<Word>) -> ID {
         // todo: unique label enforcement for inputs and clocked comps
-        let mut inp = Gate::make_input(label, init_val);
+        let mut inp = Gate::make_input(label, init_val.into());
         let id = self.new_id();
         inp.id = id;
         self.components.insert(id, RefCell::new(inp));
@@ -193,14 +410,37 @@ impl BCircuit {
     pub fn get_component(&self, id: &ID) -> Option<&RefCell<Gate>> {
         return self.components.get(id);
     }
-    pub fn set_component_state(&mut self, id: ID, val: bool) -> Result<(), String> {
+    pub fn set_component_state(&mut self, id: ID, val: impl Into<Word>) -> Result<(), String> {
         if !self.active {
             return Err(format!("Power on the circuit first!"));
         }
-
-        let childs = match self.components.get(&id) {
+        let val = val.into();
+        if self.is_timed() {
+            // applied at the current time, receivers follow after their delays
+            let now = self.now();
+            self.schedule_state(id, val, now)?;
+            return self.advance_to(now);
+        }
+        let childs = self.drive(id, val)?;
+        self.graph_act(update_component_state, &childs)?;
+        self.check_buses()
+    }
+    // sets the state of `id` and the pins it drives, returning the
+    // receivers that need an update.
+    pub(crate) fn drive(&mut self, id: ID, val: Word) -> Result<Vec<ID>, String> {
+        match self.components.get(&id) {
             //todo: might panic if component is connected to itself
             Some(k) => {
+                let width = k.borrow().width();
+                if val.width() != width {
+                    return Err(format!(
+                        "id_{} is {} bit(s) wide, can't set it to the {} bit value {}",
+                        id,
+                        width,
+                        val.width(),
+                        val
+                    ));
+                }
                 k.borrow_mut().set_state(val);
                 for (id, pin) in k.borrow().get_output_receivers() {
                     self.components
@@ -209,28 +449,61 @@ impl BCircuit {
                         .borrow_mut()
                         .set_pin_val(*pin, val);
                 }
-                k.borrow()
+                Ok(k.borrow()
                     .get_output_receivers()
                     .iter()
                     .map(|a| a.0)
-                    .collect()
+                    .collect())
             }
-            None => return Err(format!("No element with id_{}", id)),
-        };
-
-        self.graph_act(update_component_state, &childs);
-        Ok(())
+            None => Err(format!("No element with id_{}", id)),
+        }
     }
     pub fn connect(&mut self, receiver_id: ID, pin: PIN, emitter_id: ID) -> Result<(), String> {
-        let res = self.do_connect(receiver_id, pin, emitter_id);
+        self.connect_with(receiver_id, pin, emitter_id, None)
+    }
+    // connects the pin called `port` of `receiver_id`, see `port_pin`
+    pub fn connect_named(
+        &mut self,
+        receiver_id: ID,
+        port: &str,
+        emitter_id: ID,
+    ) -> Result<(), String> {
+        let pin = self.port_pin(receiver_id, port)?;
+        self.connect(receiver_id, pin, emitter_id)
+    }
+    // connects `pin` to the bits of `emitter`'s output starting at
+    // `lsb`, as many as the pin is wide.
+    pub fn connect_slice(
+        &mut self,
+        receiver_id: ID,
+        pin: PIN,
+        emitter_id: ID,
+        lsb: u8,
+    ) -> Result<(), String> {
+        self.connect_with(receiver_id, pin, emitter_id, Some(lsb))
+    }
+    fn connect_with(
+        &mut self,
+        receiver_id: ID,
+        pin: PIN,
+        emitter_id: ID,
+        slice: Option<u8>,
+    ) -> Result<(), String> {
+        let res = self.do_connect(receiver_id, pin, emitter_id, slice);
         if res.is_err() {
             return res;
         }
-        self.graph_act(update_component_state, &vec![receiver_id]);
-        self.graph_act(set_expressions, &vec![receiver_id]);
-        Ok(())
+        self.graph_act(update_component_state, &vec![receiver_id])?;
+        self.graph_act(set_expressions, &vec![receiver_id])?;
+        self.check_buses()
     }
-    fn do_connect(&mut self, receiver_id: ID, pin: PIN, emitter_id: ID) -> Result<(), String> {
+    pub(crate) fn do_connect(
+        &mut self,
+        receiver_id: ID,
+        pin: PIN,
+        emitter_id: ID,
+        slice: Option<u8>,
+    ) -> Result<(), String> {
         if !self.components.contains_key(&receiver_id) {
             return Err(format!("No receiver with id {}", receiver_id));
         }
@@ -247,7 +520,7 @@ impl BCircuit {
         let receiver = self.components.get(&receiver_id).unwrap();
         let emitter = self.components.get(&emitter_id).unwrap();
 
-        if pin >= receiver.borrow().num_inputs() {
+        if pin >= receiver.borrow().num_inputs() && !receiver.borrow().multi_driver {
             return Err(format!(
                 "There are only {} pins, can't access {}",
                 receiver.borrow().num_inputs(),
@@ -264,20 +537,20 @@ impl BCircuit {
         // todo: If emitter and receiver are same, we'd need to handle this
         // separately.
 
-        emitter.borrow_mut().link_output_receiver(receiver_id, pin);
-
         receiver
             .borrow_mut()
-            .set_input_pin_connection(pin, &emitter.borrow())
+            .set_input_pin_connection(pin, &emitter.borrow(), slice)?;
+        emitter.borrow_mut().link_output_receiver(receiver_id, pin);
+        Ok(())
     }
     pub fn disconnect(&mut self, receiver_id: ID, pin: PIN, emitter_id: ID) -> Result<(), String> {
         let res = self.do_disconnect(receiver_id, pin, emitter_id);
         if res.is_err() {
             return res;
         }
-        self.graph_act(update_component_state, &vec![receiver_id]);
-        self.graph_act(set_expressions, &vec![receiver_id]);
-        Ok(())
+        self.graph_act(update_component_state, &vec![receiver_id])?;
+        self.graph_act(set_expressions, &vec![receiver_id])?;
+        self.check_buses()
     }
     fn do_disconnect(&mut self, receiver_id: ID, pin: PIN, emitter_id: ID) -> Result<(), String> {
         if !self.components.contains_key(&receiver_id) {
@@ -306,20 +579,13 @@ impl BCircuit {
         self.outputs.insert(comp_id);
         true
     }
-    pub fn compile(&mut self) {
-        // 1. we generate boolean expression for each component
-        // in the circuit.
-        // 2. we check for dangerous loops.
-        // todo
-    }
     pub fn all_inputs_and_states(&self) -> Vec<ID> {
-        // excludes clk
-        // todo: add clocked components (circuit states)
+        // excludes clocks
         let mut q = Vec::<ID>::new();
         for (id, c) in &self.components {
             let ct = c.borrow().comp_type;
             if ct == CompType::Input || ct == CompType::Sequential {
-                if c.borrow().id == self.clk.unwrap_or(NULL) {
+                if self.is_clock(*id) {
                     continue;
                 }
                 q.push(*id);
@@ -327,63 +593,107 @@ impl BCircuit {
         }
         q
     }
-    pub fn gen_truth_table(&mut self) -> Table<char> {
+    // one column per bit: buses are expanded to `<name>[i]` columns,
+    // most significant bit first.
+    pub fn gen_truth_table(&mut self) -> Result<Table<char>, String> {
+        let bt = self.gen_bus_table()?;
+        let widths = bt.rows.first().map_or(Vec::new(), |r| {
+            r.iter().map(|w| w.width()).collect::<Vec<u8>>()
+        });
         let mut t = Table::<char>::new();
-        let inps = self.all_inputs_and_states();
-        let outs = &self.outputs.iter().map(|v| *v).collect::<Vec<ID>>();
-
+        t.set_columns(
+            bt.columns()
+                .iter()
+                .zip(&widths)
+                .flat_map(|(c, w)| bit_columns(c, *w))
+                .collect(),
+        )
+        .unwrap();
+        for row in &bt.rows {
+            let vals = row
+                .iter()
+                .flat_map(|w| w.bits().into_iter().rev().map(|b| b.to_char()))
+                .collect();
+            t.rows.push(vals);
+        }
+        Ok(t)
+    }
+    // inputs and states sorted by name, outputs sorted by label: the
+    // columns of `gen_bus_table`
+    pub(crate) fn ports(&self) -> (Vec<ID>, Vec<ID>) {
+        let mut inps = self.all_inputs_and_states();
+        inps.sort_by_key(|id| self.components.get(id).unwrap().borrow().state_expr.clone());
+        let mut outs = self.outputs.iter().cloned().collect::<Vec<ID>>();
+        outs.sort_by_key(|id| self.components.get(id).unwrap().borrow().label.clone());
+        (inps, outs)
+    }
+    fn bus_table_header(&self, inps: &[ID], outs: &[ID]) -> Table<Word> {
+        let mut t = Table::<Word>::new();
         let mut cols = inps
             .iter()
-            .map(|id| self.get_component(id).unwrap().borrow().state_expr.clone())
-            .collect::<Vec<String>>();
-        cols.sort();
-        let sorted_out_labels = &mut outs
-            .iter()
-            .map(|id| self.get_component(id).unwrap().borrow().label.clone())
+            .map(|id| self.components.get(id).unwrap().borrow().state_expr.clone())
             .collect::<Vec<String>>();
-        sorted_out_labels.sort();
-        cols.append(sorted_out_labels);
-
+        cols.extend(
+            outs.iter()
+                .map(|id| self.components.get(id).unwrap().borrow().label.clone()),
+        );
         t.set_columns(cols).unwrap();
-        for ct in bitwise_counter(inps.len()) {
-            let idx = t.add_row();
-            let mut i = 0;
-            for id in &inps {
-                let mut in_el = self.components.get(id).unwrap().borrow_mut();
-                in_el.set_state(ct[i]);
-                t.set_val_at(
-                    idx,
-                    &in_el.state_expr.as_str(),
-                    (in_el.state as u8 + '0' as u8) as char,
-                );
-
-                i += 1;
-            }
-            self.graph_act(update_component_state, &inps);
-            self.pulse_clock();
-            for id in &self.outputs {
-                let out_el = self.components.get(id).unwrap().borrow_mut();
-                t.set_val_at(
-                    idx,
-                    out_el.label.as_str(),
-                    (out_el.state as u8 + '0' as u8) as char,
-                );
+        t
+    }
+    pub(crate) fn input_widths(&self, inps: &[ID]) -> Vec<u8> {
+        inps.iter()
+            .map(|id| self.components.get(id).unwrap().borrow().width())
+            .collect::<Vec<u8>>()
+    }
+    // like `gen_truth_table`, but with a single column per input, state
+    // and output holding its whole value.
+    // Rows are computed 64 at a time by pattern simulation, which leaves
+    // the circuit untouched. Circuits that can't be levelized are run
+    // row by row instead.
+    pub fn gen_bus_table(&mut self) -> Result<Table<Word>, String> {
+        let mut pc = match self.pattern_sim() {
+            Ok(pc) => pc,
+            Err(_) => return self.gen_bus_table_serial(),
+        };
+        let (inps, outs) = self.ports();
+        let mut t = self.bus_table_header(&inps, &outs);
+        let widths = self.input_widths(&inps);
+        let total = table_rows(&widths, MAX_TRUTH_TABLE_ROWS)?;
+        for start in (0..total).step_by(PATTERNS) {
+            let mut rows = (start..total.min(start + PATTERNS as u64))
+                .map(|n| counter_row(n, &widths))
+                .collect::<Vec<Vec<Word>>>();
+            let vals = pc.run_rows(&inps, &outs, &rows)?;
+            for (row, v) in rows.iter_mut().zip(vals) {
+                row.extend(v);
             }
+            t.rows.extend(rows);
         }
-        return t;
+        Ok(t)
     }
-    pub fn state(&self, id: ID) -> Option<bool> {
-        match self.components.get(&id) {
-            Some(a) => {
-                let val = a.borrow().state.to_string().chars().nth(0).unwrap();
-                if val == 'f' {
-                    Some(false)
-                } else {
-                    Some(true)
-                }
+    // `gen_bus_table` on the circuit itself: every row is driven,
+    // settled and clocked in turn
+    pub(crate) fn gen_bus_table_serial(&mut self) -> Result<Table<Word>, String> {
+        let (inps, outs) = self.ports();
+        let mut t = self.bus_table_header(&inps, &outs);
+        let widths = self.input_widths(&inps);
+        for n in 0..table_rows(&widths, MAX_SERIAL_TABLE_ROWS)? {
+            let mut row = counter_row(n, &widths);
+            let mut childs = Vec::new();
+            for (id, val) in inps.iter().zip(&row) {
+                childs.extend(self.drive(*id, *val)?);
+            }
+            self.graph_act(update_component_state, &childs)?;
+            self.pulse_clock()?;
+            for id in &outs {
+                row.push(self.components.get(id).unwrap().borrow().state);
             }
-            None => None,
+            t.rows.push(row);
         }
+        Ok(t)
+    }
+    pub fn state(&self, id: ID) -> Option<Word> {
+        self.components.get(&id).map(|a| a.borrow().state)
     }
     pub fn new_id(&mut self) -> ID {
         self.last_id += 1;
@@ -391,15 +701,38 @@ impl BCircuit {
     }
 }
 
+// the rows of a table over inputs of `widths` bits, at most `max`
+fn table_rows(widths: &[u8], max: u64) -> Result<u64, String> {
+    let total_bits = widths.iter().map(|w| *w as u32).sum::<u32>();
+    if total_bits >= 64 || 1u64 << total_bits > max {
+        return Err(format!("Too many input combinations: 2^{}", total_bits));
+    }
+    Ok(1 << total_bits)
+}
+
+fn bit_columns(name: &str, width: u8) -> Vec<String> {
+    if width == 1 {
+        return vec![name.to_string()];
+    }
+    (0..width)
+        .rev()
+        .map(|i| format!("{}[{}]", name, i))
+        .collect()
+}
+
 mod tests {
     use crate::{
         circuit::BCircuit,
-        types::{CLOCK_PIN, NULL},
+        signal::{
+            Signal::{One, X, Zero},
+            Word,
+        },
+        types::{CLOCK_PIN, ID, NULL},
     };
     #[test]
     fn add_component() {
         let mut c = BCircuit::new();
-        c.power_on();
+        c.power_on().unwrap();
         let a = c.add_component("AND", "A").unwrap();
         let b = c.add_component("JK", "Q1").unwrap();
         assert!(c.get_component(&a).is_some());
@@ -414,9 +747,9 @@ mod tests {
     #[test]
     fn connect_disconnect() {
         let mut c = BCircuit::new();
-        c.power_on();
-        let a = c.add_input("A", true);
-        let clk = c.add_input("CLK", false);
+        c.power_on().unwrap();
+        let a = c.add_input("A", One);
+        let clk = c.add_input("CLK", Zero);
         let q1 = c.add_component("JK", "Q1").unwrap();
 
         assert!(c.connect(q1, CLOCK_PIN, clk).is_ok());
@@ -449,7 +782,8 @@ mod tests {
             .output_recvlist
             .contains(&(q1, 2)));
 
-        assert!(c.connect(q1, 3, a).is_err());
+        // pins 3 and 4 are the asynchronous preset and clear
+        assert!(c.connect(q1, 5, a).is_err());
         assert_eq!(
             c.components.get(&a).unwrap().borrow().output_recvlist.len(),
             2
@@ -488,31 +822,32 @@ mod tests {
         // 1. A parent component changed value at a pin and bfs state update was performed.
         // 2. A parent component was removed/disconnected.
         let mut c = BCircuit::new();
-        c.power_on();
-        let i = c.add_input("A", false);
+        c.power_on().unwrap();
+        let i = c.add_input("A", Zero);
         let n1 = c.add_component("NOT", "B").unwrap();
         let n2 = c.add_component("NOT", "C").unwrap();
 
         c.connect(n1, 1, i).unwrap();
         c.connect(n2, 1, n1).unwrap();
 
-        assert_eq!(c.state(i).unwrap(), false);
-        assert_eq!(c.state(n1).unwrap(), true);
-        assert_eq!(c.state(n2).unwrap(), false);
+        assert_eq!(c.state(i).unwrap(), Zero);
+        assert_eq!(c.state(n1).unwrap(), One);
+        assert_eq!(c.state(n2).unwrap(), Zero);
 
-        c.set_component_state(i, true).unwrap();
+        c.set_component_state(i, One).unwrap();
 
-        assert_eq!(c.state(i).unwrap(), true);
-        assert_eq!(c.state(n1).unwrap(), false);
-        assert_eq!(c.state(n2).unwrap(), true);
+        assert_eq!(c.state(i).unwrap(), One);
+        assert_eq!(c.state(n1).unwrap(), Zero);
+        assert_eq!(c.state(n2).unwrap(), One);
 
-        // if an and gate has its input disconnected, it turns off (not ON)
+        // if a gate has its input disconnected, the floating pin
+        // makes its output unknown
         c.remove_component(i).unwrap();
-        assert_eq!(c.state(n1).unwrap(), false);
-        assert_eq!(c.state(n2).unwrap(), true);
+        assert_eq!(c.state(n1).unwrap(), X);
+        assert_eq!(c.state(n2).unwrap(), X);
 
         c.disconnect(n2, 1, n1).unwrap();
-        assert_eq!(c.state(n2).unwrap(), false);
+        assert_eq!(c.state(n2).unwrap(), X);
     }
 
     #[test]
@@ -520,8 +855,8 @@ mod tests {
         let mut c = BCircuit::new();
 
         // 2 bit async up counter
-        let one = c.add_input("1", true);
-        let clk = c.add_input("clk", false);
+        let one = c.add_input("1", One);
+        let clk = c.add_input("clk", Zero);
         let q = c.add_component("JK", "Q1").unwrap();
         let qq = c.add_component("JK", "Q2").unwrap();
         let n = c.add_component("NOT", "!Q1").unwrap();
@@ -538,40 +873,43 @@ mod tests {
 
         c.clock(clk);
 
-        // THIS TEST IS NONDETERMINISTIC!??
-        c.power_on();
+        c.power_on().unwrap();
+        c.reset_states().unwrap();
 
-        assert_eq!((c.state(q).unwrap(), c.state(qq).unwrap()), (false, false));
-        c.pulse_clock();
-        assert_eq!((c.state(q).unwrap(), c.state(qq).unwrap()), (true, false));
-        c.pulse_clock();
-        assert_eq!((c.state(q).unwrap(), c.state(qq).unwrap()), (false, true));
-        c.pulse_clock();
-        assert_eq!((c.state(q).unwrap(), c.state(qq).unwrap()), (true, true));
+        let report = c
+            .run_vectors(
+                "clk | Q1 Q2
+                - | 0 0
+                C | 1 0
+                C | 0 1
+                C | 1 1",
+            )
+            .unwrap();
+        assert!(report.passed(), "{}", report);
     }
 
     #[test]
     fn remove_component() {
         let mut c = BCircuit::new();
-        c.power_on();
-        let i = c.add_input("A", false);
+        c.power_on().unwrap();
+        let i = c.add_input("A", Zero);
         let n1 = c.add_component("NOT", "B").unwrap();
         let n2 = c.add_component("NOT", "C").unwrap();
 
         c.connect(n1, 1, i).unwrap();
         c.connect(n2, 1, n1).unwrap();
-        assert_eq!(c.state(n2).unwrap(), false);
+        assert_eq!(c.state(n2).unwrap(), Zero);
         c.remove_component(n1).unwrap();
         assert_eq!(c.components.get(&n1).is_none(), true);
-        assert_eq!(c.state(n2).unwrap(), true);
+        assert_eq!(c.state(n2).unwrap(), X);
     }
 
     #[test]
     fn sample_comb_ckt() {
         let mut c = BCircuit::new();
-        let i1 = c.add_input("A", !false);
-        let i2 = c.add_input("B", false);
-        let i3 = c.add_input("C", true);
+        let i1 = c.add_input("A", One);
+        let i2 = c.add_input("B", Zero);
+        let i3 = c.add_input("C", One);
 
         let n1 = c.add_component("NOT", "not1").unwrap();
         let n2 = c.add_component("NOT", "not2").unwrap();
@@ -598,18 +936,18 @@ mod tests {
 
         c.track_output(or);
         c.compile();
-        c.power_on();
+        c.power_on().unwrap();
         println!("{}", c.components.get(&or).unwrap().borrow().state_expr);
     }
     #[test]
     fn latch() {
         let mut c = BCircuit::new();
-        c.power_on();
+        c.power_on().unwrap();
 
         let q = c.add_component("NAND", "q").unwrap();
         let nq = c.add_component("NAND", "nq").unwrap();
-        let is = c.add_input("S", true);
-        let ir = c.add_input("R", false);
+        let is = c.add_input("S", One);
+        let ir = c.add_input("R", Zero);
 
         c.connect(q, 1, is).unwrap();
         c.connect(nq, 2, ir).unwrap();
@@ -617,4 +955,587 @@ mod tests {
         c.connect(q, 2, nq).unwrap();
         c.connect(nq, 1, q).unwrap();
     }
+    #[test]
+    fn unknowns_propagate() {
+        let mut c = BCircuit::new();
+        c.power_on().unwrap();
+        let one = c.add_input("1", One);
+        let zero = c.add_input("0", Zero);
+        let a = c.add_component("AND", "a").unwrap();
+        let o = c.add_component("OR", "o").unwrap();
+
+        // floating inputs read as unknown
+        assert_eq!(c.state(a).unwrap(), X);
+        c.connect(a, 1, one).unwrap();
+        assert_eq!(c.state(a).unwrap(), X);
+        c.connect(o, 1, a).unwrap();
+        c.connect(o, 2, zero).unwrap();
+        assert_eq!(c.state(o).unwrap(), X);
+        // a controlling value masks the unknown
+        c.disconnect(a, 1, one).unwrap();
+        c.connect(a, 1, zero).unwrap();
+        assert_eq!(c.state(a).unwrap(), Zero);
+        assert_eq!(c.state(o).unwrap(), Zero);
+
+        // a NAND latch with neither set nor reset asserted stays unresolved
+        let q = c.add_component("NAND", "q").unwrap();
+        let nq = c.add_component("NAND", "nq").unwrap();
+        c.connect(q, 1, one).unwrap();
+        c.connect(nq, 2, one).unwrap();
+        c.connect(q, 2, nq).unwrap();
+        c.connect(nq, 1, q).unwrap();
+        assert_eq!(
+            (c.state(q).unwrap().bit(0), c.state(nq).unwrap().bit(0)),
+            (X, X)
+        );
+        c.set_component_state(one, Zero).unwrap();
+        assert_eq!(
+            (c.state(q).unwrap().bit(0), c.state(nq).unwrap().bit(0)),
+            (One, One)
+        );
+
+        // flip-flops and registers power on unknown until reset
+        let ff = c.add_component("D", "ff").unwrap();
+        let reg = c.add_component_with_width("REG", "r", 4).unwrap();
+        let n = c.add_component("NOT", "n").unwrap();
+        c.connect(n, 1, ff).unwrap();
+        assert_eq!(c.state(n).unwrap(), X);
+        assert_eq!(c.state(reg).unwrap(), Word::unknown(4));
+        c.reset_states().unwrap();
+        assert_eq!(c.state(n).unwrap(), One);
+        assert_eq!(c.state(reg).unwrap(), Word::from_u64(0, 4));
+    }
+
+    #[test]
+    fn buses() {
+        let mut c = BCircuit::new();
+        c.power_on().unwrap();
+        let a = c.add_input("A", Word::from_u64(0b1100_1010, 8));
+        let b = c.add_input("B", Word::from_u64(0b1010_0110, 8));
+        let and = c.add_component_with_width("AND", "", 8).unwrap();
+        c.connect(and, 1, a).unwrap();
+        c.connect(and, 2, b).unwrap();
+        assert_eq!(c.state(and).unwrap().to_u64(), Some(0b1000_0010));
+
+        // a single bit can't be wired to a bus pin, nor set to a bus value
+        let one = c.add_input("1", One);
+        let and2 = c.add_component_with_width("AND", "", 8).unwrap();
+        let err = c.connect(and2, 1, one).unwrap_err();
+        assert!(err.contains("Width mismatch"));
+        assert!(c.set_component_state(one, Word::from_u64(3, 2)).is_err());
+        assert!(c.add_component_with_width("AND", "", 65).is_err());
+
+        // split into bits and merge back in reverse order
+        let bits = c.add_splitter("a", and).unwrap();
+        assert_eq!(bits.len(), 8);
+        assert_eq!(c.state(bits[1]).unwrap(), One);
+        assert_eq!(c.get_component(&bits[1]).unwrap().borrow().label, "a[1]");
+        let m = c.add_merger("rev", 8).unwrap();
+        for (i, bit) in bits.iter().enumerate() {
+            c.connect(m, 8 - i, *bit).unwrap();
+        }
+        assert_eq!(c.state(m).unwrap().to_u64(), Some(0b0100_0001));
+        c.set_component_state(b, Word::from_u64(0xFF, 8)).unwrap();
+        assert_eq!(c.state(m).unwrap().to_u64(), Some(0b0101_0011));
+
+        // a slice of a bus feeds a narrower pin
+        let hi = c.add_component_with_width("BFR", "", 4).unwrap();
+        c.connect_slice(hi, 1, a, 4).unwrap();
+        assert_eq!(c.state(hi).unwrap().to_u64(), Some(0b1100));
+        let lo = c.add_component_with_width("BFR", "", 4).unwrap();
+        assert!(c.connect_slice(lo, 1, a, 5).is_err());
+    }
+    #[test]
+    fn bus_truth_table() {
+        let mut c = BCircuit::new();
+        c.power_on().unwrap();
+        let a = c.add_input("A", Word::from_u64(0, 2));
+        let s = c.add_input("S", Zero);
+        let t = c.add_component_with_width("TRI", "Y", 2).unwrap();
+        c.connect(t, 1, a).unwrap();
+        c.connect(t, 2, s).unwrap();
+        c.track_output(t);
+
+        let bt = c.gen_bus_table().unwrap();
+        assert_eq!(bt.columns(), &vec!["A", "S", "Y"]);
+        assert_eq!(bt.rows.len(), 8);
+        assert_eq!(bt.rows[7][0].to_string(), "3");
+        assert_eq!(bt.rows[7][2].to_string(), "3");
+        assert_eq!(bt.rows[6][2].to_string(), "ZZ");
+
+        let tt = c.gen_truth_table().unwrap();
+        assert_eq!(tt.columns(), &vec!["A[1]", "A[0]", "S", "Y[1]", "Y[0]"]);
+        assert_eq!(tt.rows[5], vec!['1', '0', '1', '1', '0']);
+        assert_eq!(tt.rows[4], vec!['1', '0', '0', 'Z', 'Z']);
+
+        // too wide to tabulate, with pattern simulation or without
+        let mut c = BCircuit::new();
+        let a = c.add_input("A", Word::from_u64(0, 64));
+        let n = c.add_component_with_width("NOT", "N", 64).unwrap();
+        c.connect(n, 1, a).unwrap();
+        c.track_output(n);
+        let err = Err("Too many input combinations: 2^64".to_string());
+        assert_eq!(c.gen_bus_table().map(|t| t.rows.len()), err);
+        assert_eq!(c.gen_bus_table_serial().map(|t| t.rows.len()), err);
+        let mut c = BCircuit::new();
+        c.add_input("B", Word::from_u64(0, 40));
+        assert!(c.gen_truth_table().is_err());
+
+        // 17 input bits are within the pattern limit, not the serial one
+        let mut c = BCircuit::new();
+        let a = c.add_input("A", Word::from_u64(0, 17));
+        let n = c.add_component_with_width("NOT", "N", 17).unwrap();
+        c.connect(n, 1, a).unwrap();
+        c.track_output(n);
+        let t = c.gen_bus_table().unwrap();
+        assert_eq!(t.rows.len(), 1 << 17);
+        assert_eq!(t.rows[5], vec![Word::from_u64(5, 17), Word::from_u64(!5, 17)]);
+        assert_eq!(
+            c.gen_bus_table_serial().map(|t| t.rows.len()),
+            Err("Too many input combinations: 2^17".to_string())
+        );
+    }
+    #[test]
+    fn oscillation() {
+        let mut c = BCircuit::new();
+        c.power_on().unwrap();
+        let en = c.add_input("EN", Zero);
+        let buf = c.add_component("BFR", "").unwrap();
+        let and = c.add_component("AND", "g").unwrap();
+        let n = c.add_component("NOT", "").unwrap();
+        c.connect(buf, 1, en).unwrap();
+        c.connect(and, 1, buf).unwrap();
+        c.connect(n, 1, and).unwrap();
+        c.connect(and, 2, n).unwrap();
+        assert_eq!(c.state(n).unwrap(), One);
+
+        let err = c.set_component_state(en, One).unwrap_err();
+        assert!(err.contains("oscillating"));
+        assert_eq!(c.state(n).unwrap(), X);
+        assert!(err.contains(&format!("id_{} AND (g), id_{} NOT", and, n)));
+        assert!(!err.contains(&format!("id_{}", buf)));
+        // disabling the loop settles it again
+        c.set_component_state(en, Zero).unwrap();
+        assert_eq!(c.state(n).unwrap(), One);
+
+        // a long chain is not an oscillation, even with a small budget
+        c.set_iteration_limit(10);
+        let mut last = en;
+        for _ in 0..20 {
+            let b = c.add_component("BFR", "").unwrap();
+            c.connect(b, 1, last).unwrap();
+            last = b;
+        }
+        c.set_component_state(en, One).unwrap_err();
+        c.set_iteration_limit(5000);
+        c.set_component_state(en, Zero).unwrap();
+        assert_eq!(c.state(last).unwrap(), Zero);
+    }
+    #[test]
+    fn variadic_gates() {
+        let mut c = BCircuit::new();
+        let ins = (0..8)
+            .map(|i| c.add_input(&((b'A' + i) as char).to_string(), One))
+            .collect::<Vec<ID>>();
+        let and = c.add_component_with_inputs("AND", "", 5).unwrap();
+        let xor = c.add_component_with_inputs("XOR", "P", 8).unwrap();
+        let nand = c.add_component_with_inputs("NAND", "", 3).unwrap();
+        assert_eq!(c.get_component(&and).unwrap().borrow().num_inputs(), 6);
+        for (i, id) in ins.iter().enumerate() {
+            if i < 5 {
+                c.connect(and, i + 1, *id).unwrap();
+            }
+            if i < 3 {
+                c.connect(nand, i + 1, *id).unwrap();
+            }
+            c.connect(xor, i + 1, *id).unwrap();
+        }
+        assert!(c.connect(and, 6, ins[5]).is_err());
+        c.track_output(xor);
+        c.power_on().unwrap();
+        assert_eq!(c.state(and).unwrap(), One);
+        assert_eq!(c.state(xor).unwrap(), Zero);
+        assert_eq!(c.state(nand).unwrap(), Zero);
+        c.set_component_state(ins[4], Zero).unwrap();
+        assert_eq!(c.state(and).unwrap(), Zero);
+        assert_eq!(c.state(xor).unwrap(), One);
+        assert_eq!(c.state(nand).unwrap(), Zero);
+        c.set_component_state(ins[2], X).unwrap();
+        assert_eq!(c.state(nand).unwrap(), X);
+        assert_eq!(c.state(and).unwrap(), Zero);
+
+        let expr = |id: ID| c.get_component(&id).unwrap().borrow().state_expr.clone();
+        assert_eq!(expr(and), "(A.B.C.D.E)");
+        assert_eq!(expr(nand), "!(A.B.C)");
+
+        let t = c.gen_truth_table().unwrap();
+        assert_eq!(t.rows.len(), 256);
+        for row in &t.rows {
+            let ones = row[..8].iter().filter(|b| **b == '1').count();
+            assert_eq!(row[8], if ones % 2 == 1 { '1' } else { '0' });
+        }
+
+        let mut d = BCircuit::new();
+        d.load_netlist(&c.to_netlist().unwrap()).unwrap();
+        assert_eq!(d.to_netlist(), c.to_netlist());
+        assert_eq!(d.get_component(&xor).unwrap().borrow().num_inputs(), 9);
+
+        assert!(c.add_component_with_inputs("NOT", "", 3).is_err());
+        assert!(c.add_component_with_inputs("AND", "", 1).is_err());
+    }
+
+    #[test]
+    fn custom_gates() {
+        use crate::components::{reducer, ComponentDefParams};
+        use crate::{signal::Signal, table::Table};
+
+        // closures can capture parameters
+        let mut c = BCircuit::new();
+        let threshold = 2;
+        c.define_gate(ComponentDefParams {
+            name: "MAJ".to_string(),
+            eval: reducer(move |v, _| {
+                let ones = v[1..].iter().filter(|w| w.bit(0) == One).count();
+                Word::from(ones >= threshold)
+            }),
+            default_inputs: 3,
+            symbol: "MAJ".to_string(),
+            ..Default::default()
+        });
+        let ins = ["A", "B", "C"].map(|l| c.add_input(l, Zero));
+        let maj = c.add_component("MAJ", "").unwrap();
+        for (i, id) in ins.iter().enumerate() {
+            c.connect(maj, i + 1, *id).unwrap();
+        }
+        c.power_on().unwrap();
+        assert_eq!(c.state(maj).unwrap(), Zero);
+        c.set_component_state(ins[0], One).unwrap();
+        assert_eq!(c.state(maj).unwrap(), Zero);
+        c.set_component_state(ins[2], One).unwrap();
+        assert_eq!(c.state(maj).unwrap(), One);
+        assert_eq!(
+            c.get_component(&maj).unwrap().borrow().state_expr,
+            "MAJ(A, B, C)"
+        );
+
+        // a gate from the truth table of another circuit
+        let mut x = BCircuit::new();
+        let a = x.add_input("A", Zero);
+        let b = x.add_input("B", Zero);
+        let xor = x.add_component("XOR", "F").unwrap();
+        x.connect(xor, 1, a).unwrap();
+        x.connect(xor, 2, b).unwrap();
+        x.track_output(xor);
+        let t = x.gen_truth_table().unwrap();
+        c.define_gate(ComponentDefParams::from_table("XT", &t, &["A", "B"], "F").unwrap());
+        let xt = c.add_component("XT", "").unwrap();
+        c.connect(xt, 1, ins[0]).unwrap();
+        c.connect(xt, 2, ins[1]).unwrap();
+        c.refresh().unwrap();
+        assert_eq!(c.state(xt).unwrap(), One);
+        c.set_component_state(ins[1], One).unwrap();
+        assert_eq!(c.state(xt).unwrap(), Zero);
+        c.set_component_state(ins[1], X).unwrap();
+        assert_eq!(c.state(xt).unwrap(), X);
+
+        // partial tables: X inputs resolve when every completion agrees
+        let mut t = Table::<char>::new();
+        t.set_columns(vec!["S".into(), "D".into(), "Q".into()])
+            .unwrap();
+        t.set_rows(vec![
+            vec!['0', '0', '0'],
+            vec!['0', '1', '0'],
+            vec!['1', '1', '1'],
+        ])
+        .unwrap();
+        let def = ComponentDefParams::from_table("G", &t, &["S", "D"], "Q").unwrap();
+        let eval =
+            |s: Signal, d: Signal| (def.eval)(&[Zero.into(), s.into(), d.into()], Zero.into());
+        assert_eq!(eval(Zero, X), Zero);
+        assert_eq!(eval(One, One), One);
+        assert_eq!(eval(One, Zero), X);
+        assert_eq!(eval(X, One), X);
+
+        assert!(ComponentDefParams::from_table("G", &t, &["S", "E"], "Q").is_err());
+        assert!(ComponentDefParams::from_table("G", &t, &[], "Q").is_err());
+        t.rows.push(vec!['1', '1', '0']);
+        assert!(ComponentDefParams::from_table("G", &t, &["S", "D"], "Q").is_err());
+        t.rows[3] = vec!['X', '1', '0'];
+        assert!(ComponentDefParams::from_table("G", &t, &["S", "D"], "Q").is_err());
+    }
+
+    #[test]
+    fn flip_flops() {
+        let mut c = BCircuit::new();
+        let clk = c.add_input("clk", Zero);
+        let a = c.add_input("A", One);
+        let b = c.add_input("B", Zero);
+        c.clock(clk);
+        let ff = |c: &mut BCircuit, typ: &str, pins: &[ID]| {
+            let id = c.add_component(typ, typ).unwrap();
+            c.connect(id, CLOCK_PIN, clk).unwrap();
+            for (i, p) in pins.iter().enumerate() {
+                c.connect(id, i + 1, *p).unwrap();
+            }
+            id
+        };
+        let d = ff(&mut c, "D", &[a]);
+        let dn = ff(&mut c, "DN", &[a]);
+        let t = ff(&mut c, "T", &[a]);
+        let sr = ff(&mut c, "SR", &[a, b]);
+        let jk = ff(&mut c, "JK", &[a, a]);
+        let latch = ff(&mut c, "DLATCH", &[a]);
+        c.power_on().unwrap();
+        for id in [d, dn, t, sr, jk, latch] {
+            c.set_component_state(id, Zero).unwrap();
+        }
+
+        // rising edge
+        c.set_component_state(clk, One).unwrap();
+        assert_eq!(c.state(d).unwrap(), One);
+        assert_eq!(c.state(dn).unwrap(), Zero);
+        assert_eq!(c.state(t).unwrap(), One);
+        assert_eq!(c.state(sr).unwrap(), One);
+        assert_eq!(c.state(jk).unwrap(), One);
+        // the latch is transparent while the clock is high
+        assert_eq!(c.state(latch).unwrap(), One);
+        c.set_component_state(a, Zero).unwrap();
+        assert_eq!(c.state(latch).unwrap(), Zero);
+        assert_eq!(c.state(d).unwrap(), One);
+
+        // falling edge
+        c.set_component_state(clk, Zero).unwrap();
+        assert_eq!(c.state(dn).unwrap(), Zero);
+        c.set_component_state(a, One).unwrap();
+        assert_eq!(c.state(latch).unwrap(), Zero);
+        c.pulse_clock().unwrap();
+        assert_eq!(c.state(dn).unwrap(), One);
+        assert_eq!(c.state(t).unwrap(), Zero);
+
+        // S = R = 1 is invalid, X inputs matter only if they change the outcome
+        c.set_component_state(b, One).unwrap();
+        c.pulse_clock().unwrap();
+        assert_eq!(c.state(sr).unwrap(), X);
+        c.set_component_state(sr, One).unwrap();
+        c.set_component_state(a, X).unwrap();
+        c.set_component_state(b, Zero).unwrap();
+        c.pulse_clock().unwrap();
+        assert_eq!(c.state(sr).unwrap(), One);
+        assert_eq!(c.state(t).unwrap(), X);
+
+        // a flip-flop can be levelized unless its asynchronous pins are used
+        assert!(c.remove_component(latch).is_ok());
+        assert!(c.levelize().is_err());
+        c.remove_component(dn).unwrap();
+        assert!(c.levelize().is_ok());
+        c.connect(d, 3, b).unwrap();
+        assert!(c.levelize().is_err());
+    }
+
+    #[test]
+    fn falling_edge_and_sr_latch() {
+        let mut c = BCircuit::new();
+        let clk = c.add_input("clk", Zero);
+        let s = c.add_input("S", One);
+        let r = c.add_input("R", Zero);
+        c.clock(clk);
+        let ff = |c: &mut BCircuit, typ: &str| {
+            let id = c.add_component(typ, typ).unwrap();
+            c.connect(id, CLOCK_PIN, clk).unwrap();
+            c.connect(id, 1, s).unwrap();
+            if typ != "TN" {
+                c.connect(id, 2, r).unwrap();
+            }
+            id
+        };
+        let tn = ff(&mut c, "TN");
+        let srn = ff(&mut c, "SRN");
+        let jkn = ff(&mut c, "JKN");
+        let latch = ff(&mut c, "SRLATCH");
+        c.power_on().unwrap();
+        for id in [tn, srn, jkn, latch] {
+            c.set_component_state(id, Zero).unwrap();
+        }
+        let states = |c: &BCircuit| [tn, srn, jkn, latch].map(|id| c.state(id).unwrap());
+
+        // the rising edge changes nothing, the latch follows S and R
+        // while enabled
+        c.set_component_state(clk, One).unwrap();
+        assert_eq!(states(&c), [Zero, Zero, Zero, One].map(Word::from));
+        c.set_component_state(s, Zero).unwrap();
+        assert_eq!(c.state(latch).unwrap(), One);
+        c.set_component_state(r, One).unwrap();
+        assert_eq!(c.state(latch).unwrap(), Zero);
+        c.set_component_state(r, Zero).unwrap();
+        c.set_component_state(s, One).unwrap();
+        assert_eq!(c.state(latch).unwrap(), One);
+
+        // falling edge with S = J = T = 1
+        c.set_component_state(clk, Zero).unwrap();
+        assert_eq!(states(&c), [One, One, One, One].map(Word::from));
+        // the disabled latch holds
+        c.set_component_state(s, Zero).unwrap();
+        c.set_component_state(r, One).unwrap();
+        assert_eq!(c.state(latch).unwrap(), One);
+
+        // R = K = 1 resets, T = 0 holds
+        c.pulse_clock().unwrap();
+        assert_eq!(states(&c), [One, Zero, Zero, Zero].map(Word::from));
+
+        // J = K = 1 toggles
+        c.set_component_state(s, One).unwrap();
+        c.pulse_clock().unwrap();
+        assert_eq!(c.state(jkn).unwrap(), One);
+        assert_eq!(c.state(tn).unwrap(), Zero);
+        assert_eq!(c.state(srn).unwrap(), X);
+    }
+
+    #[test]
+    fn async_set_reset() {
+        let mut c = BCircuit::new();
+        let clk = c.add_input("clk", Zero);
+        let d = c.add_input("D", Zero);
+        let pre = c.add_input("PRE", Zero);
+        let clr = c.add_input("CLR", Zero);
+        let q = c.add_component("D", "Q").unwrap();
+        c.connect(q, CLOCK_PIN, clk).unwrap();
+        c.connect(q, 1, d).unwrap();
+        c.clock(clk);
+        c.power_on().unwrap();
+        // floating asynchronous pins are inactive
+        assert!(c.compile().unconnected_inputs.is_empty());
+        c.pulse_clock().unwrap();
+        assert_eq!(c.state(q).unwrap(), Zero);
+
+        c.connect(q, 2, pre).unwrap();
+        c.connect(q, 3, clr).unwrap();
+        c.set_component_state(pre, One).unwrap();
+        assert_eq!(c.state(q).unwrap(), One);
+        // preset overrides the clock
+        c.pulse_clock().unwrap();
+        assert_eq!(c.state(q).unwrap(), One);
+        c.set_component_state(pre, Zero).unwrap();
+        assert_eq!(c.state(q).unwrap(), One);
+        c.pulse_clock().unwrap();
+        assert_eq!(c.state(q).unwrap(), Zero);
+
+        c.set_component_state(d, One).unwrap();
+        c.set_component_state(clr, X).unwrap();
+        assert_eq!(c.state(q).unwrap(), Zero);
+        c.pulse_clock().unwrap();
+        assert_eq!(c.state(q).unwrap(), X);
+        c.set_component_state(clr, One).unwrap();
+        assert_eq!(c.state(q).unwrap(), Zero);
+        c.set_component_state(pre, One).unwrap();
+        assert_eq!(c.state(q).unwrap(), X);
+        c.set_component_state(clr, Zero).unwrap();
+        assert_eq!(c.state(q).unwrap(), One);
+    }
+
+    #[test]
+    fn registers() {
+        let mut c = BCircuit::new();
+        let clk = c.add_input("clk", Zero);
+        let d = c.add_input("D", Word::from_u64(0b1010, 4));
+        let one = c.add_input("1", One);
+        let load = c.add_input("LOAD", Zero);
+        let en = c.add_input("EN", One);
+        let up = c.add_input("UP", One);
+        c.clock(clk);
+
+        let ctr = c.add_component("COUNTER", "C").unwrap();
+        for (pin, id) in [
+            ("CLK", clk),
+            ("D", d),
+            ("LOAD", load),
+            ("EN", en),
+            ("UP", up),
+        ] {
+            c.connect_named(ctr, pin, id).unwrap();
+        }
+        assert!(c.connect_named(ctr, "Q", one).is_err());
+        let reg = c.add_component_with_width("REG", "R", 8).unwrap();
+        c.connect_named(reg, "CLK", clk).unwrap();
+        c.connect_named(reg, "EN", en).unwrap();
+        // the register loads the counter into its low half
+        let m = c.add_merger("", 8).unwrap();
+        for i in 0..8 {
+            if i < 4 {
+                c.connect_slice(m, i + 1, ctr, i as u8).unwrap();
+            } else {
+                c.connect(m, i + 1, one).unwrap();
+            }
+        }
+        c.connect_named(reg, "D", m).unwrap();
+        let sipo = c.add_component("SIPO", "S").unwrap();
+        c.connect_named(sipo, "CLK", clk).unwrap();
+        c.connect_named(sipo, "SI", one).unwrap();
+        c.connect_named(sipo, "EN", en).unwrap();
+        let piso = c.add_component("PISO", "P").unwrap();
+        for (pin, id) in [("CLK", clk), ("D", d), ("LOAD", load), ("SI", up)] {
+            c.connect_named(piso, pin, id).unwrap();
+        }
+        assert!(c.compile().unconnected_inputs.is_empty());
+        c.power_on().unwrap();
+        c.reset_states().unwrap();
+
+        let val = |c: &BCircuit, id: ID| c.state(id).unwrap().to_u64().unwrap();
+        assert_eq!(val(&c, ctr), 0);
+        for i in 1..=17 {
+            c.pulse_clock().unwrap();
+            assert_eq!(val(&c, ctr), i % 16);
+            assert_eq!(val(&c, reg), 0xf0 | ((i - 1) % 16));
+        }
+        assert_eq!(val(&c, sipo), 0b1111);
+
+        c.set_component_state(load, One).unwrap();
+        c.pulse_clock().unwrap();
+        assert_eq!(val(&c, ctr), 0b1010);
+        assert_eq!(val(&c, piso), 0b1010);
+        c.set_component_state(load, Zero).unwrap();
+        c.set_component_state(up, Zero).unwrap();
+        c.pulse_clock().unwrap();
+        assert_eq!(val(&c, ctr), 0b1001);
+        assert_eq!(val(&c, piso), 0b0100);
+        c.set_component_state(en, Zero).unwrap();
+        c.pulse_clock().unwrap();
+        assert_eq!(val(&c, ctr), 0b1001);
+        assert_eq!(val(&c, sipo), 0b1111);
+        assert_eq!(val(&c, piso), 0b1000);
+
+        // SI reaches the serial output of a 3 bit SISO on the 3rd edge,
+        // after the unknown power-on bits
+        let (siso, so) = c.add_siso("SS", 3).unwrap();
+        c.connect_named(siso, "CLK", clk).unwrap();
+        c.connect_named(siso, "SI", up).unwrap();
+        c.connect_named(siso, "EN", one).unwrap();
+        c.set_component_state(up, One).unwrap();
+        assert_eq!(c.state(so).unwrap(), Word::from(X));
+        for level in [X, X, One] {
+            c.pulse_clock().unwrap();
+            assert_eq!(c.state(so).unwrap(), Word::from(level));
+        }
+        c.set_component_state(up, Zero).unwrap();
+        for level in [One, One, Zero] {
+            c.pulse_clock().unwrap();
+            assert_eq!(c.state(so).unwrap(), Word::from(level));
+        }
+
+        // asynchronous clear
+        c.connect_named(ctr, "CLR", one).unwrap();
+        assert_eq!(val(&c, ctr), 0);
+
+        // the state is reported bit by bit
+        let mut c = BCircuit::new();
+        let clk = c.add_input("clk", Zero);
+        let si = c.add_input("SI", Zero);
+        let sipo = c.add_component_with_width("SIPO", "S", 2).unwrap();
+        c.connect_named(sipo, "CLK", clk).unwrap();
+        c.connect_named(sipo, "SI", si).unwrap();
+        c.connect_named(sipo, "EN", si).unwrap();
+        c.track_output(sipo);
+        let t = c.gen_truth_table().unwrap();
+        assert_eq!(t.columns()[..4], ["S[1]", "S[0]", "SI", "clk"]);
+        assert_eq!(t.rows.len(), 16);
+    }
 }
@@ -1,25 +1,63 @@
+use crate::signal::Signal;
+
+// what makes a clocked component take its next state: an edge of the
+// clock pin for flip-flops, or its level for latches, which follow
+// their inputs for as long as the clock (enable) pin is at that level.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Trigger {
+    RisingEdge,
+    FallingEdge,
+    High,
+    Low,
+}
+
+impl Trigger {
+    pub fn is_edge(self) -> bool {
+        self == Trigger::RisingEdge || self == Trigger::FallingEdge
+    }
+}
+
+#[derive(Clone)]
 pub struct ClockManager {
-    curr: bool,
-    last: bool,
+    curr: Signal,
+    last: Signal,
     expr: String,
+    trigger: Trigger,
 }
 
 impl ClockManager {
-    pub fn new() -> ClockManager {
+    pub fn new(trigger: Trigger) -> ClockManager {
         return ClockManager {
-            curr: false,
-            last: false,
+            curr: Signal::Zero,
+            last: Signal::Zero,
             expr: String::new(),
+            trigger,
         };
     }
+    pub fn trigger(&self) -> Trigger {
+        self.trigger
+    }
+    // consumes the current edge, if any
     pub fn reset_clock_hist(&mut self) {
-        self.curr = false;
-        self.last = false;
+        self.last = self.curr;
+    }
+    // starts tracking from `val` without registering an edge, e.g.
+    // when the clock pin gets connected to a line that is already high
+    pub fn init(&mut self, val: Signal) {
+        self.curr = val.read();
+        self.last = self.curr;
     }
     pub fn clock_triggered(&self) -> bool {
-        self.last == false && self.curr
+        // only a clean 0 -> 1 (or 1 -> 0) transition is an edge
+        match self.trigger {
+            Trigger::RisingEdge => self.last == Signal::Zero && self.curr == Signal::One,
+            Trigger::FallingEdge => self.last == Signal::One && self.curr == Signal::Zero,
+            Trigger::High => self.curr == Signal::One,
+            Trigger::Low => self.curr == Signal::Zero,
+        }
     }
-    pub fn push(&mut self, val: bool) {
+    pub fn push(&mut self, val: Signal) {
+        let val = val.read();
         if val == self.curr {
             return;
         }
@@ -0,0 +1,371 @@
+// Clock domains.
+//
+// A circuit can have any number of clocks, each an input with its own
+// period, phase and duty cycle: it is low until `phase`, then every
+// period starts with a rising edge and stays high for `high` time units.
+// `run_clocks` drives all of them together on the circuit timeline (see
+// `timing`), edges due at the same time being applied together.
+//
+// Every clocked component belongs to the domain of the clock found by
+// following its clock pin upstream, through combinational logic (gated
+// clocks) and through the clock pins of other clocked components
+// (derived clocks, e.g. the stages of a ripple counter).
+
+use std::collections::HashSet;
+
+use crate::{
+    circuit::BCircuit,
+    components::update_component_state,
+    signal::{Signal, Word},
+    table::Table,
+    types::{CompType, Time, CLOCK_PIN, ID, NULL},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Clock {
+    pub name: String,
+    pub id: ID,
+    pub period: Time,
+    pub phase: Time,
+    // time spent high in each period
+    pub high: Time,
+}
+
+impl Clock {
+    pub fn level_at(&self, t: Time) -> Signal {
+        Signal::from(t >= self.phase && (t - self.phase) % self.period < self.high)
+    }
+    // the level changes in (from, to], earliest first
+    pub fn edges(&self, from: Time, to: Time) -> Vec<(Time, Signal)> {
+        let mut res = Vec::new();
+        let first = from.saturating_sub(self.phase) / self.period;
+        let mut start = self.phase + first * self.period;
+        while start <= to {
+            for (t, level) in [(start, Signal::One), (start + self.high, Signal::Zero)] {
+                if t > from && t <= to {
+                    res.push((t, level));
+                }
+            }
+            start += self.period;
+        }
+        res
+    }
+}
+
+impl BCircuit {
+    // adds an input named `name` driven as a clock, high for `duty`
+    // percent of every period
+    pub fn add_clock(
+        &mut self,
+        name: &str,
+        period: Time,
+        phase: Time,
+        duty: u8,
+    ) -> Result<ID, String> {
+        let high = period * duty as Time / 100;
+        if high == 0 || high >= period {
+            return Err(format!(
+                "Clock {} would never change with a period of {} and a duty cycle of {}%",
+                name, period, duty
+            ));
+        }
+        if self.clocks.iter().any(|c| c.name == name) {
+            return Err(format!("There is already a clock named {}", name));
+        }
+        let clock = Clock {
+            name: name.to_string(),
+            id: 0,
+            period,
+            phase,
+            high,
+        };
+        let id = self.add_input(name, clock.level_at(self.now()));
+        self.clocks.push(Clock { id, ..clock });
+        Ok(id)
+    }
+    pub fn clocks(&self) -> &[Clock] {
+        &self.clocks
+    }
+    pub fn clock_named(&self, name: &str) -> Option<&Clock> {
+        self.clocks.iter().find(|c| c.name == name)
+    }
+    pub fn is_clock(&self, id: ID) -> bool {
+        self.clocks.iter().any(|c| c.id == id)
+    }
+
+    // drives every clock up to time `t`, the circuit settling after
+    // each edge. In timed mode the edges are scheduled as events.
+    pub fn run_clocks(&mut self, t: Time) -> Result<(), String> {
+        if !self.active {
+            return Err("Power on the circuit first!".to_string());
+        }
+        let mut edges = Vec::new();
+        for c in &self.clocks {
+            for (at, level) in c.edges(self.now(), t) {
+                edges.push((at, c.id, Word::from(level)));
+            }
+        }
+        if self.is_timed() {
+            for (at, id, level) in edges {
+                self.schedule_state(id, level, at)?;
+            }
+            return self.advance_to(t);
+        }
+        edges.sort_by_key(|e| e.0);
+        for group in edges.chunk_by(|a, b| a.0 == b.0) {
+            self.timeline.now = group[0].0;
+            let levels = group
+                .iter()
+                .map(|e| (e.1, e.2))
+                .collect::<Vec<(ID, Word)>>();
+            self.drive_together(&levels)?;
+        }
+        self.timeline.now = self.now().max(t);
+        Ok(())
+    }
+    // one cycle of every clock, their edges applied together
+    pub fn pulse_clock(&mut self) -> Result<(), String> {
+        let levels = self
+            .clocks
+            .iter()
+            .map(|c| (c.id, self.state(c.id).unwrap()))
+            .collect::<Vec<(ID, Word)>>();
+        let toggled = levels
+            .iter()
+            .map(|(id, v)| (*id, !*v))
+            .collect::<Vec<(ID, Word)>>();
+        self.drive_together(&toggled)?;
+        self.drive_together(&levels)
+    }
+    // sets several inputs at once, their receivers settling together
+    pub(crate) fn drive_together(&mut self, vals: &[(ID, Word)]) -> Result<(), String> {
+        if vals.is_empty() {
+            return Ok(());
+        }
+        if !self.active {
+            return Err("Power on the circuit first!".to_string());
+        }
+        if self.is_timed() {
+            let now = self.now();
+            for (id, val) in vals {
+                self.schedule_state(*id, *val, now)?;
+            }
+            return self.advance_to(now);
+        }
+        let mut childs = Vec::new();
+        for (id, val) in vals {
+            childs.extend(self.drive(*id, *val)?);
+        }
+        self.graph_act(update_component_state, &childs)?;
+        self.check_buses()
+    }
+
+    // the clock whose edges trigger the clocked component `id`. None if
+    // it is not clocked by a circuit clock, or by more than one.
+    pub fn clock_domain(&self, id: ID) -> Option<&Clock> {
+        let mut stack = match self.components.get(&id) {
+            Some(c) if c.borrow().clock_manager.is_some() => {
+                vec![c.borrow().input_pin_sources[CLOCK_PIN]]
+            }
+            _ => return None,
+        };
+        let mut seen = HashSet::new();
+        let mut found = HashSet::new();
+        while let Some(src) = stack.pop() {
+            if src == NULL || !seen.insert(src) {
+                continue;
+            }
+            if self.is_clock(src) {
+                found.insert(src);
+                continue;
+            }
+            let c = self.components[&src].borrow();
+            match c.comp_type {
+                CompType::Combinational => stack.extend(&c.input_pin_sources[1..]),
+                _ if c.clock_manager.is_some() => stack.push(c.input_pin_sources[CLOCK_PIN]),
+                _ => {}
+            }
+        }
+        match found.len() {
+            1 => self.clocks.iter().find(|c| found.contains(&c.id)),
+            _ => None,
+        }
+    }
+    // the clocked components of every domain, sorted by label. The last
+    // entry, named "", has the ones that belong to no single domain.
+    pub fn domains(&self) -> Vec<(String, Vec<ID>)> {
+        let mut res = self
+            .clocks
+            .iter()
+            .map(|c| (c.name.clone(), Vec::new()))
+            .collect::<Vec<(String, Vec<ID>)>>();
+        res.push((String::new(), Vec::new()));
+        let mut ids = self
+            .components
+            .iter()
+            .filter(|(_, c)| c.borrow().clock_manager.is_some())
+            .map(|(id, _)| *id)
+            .collect::<Vec<ID>>();
+        ids.sort_by_key(|id| (self.components[id].borrow().label.clone(), *id));
+        for id in ids {
+            let i = match self.clock_domain(id) {
+                Some(clk) => self.clocks.iter().position(|c| c.id == clk.id).unwrap(),
+                None => self.clocks.len(),
+            };
+            res[i].1.push(id);
+        }
+        res
+    }
+    // the state of every clocked component with its domain, "-" for
+    // the ones that belong to no single domain
+    pub fn state_report(&self) -> Table<String> {
+        let mut t = Table::new();
+        t.set_columns(vec!["state".into(), "domain".into(), "value".into()])
+            .unwrap();
+        for (domain, ids) in self.domains() {
+            for id in ids {
+                let c = self.components[&id].borrow();
+                t.rows.push(vec![
+                    c.label.clone(),
+                    if domain.is_empty() {
+                        "-".into()
+                    } else {
+                        domain.clone()
+                    },
+                    c.state.to_string(),
+                ]);
+            }
+        }
+        t
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        circuit::BCircuit,
+        signal::{
+            Signal::{One, Zero},
+            Word,
+        },
+        types::CLOCK_PIN,
+    };
+
+    #[test]
+    fn clock_edges() {
+        let mut c = BCircuit::new();
+        let a = c.add_clock("a", 10, 0, 50).unwrap();
+        c.add_clock("b", 4, 3, 25).unwrap();
+        let b = c.clock_named("b").unwrap().clone();
+        assert_eq!(c.state(a).unwrap(), One);
+        assert_eq!(c.state(b.id).unwrap(), Zero);
+        assert_eq!(
+            b.edges(0, 12),
+            vec![
+                (3, One),
+                (4, Zero),
+                (7, One),
+                (8, Zero),
+                (11, One),
+                (12, Zero)
+            ]
+        );
+        assert_eq!(b.edges(7, 8), vec![(8, Zero)]);
+        assert_eq!(
+            (b.level_at(2), b.level_at(3), b.level_at(4)),
+            (Zero, One, Zero)
+        );
+        assert!(c.add_clock("c", 10, 0, 100).is_err());
+        assert!(c.add_clock("c", 1, 0, 50).is_err());
+        assert!(c.add_clock("a", 4, 0, 50).is_err());
+    }
+
+    #[test]
+    fn two_domains() {
+        let mut c = BCircuit::new();
+        let fast = c.add_clock("fast", 2, 0, 50).unwrap();
+        let slow = c.add_clock("slow", 6, 1, 50).unwrap();
+        let one = c.add_input("1", One);
+        let cnt = |c: &mut BCircuit, label: &str, clk| {
+            let q = c.add_component("COUNTER", label).unwrap();
+            c.connect_named(q, "CLK", clk).unwrap();
+            c.connect_named(q, "EN", one).unwrap();
+            c.connect_named(q, "UP", one).unwrap();
+            let zero = c.add_input(&format!("{}.load", label), Zero);
+            c.connect_named(q, "LOAD", zero).unwrap();
+            q
+        };
+        let f = cnt(&mut c, "F", fast);
+        let s = cnt(&mut c, "S", slow);
+        // derived clocks: an inverted fast clock and a divided slow one
+        let n = c.add_component("NOT", "").unwrap();
+        c.connect(n, 1, fast).unwrap();
+        let g = cnt(&mut c, "G", n);
+        let div = c.add_component("T", "DIV").unwrap();
+        c.connect(div, CLOCK_PIN, slow).unwrap();
+        c.connect(div, 1, one).unwrap();
+        let h = c.add_component("D", "H").unwrap();
+        c.connect(h, CLOCK_PIN, div).unwrap();
+        c.connect(h, 1, one).unwrap();
+        // clocked by both
+        let both = c.add_component("AND", "").unwrap();
+        c.connect(both, 1, fast).unwrap();
+        c.connect(both, 2, slow).unwrap();
+        let x = c.add_component("D", "X").unwrap();
+        c.connect(x, CLOCK_PIN, both).unwrap();
+        c.connect(x, 1, one).unwrap();
+
+        c.power_on().unwrap();
+        c.reset_states().unwrap();
+        c.run_clocks(12).unwrap();
+        assert_eq!(c.now(), 12);
+        // fast rises at 2, 4, .. 12, slow at 1 and 7
+        assert_eq!(c.state(f).unwrap(), Word::from_u64(6, 4));
+        assert_eq!(c.state(g).unwrap(), Word::from_u64(6, 4));
+        assert_eq!(c.state(s).unwrap(), Word::from_u64(2, 4));
+        c.run_clocks(13).unwrap();
+        assert_eq!(c.state(s).unwrap(), Word::from_u64(3, 4));
+
+        let names = |ids: &Vec<i32>| {
+            ids.iter()
+                .map(|id| c.components()[id].borrow().label.clone())
+                .collect::<Vec<String>>()
+        };
+        let domains = c.domains();
+        assert_eq!(domains.len(), 3);
+        assert_eq!(domains[0].0, "fast");
+        assert_eq!(names(&domains[0].1), ["F", "G"]);
+        assert_eq!(names(&domains[1].1), ["DIV", "H", "S"]);
+        assert_eq!(names(&domains[2].1), ["X"]);
+        assert_eq!(c.clock_domain(h).unwrap().name, "slow");
+        assert!(c.clock_domain(x).is_none());
+        let report = c.state_report();
+        assert_eq!(report.rows[0], ["F", "fast", "6"]);
+        assert_eq!(report.rows[5][..2], ["X", "-"]);
+
+        // clocks are not inputs of truth tables
+        assert!(!c.all_inputs_and_states().contains(&fast));
+        assert!(!c.all_inputs_and_states().contains(&slow));
+    }
+
+    #[test]
+    fn timed_clocks() {
+        let mut c = BCircuit::new();
+        let clk = c.add_clock("clk", 10, 5, 50).unwrap();
+        let n = c.add_component("NOT", "n").unwrap();
+        c.connect(n, 1, clk).unwrap();
+        c.set_component_delay(n, 3, 3).unwrap();
+        c.power_on().unwrap();
+        c.set_timed(true);
+        c.run_clocks(7).unwrap();
+        assert_eq!(c.state(clk).unwrap(), One);
+        assert_eq!(c.state(n).unwrap(), One);
+        c.run_clocks(8).unwrap();
+        assert_eq!(c.state(n).unwrap(), Zero);
+        c.run_clocks(12).unwrap();
+        assert_eq!(c.state(clk).unwrap(), Zero);
+        assert_eq!(c.state(n).unwrap(), Zero);
+        c.run_clocks(13).unwrap();
+        assert_eq!(c.state(n).unwrap(), One);
+    }
+}
@@ -0,0 +1,297 @@
+// Static analysis of a circuit, run by `BCircuit::compile` before
+// powering on.
+//
+// - levelization: inputs and clocked components are at level 0, every
+//   other component is one level above the highest of its sources.
+//   Components of a combinational loop share a level.
+// - combinational loops: cycles that don't pass through a clocked
+//   component. They are legal (e.g. latches) but can oscillate.
+// - unconnected input pins and outputs that drive nothing.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::{
+    circuit::BCircuit,
+    types::{ID, NULL, PIN},
+};
+
+#[derive(Debug, Default)]
+pub struct CompileReport {
+    // components by level, each level sorted by id
+    pub levels: Vec<Vec<ID>>,
+    // members of each combinational loop, sorted by id
+    pub comb_loops: Vec<Vec<ID>>,
+    pub unconnected_inputs: Vec<(ID, PIN)>,
+    // components whose output is neither connected nor tracked
+    pub dangling_outputs: Vec<ID>,
+    names: HashMap<ID, String>,
+}
+
+impl CompileReport {
+    // true if there is nothing to report
+    pub fn is_clean(&self) -> bool {
+        self.comb_loops.is_empty()
+            && self.unconnected_inputs.is_empty()
+            && self.dangling_outputs.is_empty()
+    }
+    pub fn level_of(&self, id: ID) -> Option<usize> {
+        self.levels.iter().position(|l| l.contains(&id))
+    }
+}
+
+impl fmt::Display for CompileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} components in {} levels",
+            self.names.len(),
+            self.levels.len()
+        )?;
+        for lp in &self.comb_loops {
+            let members = lp
+                .iter()
+                .map(|id| self.names[id].as_str())
+                .collect::<Vec<&str>>();
+            writeln!(
+                f,
+                "warning: combinational loop through {}",
+                members.join(", ")
+            )?;
+        }
+        for (id, pin) in &self.unconnected_inputs {
+            writeln!(
+                f,
+                "warning: pin_{} of {} is not connected",
+                pin, self.names[id]
+            )?;
+        }
+        for id in &self.dangling_outputs {
+            writeln!(f, "warning: output of {} is not used", self.names[id])?;
+        }
+        Ok(())
+    }
+}
+
+impl BCircuit {
+    pub fn compile(&self) -> CompileReport {
+        let mut ids = self.components.keys().cloned().collect::<Vec<ID>>();
+        ids.sort();
+        let mut report = CompileReport {
+            names: ids.iter().map(|id| (*id, self.describe(*id))).collect(),
+            ..Default::default()
+        };
+
+        // edges that a value crosses within the same clock cycle
+        let comb_edges = |id: ID| -> Vec<ID> {
+            let mut out = self.components[&id]
+                .borrow()
+                .get_output_receivers()
+                .iter()
+                .map(|(r, _)| *r)
+                .filter(|r| !self.components[r].borrow().has_independent_state())
+                .collect::<Vec<ID>>();
+            out.sort();
+            out.dedup();
+            out
+        };
+
+        // collapse loops so that the remaining graph is acyclic
+        let sccs = strongly_connected(&ids, &comb_edges);
+        let mut scc_of = HashMap::new();
+        for (i, scc) in sccs.iter().enumerate() {
+            for id in scc {
+                scc_of.insert(*id, i);
+            }
+            if scc.len() > 1 {
+                report.comb_loops.push(scc.clone());
+            }
+        }
+
+        // sccs come out in reverse topological order
+        let mut scc_level = vec![0; sccs.len()];
+        for i in (0..sccs.len()).rev() {
+            for id in &sccs[i] {
+                for r in comb_edges(*id) {
+                    let j = scc_of[&r];
+                    if j != i {
+                        scc_level[j] = scc_level[j].max(scc_level[i] + 1);
+                    }
+                }
+            }
+        }
+        for id in &ids {
+            let l = scc_level[scc_of[id]];
+            if report.levels.len() <= l {
+                report.levels.resize(l + 1, Vec::new());
+            }
+            report.levels[l].push(*id);
+        }
+
+        for id in &ids {
+            let c = self.components[id].borrow();
+            if !c.multi_driver {
+                // the clock pin only matters for clocked components
+                let first = if c.clock_manager.is_some() { 0 } else { 1 };
+                for pin in first..c.num_inputs() {
+                    // asynchronous set/reset pins are optional
+                    if c.input_pin_sources[pin] == NULL && !c.is_async_pin(pin) {
+                        report.unconnected_inputs.push((*id, pin));
+                    }
+                }
+            } else if c.input_pin_sources.iter().skip(1).all(|s| *s == NULL) {
+                // a bus without any driver
+                report.unconnected_inputs.push((*id, 1));
+            }
+            if c.get_output_receivers().is_empty() && !self.outputs.contains(id) {
+                report.dangling_outputs.push(*id);
+            }
+        }
+        report.comb_loops.sort();
+        report
+    }
+}
+
+// Tarjan's algorithm, with an explicit stack so that long chains don't
+// overflow the call stack. Components are returned sorted, in reverse
+// topological order of the condensed graph.
+fn strongly_connected(ids: &[ID], edges: &dyn Fn(ID) -> Vec<ID>) -> Vec<Vec<ID>> {
+    let mut index = HashMap::<ID, usize>::new();
+    let mut low = HashMap::<ID, usize>::new();
+    let mut stack = Vec::new();
+    let mut on_stack = HashSet::new();
+    let mut res = Vec::new();
+    // the components being visited, with the edges left to follow
+    let mut visits: Vec<(ID, std::vec::IntoIter<ID>)> = Vec::new();
+    for root in ids {
+        if index.contains_key(root) {
+            continue;
+        }
+        let mut next = Some(*root);
+        loop {
+            if let Some(v) = next.take() {
+                index.insert(v, index.len());
+                low.insert(v, index[&v]);
+                stack.push(v);
+                on_stack.insert(v);
+                visits.push((v, edges(v).into_iter()));
+            }
+            let (v, rest) = match visits.last_mut() {
+                Some((v, rest)) => (*v, rest),
+                None => break,
+            };
+            match rest.next() {
+                Some(w) if !index.contains_key(&w) => next = Some(w),
+                Some(w) => {
+                    if on_stack.contains(&w) {
+                        low.insert(v, low[&v].min(index[&w]));
+                    }
+                }
+                None => {
+                    visits.pop();
+                    if let Some((u, _)) = visits.last() {
+                        low.insert(*u, low[u].min(low[&v]));
+                    }
+                    if low[&v] == index[&v] {
+                        let mut scc = Vec::new();
+                        loop {
+                            let w = stack.pop().unwrap();
+                            on_stack.remove(&w);
+                            scc.push(w);
+                            if w == v {
+                                break;
+                            }
+                        }
+                        scc.sort();
+                        res.push(scc);
+                    }
+                }
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{circuit::BCircuit, signal::Signal::One, types::CLOCK_PIN};
+
+    #[test]
+    fn levels_and_diagnostics() {
+        let mut c = BCircuit::new();
+        let a = c.add_input("A", One);
+        let b = c.add_input("B", One);
+        let n = c.add_component("NOT", "").unwrap();
+        let and = c.add_component("AND", "F").unwrap();
+        let or = c.add_component("OR", "").unwrap();
+        c.connect(n, 1, a).unwrap();
+        c.connect(and, 1, n).unwrap();
+        c.connect(and, 2, b).unwrap();
+        c.connect(or, 1, and).unwrap();
+        c.track_output(and);
+
+        let r = c.compile();
+        assert_eq!(r.levels, vec![vec![a, b], vec![n], vec![and], vec![or]]);
+        assert!(r.comb_loops.is_empty());
+        assert_eq!(r.unconnected_inputs, vec![(or, 2)]);
+        assert_eq!(r.dangling_outputs, vec![or]);
+        let text = r.to_string();
+        assert!(text.contains(&format!("pin_2 of id_{} OR is not connected", or)));
+        assert!(!r.is_clean());
+    }
+
+    #[test]
+    fn loops() {
+        let mut c = BCircuit::new();
+        let s = c.add_input("S", One);
+        let r = c.add_input("R", One);
+        let q = c.add_component("NAND", "q").unwrap();
+        let nq = c.add_component("NAND", "nq").unwrap();
+        let out = c.add_component("BFR", "").unwrap();
+        c.connect(q, 1, s).unwrap();
+        c.connect(nq, 2, r).unwrap();
+        c.connect(q, 2, nq).unwrap();
+        c.connect(nq, 1, q).unwrap();
+        c.connect(out, 1, q).unwrap();
+
+        // a loop through a flip-flop is not combinational
+        let ff = c.add_component("JK", "Q").unwrap();
+        let n = c.add_component("NOT", "").unwrap();
+        c.connect(ff, CLOCK_PIN, s).unwrap();
+        c.connect(ff, 1, n).unwrap();
+        c.connect(ff, 2, n).unwrap();
+        c.connect(n, 1, ff).unwrap();
+        c.track_output(out);
+
+        let rep = c.compile();
+        assert_eq!(rep.comb_loops, vec![vec![q, nq]]);
+        assert_eq!(rep.level_of(q), Some(1));
+        assert_eq!(rep.level_of(nq), Some(1));
+        assert_eq!(rep.level_of(out), Some(2));
+        assert_eq!(rep.level_of(ff), Some(0));
+        assert_eq!(rep.level_of(n), Some(1));
+        assert!(rep.unconnected_inputs.is_empty());
+        assert!(rep.to_string().contains(&format!(
+            "loop through id_{} NAND (q), id_{} NAND (nq)",
+            q, nq
+        )));
+    }
+
+    #[test]
+    fn long_chain() {
+        // deep enough to overflow a recursive walk
+        let mut c = BCircuit::new();
+        let mut prev = c.add_input("A", One);
+        for _ in 0..50_000 {
+            let b = c.add_component("BFR", "").unwrap();
+            c.connect(b, 1, prev).unwrap();
+            prev = b;
+        }
+        let r = c.compile();
+        assert_eq!(r.levels.len(), 50_001);
+        assert!(r.comb_loops.is_empty());
+        assert_eq!(r.dangling_outputs, vec![prev]);
+    }
+}
@@ -2,11 +2,18 @@ use std::{
     cell::RefCell,
     collections::{HashMap, HashSet, VecDeque},
     fmt::{self},
+    rc::Rc,
 };
 
 use crate::{
-    clock_manager::ClockManager,
-    types::{BinaryLogicReducer, CompType, CLOCK_PIN, ID, NULL, PIN, UNASSIGNED},
+    clock_manager::{ClockManager, Trigger},
+    memory::{Memory, MemoryDef, DEFAULT_ADDR_WIDTH},
+    signal::{Signal, Word, MAX_WIDTH},
+    subcircuit::Subcircuit,
+    table::Table,
+    types::{
+        BinaryLogicReducer, CompType, PatternReducer, Time, CLOCK_PIN, ID, NULL, PIN, UNASSIGNED,
+    },
     utils::form_expr,
 };
 #[derive(Clone)]
@@ -17,25 +24,203 @@ pub struct ComponentDefParams {
     pub eval: BinaryLogicReducer,
     pub default_inputs: u16,
     pub symbol: String,
+    // input pins are drivers of a shared net: more pins are added as
+    // drivers get connected, see `BCircuit::connect_driver`.
+    pub multi_driver: bool,
+    // the gate reduces over all its data pins, so instances can have
+    // any number of them, see `BCircuit::add_component_with_inputs`.
+    // `default_inputs` is then the minimum.
+    pub variadic: bool,
+    // bus width of the output and of every data input. Instances can
+    // override it with `BCircuit::add_component_with_width`.
+    pub width: u8,
+    // pins that are always a single bit wide, e.g. enables. The clock
+    // pin is always a single bit.
+    pub control_pins: Vec<PIN>,
+    // propagation delays used by timed simulation, for output changes
+    // that raise a bit to 1 and for every other change respectively.
+    pub rise_delay: Time,
+    pub fall_delay: Time,
+    // optional bitwise version of `eval` used by pattern simulation, see
+    // `pattern`. Without it patterns are evaluated one at a time.
+    pub pattern_eval: Option<PatternReducer>,
+    // set for types defined from a circuit, which is evaluated in place
+    // of `eval`. See `BCircuit::define_subcircuit`.
+    pub subcircuit: Option<Rc<Subcircuit>>,
+    // when clocked components take their next state, see `Trigger`
+    pub trigger: Trigger,
+    // asynchronous pins of clocked components, each forcing the state
+    // to its value while high, regardless of the clock. They are
+    // optional: a floating pin is inactive.
+    pub async_pins: Vec<(PIN, Signal)>,
+    // names of the input pins, starting from the clock pin, e.g.
+    // ["CLK", "D"]. See `BCircuit::port_pin`.
+    pub pin_names: Vec<String>,
+    // set for memories, whose `eval` is replaced by an access to the
+    // contents of each instance, see `memory`
+    pub memory: Option<MemoryDef>,
 }
 
+// wraps a closure as the `eval` of a component definition
+pub fn reducer(f: impl Fn(&[Word], Word) -> Word + 'static) -> BinaryLogicReducer {
+    Rc::new(f)
+}
+
+impl Default for ComponentDefParams {
+    fn default() -> Self {
+        ComponentDefParams {
+            name: String::new(),
+            label: String::new(),
+            comp_type: CompType::Combinational,
+            eval: reducer(|_, old| old),
+            default_inputs: 0,
+            symbol: String::new(),
+            multi_driver: false,
+            variadic: false,
+            width: 1,
+            control_pins: Vec::new(),
+            rise_delay: 1,
+            fall_delay: 1,
+            pattern_eval: None,
+            subcircuit: None,
+            trigger: Trigger::RisingEdge,
+            async_pins: Vec::new(),
+            pin_names: Vec::new(),
+            memory: None,
+        }
+    }
+}
+
+// truth-table gates can't have more inputs than this
+pub const MAX_TABLE_INPUTS: usize = 16;
+
+impl ComponentDefParams {
+    // defines a combinational gate from a truth table, e.g. one made by
+    // `BCircuit::gen_truth_table`. `inputs` name the columns read by
+    // pins 1, 2, ... and `output` the column of results. Input values
+    // must be 0 or 1, rows missing from the table output X.
+    pub fn from_table(
+        name: &str,
+        table: &Table<char>,
+        inputs: &[&str],
+        output: &str,
+    ) -> Result<ComponentDefParams, String> {
+        if inputs.is_empty() || inputs.len() > MAX_TABLE_INPUTS {
+            return Err(format!(
+                "{} needs 1 to {} inputs, got {}",
+                name,
+                MAX_TABLE_INPUTS,
+                inputs.len()
+            ));
+        }
+        for col in inputs.iter().chain([&output]) {
+            if !table.columns().iter().any(|c| c == col) {
+                return Err(format!("No column {} in the table of {}", col, name));
+            }
+        }
+        // indexed by the input values, the first input being the MSB
+        let mut lookup: Vec<Option<Signal>> = vec![None; 1 << inputs.len()];
+        for i in 0..table.rows.len() {
+            let mut idx = 0;
+            for col in inputs {
+                idx = idx << 1
+                    | match table.get_val_at(i, col) {
+                        '0' => 0,
+                        '1' => 1,
+                        v => {
+                            return Err(format!(
+                                "Row {} of {} has {} for input {}, expected 0 or 1",
+                                i, name, v, col
+                            ))
+                        }
+                    };
+            }
+            let val = match Signal::from_char(*table.get_val_at(i, output)) {
+                Some(s) => s.read(),
+                None => {
+                    return Err(format!(
+                        "Row {} of {} has an invalid output {}",
+                        i,
+                        name,
+                        table.get_val_at(i, output)
+                    ))
+                }
+            };
+            match lookup[idx] {
+                Some(old) if old != val => {
+                    return Err(format!("Conflicting rows in the table of {}", name))
+                }
+                _ => lookup[idx] = Some(val),
+            }
+        }
+        let lookup: Vec<Signal> = lookup.into_iter().map(|v| v.unwrap_or(Signal::X)).collect();
+        let n = inputs.len();
+        Ok(ComponentDefParams {
+            name: name.to_string(),
+            eval: reducer(move |v, old| {
+                Word::from_fn(old.width(), |b| {
+                    let mut idx = 0;
+                    let mut unknown = Vec::new();
+                    for k in 0..n {
+                        idx <<= 1;
+                        match v[k + 1].bit(b).read() {
+                            Signal::One => idx |= 1,
+                            Signal::Zero => {}
+                            _ => unknown.push(n - 1 - k),
+                        }
+                    }
+                    // unknown inputs: the output is known only if every
+                    // completion of them agrees
+                    let mut out = lookup[idx];
+                    for m in 1..1usize << unknown.len() {
+                        let mut j = idx;
+                        for (t, &bit) in unknown.iter().enumerate() {
+                            j |= (m >> t & 1) << bit;
+                        }
+                        if lookup[j] != out {
+                            out = Signal::X;
+                        }
+                    }
+                    out
+                })
+            }),
+            default_inputs: n as u16,
+            symbol: name.to_string(),
+            ..Default::default()
+        })
+    }
+}
+
+#[derive(Clone)]
 pub struct Gate {
     pub name: String,
     pub id: ID,
     pub comp_type: CompType,
     pub label: String, //todo: shift to comp_type
     eval: BinaryLogicReducer,
-    pub state: bool,
+    pattern_eval: Option<PatternReducer>,
+    pub(crate) subcircuit: Option<Rc<Subcircuit>>,
+    pub state: Word,
     pub(crate) output_recvlist: HashSet<(ID, PIN)>,
-    #[deprecated(note = "Compute from vector sizes of `self.input_pin_values`")]
-    n_inp: usize,
     pub symbol: String,
+    pub multi_driver: bool,
+    width: u8,
+    control_pins: Vec<PIN>,
+    async_pins: Vec<(PIN, Signal)>,
+    pin_names: Vec<String>,
+    memory_def: Option<MemoryDef>,
+    memory: Option<Rc<RefCell<Memory>>>,
+    pub rise_delay: Time,
+    pub fall_delay: Time,
 
     // todo: combine these three into one
-    input_pin_values: Vec<bool>,
+    input_pin_values: Vec<Word>,
     pub input_pin_sources: Vec<ID>,
     pub input_pin_exprs: Vec<String>,
-    pub active: bool,
+    input_pin_widths: Vec<u8>,
+    // Some(lsb) if the pin is connected to a slice of its emitter's
+    // output starting at bit `lsb`
+    input_pin_slices: Vec<Option<u8>>,
     pub state_expr: String,
     pub clock_manager: Option<ClockManager>,
 }
@@ -49,35 +234,52 @@ impl Gate {
             comp_type: p.comp_type,
             label: p.label.to_owned(),
             eval: p.eval,
-            state: false, // initial state always remains false as component is not fully connected
+            pattern_eval: p.pattern_eval,
+            subcircuit: p.subcircuit.clone(),
+            state: Word::unknown(1),
             output_recvlist: HashSet::new(),
-            n_inp,
             symbol: p.symbol.clone(),
-            active: p.comp_type != CompType::Combinational, // initially combinational elems are inactive
+            multi_driver: p.multi_driver,
+            width: 1,
+            control_pins: p.control_pins.clone(),
+            async_pins: p.async_pins.clone(),
+            pin_names: p.pin_names.clone(),
+            memory_def: p.memory,
+            memory: None,
+            rise_delay: p.rise_delay,
+            fall_delay: p.fall_delay,
 
-            // 0th pin is the clock pin
-            input_pin_values: vec![false; n_inp],
+            // 0th pin is the clock pin, unconnected pins float
+            input_pin_values: Vec::new(),
             input_pin_sources: vec![NULL; n_inp],
             input_pin_exprs: vec![String::new(); n_inp],
+            input_pin_widths: Vec::new(),
+            input_pin_slices: vec![None; n_inp],
             state_expr: String::new(),
 
             clock_manager: None,
         };
+        c.set_width(p.width);
+        if c.memory_def.is_some() {
+            c.resize_memory(DEFAULT_ADDR_WIDTH);
+        }
         if c.comp_type == CompType::Sequential {
-            c.clock_manager = Some(ClockManager::new());
+            c.clock_manager = Some(ClockManager::new(p.trigger));
             c.state_expr = p.label + "(t)"
         }
         c
     }
-    pub fn make_input(lab: &str, init: bool) -> Gate {
+    pub fn make_input(lab: &str, init: Word) -> Gate {
         // the eval function will not be called on input elements
         let mut c = Gate::from_params(ComponentDefParams {
             name: String::from("Input"),
             label: lab.to_owned(),
-            eval: |_, old| old, // can only be externally changed
+            eval: reducer(|_, old| old), // can only be externally changed
             default_inputs: 0,
             symbol: lab.to_owned(),
             comp_type: CompType::Input,
+            width: init.width(),
+            ..Default::default()
         });
         c.state = init;
         c.state_expr = lab.to_string();
@@ -86,21 +288,127 @@ impl Gate {
     pub fn num_inputs(&self) -> usize {
         self.input_pin_exprs.len()
     }
-    pub fn set_state(&mut self, state: bool) {
+    // the input pin called `name`, a port for subcircuit instances
+    pub fn pin_named(&self, name: &str) -> Option<PIN> {
+        match &self.subcircuit {
+            Some(sub) => sub.input_pin(name),
+            None => self.pin_names.iter().position(|n| n == name),
+        }
+    }
+    pub fn width(&self) -> u8 {
+        self.width
+    }
+    pub fn pin_width(&self, pin: PIN) -> u8 {
+        self.input_pin_widths[pin]
+    }
+    pub fn pin_value(&self, pin: PIN) -> Word {
+        self.input_pin_values[pin]
+    }
+    pub fn pin_slice(&self, pin: PIN) -> Option<u8> {
+        self.input_pin_slices[pin]
+    }
+    fn default_pin_width(&self, pin: PIN) -> u8 {
+        if let Some(sub) = &self.subcircuit {
+            sub.pin_width(pin)
+        } else if pin == CLOCK_PIN || self.control_pins.contains(&pin) {
+            1
+        } else if let (Some(def), Some(m)) = (&self.memory_def, &self.memory) {
+            if pin == def.addr_pin {
+                m.borrow().addr_width()
+            } else {
+                self.width
+            }
+        } else {
+            self.width
+        }
+    }
+    // resizes the output and all data pins. Values are reset, so this
+    // is meant for components that are not connected yet.
+    pub(crate) fn set_width(&mut self, width: u8) {
+        assert!(width > 0 && width <= MAX_WIDTH, "bad bus width {}", width);
+        self.width = width;
+        // outputs are unknown until driven, and clocked elements until
+        // cleared or clocked
+        self.state = Word::unknown(width);
+        self.input_pin_widths = (0..self.num_inputs())
+            .map(|p| self.default_pin_width(p))
+            .collect();
+        self.input_pin_values = self
+            .input_pin_widths
+            .iter()
+            .map(|w| Word::hiz(*w))
+            .collect();
+        if let Some(m) = &self.memory {
+            let addr_width = m.borrow().addr_width();
+            self.resize_memory(addr_width);
+        }
+    }
+    // clears the contents of a memory, giving it `addr_width` address
+    // bits and words as wide as the component
+    pub(crate) fn resize_memory(&mut self, addr_width: u8) {
+        let def = self.memory_def.expect("not a memory");
+        self.set_memory(Memory::new(addr_width, self.width, def.read_only));
+        self.input_pin_widths[def.addr_pin] = addr_width;
+        self.input_pin_values[def.addr_pin] = Word::hiz(addr_width);
+    }
+    fn set_memory(&mut self, mem: Memory) {
+        let access = self.memory_def.expect("not a memory").access;
+        let mem = Rc::new(RefCell::new(mem));
+        let m = mem.clone();
+        self.eval = reducer(move |v, _| access(&mut m.borrow_mut(), v));
+        self.memory = Some(mem);
+    }
+    pub fn memory(&self) -> Option<Rc<RefCell<Memory>>> {
+        self.memory.clone()
+    }
+    // overrides the width of individual input pins
+    pub(crate) fn set_pin_widths(&mut self, widths: &[u8]) {
+        for (pin, w) in widths.iter().enumerate().take(self.num_inputs()) {
+            self.input_pin_widths[pin] = *w;
+            self.input_pin_values[pin] = Word::hiz(*w);
+        }
+    }
+    pub fn pin_widths(&self) -> &Vec<u8> {
+        &self.input_pin_widths
+    }
+    pub(crate) fn default_pin_widths(&self) -> Vec<u8> {
+        (0..self.num_inputs())
+            .map(|p| self.default_pin_width(p))
+            .collect()
+    }
+    pub fn set_state(&mut self, state: Word) {
         self.state = state;
     }
     pub fn has_independent_state(&self) -> bool {
         // input and clocked components have independent state
         self.comp_type == CompType::Input || self.comp_type == CompType::Sequential
     }
-    fn are_inputs_completely_connected(&self) -> bool {
-        (match self.clock_manager {
-            Some(_) => &self.input_pin_sources[..],
-            None => &self.input_pin_sources[1..],
-        })
-        .iter()
-        .find(|a| **a == NULL)
-        .is_none()
+    pub fn are_inputs_completely_connected(&self) -> bool {
+        let first = if self.clock_manager.is_some() { 0 } else { 1 };
+        (first..self.num_inputs())
+            .find(|p| self.input_pin_sources[*p] == NULL && !self.is_async_pin(*p))
+            .is_none()
+    }
+    pub fn async_pins(&self) -> &Vec<(PIN, Signal)> {
+        &self.async_pins
+    }
+    pub fn is_async_pin(&self, pin: PIN) -> bool {
+        self.async_pins.iter().any(|(p, _)| *p == pin)
+    }
+    // drops all connections, keeping pin widths. Used when copying
+    // components into another circuit.
+    pub(crate) fn detach(&mut self) {
+        self.input_pin_sources.fill(NULL);
+        self.input_pin_slices.fill(None);
+        for (pin, w) in self.input_pin_widths.iter().enumerate() {
+            self.input_pin_values[pin] = Word::hiz(*w);
+        }
+        self.output_recvlist.clear();
+        // the copy gets its own contents
+        if let Some(m) = self.memory.take() {
+            let contents = m.borrow().clone();
+            self.set_memory(contents);
+        }
     }
     pub fn link_output_receiver(&mut self, receiver_id: ID, pin: PIN) {
         self.output_recvlist.insert((receiver_id, pin));
@@ -119,7 +427,18 @@ impl Gate {
     pub fn get_output_receivers(&self) -> &HashSet<(ID, PIN)> {
         return &self.output_recvlist;
     }
-    pub fn set_input_pin_connection(&mut self, pin: PIN, emitter: &Gate) -> Result<(), String> {
+    // connects `pin` to the output of `emitter`. With `slice` set to
+    // Some(lsb), the pin reads `pin_width` bits of the emitter output
+    // starting at `lsb` instead of the whole output.
+    pub fn set_input_pin_connection(
+        &mut self,
+        pin: PIN,
+        emitter: &Gate,
+        slice: Option<u8>,
+    ) -> Result<(), String> {
+        if self.multi_driver && pin >= self.num_inputs() {
+            self.add_input_pins(pin + 1 - self.num_inputs());
+        }
         if pin >= self.num_inputs() {
             return Err(format!(
                 "Only have {} input pins in {}, can't access pin_{}",
@@ -131,11 +450,33 @@ impl Gate {
         if self.input_pin_sources[pin] != NULL {
             return Err(format!("Please disconnect it first!"));
         }
+        let pin_width = self.input_pin_widths[pin];
+        match slice {
+            None if emitter.width != pin_width => {
+                return Err(format!(
+                    "Width mismatch: id_{} outputs {} bit(s) but pin_{} of id_{} takes {}",
+                    emitter.id, emitter.width, pin, self.id, pin_width
+                ))
+            }
+            Some(lsb) if lsb as usize + pin_width as usize > emitter.width as usize => {
+                return Err(format!(
+                    "Bits {}..{} are out of range of the {} bit output of id_{}",
+                    lsb,
+                    lsb as usize + pin_width as usize,
+                    emitter.width,
+                    emitter.id
+                ))
+            }
+            _ => {}
+        }
 
+        self.input_pin_slices[pin] = slice;
         self.set_pin_val(pin, emitter.state);
+        if let (CLOCK_PIN, Some(cm)) = (pin, &mut self.clock_manager) {
+            cm.init(emitter.state.bit(0));
+        }
         self.input_pin_sources[pin as usize] = emitter.id;
-        self.input_pin_exprs[pin as usize].push_str(&emitter.state_expr);
-        self.active = self.are_inputs_completely_connected();
+        self.set_pin_expr(pin, &emitter.state_expr);
         Ok(())
     }
     pub fn clear_input_pin_connection(&mut self, pin: PIN) -> Result<(), String> {
@@ -150,22 +491,142 @@ impl Gate {
         // we do allow setting CLOCK_PIN`th index for non clocked compos
         // they are simply never used
         // println!("{} inppin_{} val {}", self.label, pin, false);
-        self.input_pin_values[pin] = false;
+        self.input_pin_slices[pin] = None;
+        self.set_pin_val(pin, Word::hiz(self.input_pin_widths[pin]));
         self.input_pin_sources[pin] = NULL;
         self.input_pin_exprs[pin].clear();
-        self.active = self.are_inputs_completely_connected();
         Ok(())
     }
-    pub(crate) fn set_pin_val(&mut self, pin: PIN, val: bool) {
+    pub(crate) fn add_input_pins(&mut self, n: usize) {
+        let total = self.num_inputs() + n;
+        self.input_pin_values.resize(total, Word::hiz(self.width));
+        self.input_pin_sources.resize(total, NULL);
+        self.input_pin_exprs.resize(total, String::new());
+        self.input_pin_widths.resize(total, self.width);
+        self.input_pin_slices.resize(total, None);
+    }
+    // first free driver pin of a multi driver component, adding one if
+    // all of them are taken.
+    pub fn free_driver_pin(&self) -> PIN {
+        (1..self.num_inputs())
+            .find(|p| self.input_pin_sources[*p] == NULL)
+            .unwrap_or(self.num_inputs().max(1))
+    }
+    // `val` is the full output of the pin's emitter
+    pub(crate) fn set_pin_val(&mut self, pin: PIN, val: Word) {
+        let val = match self.input_pin_slices[pin] {
+            Some(lsb) => val.slice(lsb, self.input_pin_widths[pin]),
+            None => val,
+        };
         if pin == CLOCK_PIN {
             if let Some(cm) = &mut self.clock_manager {
-                cm.push(val);
+                cm.push(val.bit(0));
             }
         }
         self.input_pin_values[pin] = val;
     }
+    pub(crate) fn eval_fn(&self) -> BinaryLogicReducer {
+        self.eval.clone()
+    }
+    // like `eval_fn`, a writable memory evaluating its own copy of the
+    // contents, as `detach` gives it
+    pub(crate) fn detached_eval_fn(&self) -> BinaryLogicReducer {
+        match &self.memory {
+            Some(m) if !m.borrow().is_read_only() => {
+                let access = self.memory_def.expect("not a memory").access;
+                let m = RefCell::new(m.borrow().clone());
+                reducer(move |v, _| access(&mut m.borrow_mut(), v))
+            }
+            _ => self.eval.clone(),
+        }
+    }
+    pub(crate) fn pattern_eval_fn(&self) -> Option<PatternReducer> {
+        self.pattern_eval
+    }
+    // delay before the output changes from `from` to `to`
+    pub fn delay(&self, from: Word, to: Word) -> Time {
+        if to.ones() & !from.ones() != 0 {
+            self.rise_delay
+        } else {
+            self.fall_delay
+        }
+    }
+    // the state the component settles to given its current pin values.
+    // Clocked components only change on a clock trigger, an edge being
+    // consumed, or while an asynchronous pin is active.
+    pub(crate) fn next_state(&mut self) -> Word {
+        match &mut self.clock_manager {
+            Some(mag) => {
+                // clocked component
+                let next = if mag.clock_triggered() {
+                    mag.reset_clock_hist();
+                    (self.eval)(&self.input_pin_values, self.state)
+                } else {
+                    self.state
+                };
+                self.apply_async(next)
+            }
+            // unconnected pins float (Z), which gates read as X, so a
+            // partially connected component outputs X unless its other
+            // inputs dominate.
+            None => (self.eval)(&self.input_pin_values, self.state),
+        }
+    }
+    // whether a clocked component keeps its state: it sees no clock
+    // trigger and no active asynchronous pin
+    pub(crate) fn holds(&self) -> bool {
+        let triggered = self
+            .clock_manager
+            .as_ref()
+            .is_some_and(|cm| cm.clock_triggered());
+        !triggered
+            && self.async_pins.iter().all(|(pin, _)| {
+                matches!(self.input_pin_values[*pin].bit(0), Signal::Zero | Signal::Z)
+            })
+    }
+    // `state` overridden by the active asynchronous pins. Pins at X
+    // leave the bits that already have their value, others become X,
+    // as do all bits when pins forcing different values are active.
+    fn apply_async(&self, state: Word) -> Word {
+        let mut forced = None;
+        let mut next = state;
+        for (pin, val) in &self.async_pins {
+            match self.input_pin_values[*pin].bit(0) {
+                Signal::Zero | Signal::Z => {}
+                Signal::One => {
+                    forced = match forced {
+                        Some(f) if f != *val => Some(Signal::X),
+                        _ => Some(*val),
+                    }
+                }
+                Signal::X => {
+                    next = Word::from_fn(next.width(), |i| {
+                        if next.bit(i) == *val {
+                            *val
+                        } else {
+                            Signal::X
+                        }
+                    })
+                }
+            }
+        }
+        match forced {
+            Some(f) => Word::splat(f, state.width()),
+            None => next,
+        }
+    }
     pub fn set_pin_expr(&mut self, pin: PIN, val: &str) {
-        self.input_pin_exprs[pin].replace_range(.., &val);
+        let expr = match self.input_pin_slices[pin] {
+            Some(lsb) if self.input_pin_widths[pin] == 1 => format!("{}[{}]", val, lsb),
+            Some(lsb) => format!(
+                "{}[{}:{}]",
+                val,
+                lsb as usize + self.input_pin_widths[pin] as usize - 1,
+                lsb
+            ),
+            None => val.to_string(),
+        };
+        self.input_pin_exprs[pin].replace_range(.., &expr);
     }
 }
 
@@ -196,25 +657,7 @@ fn state_update(
     exec_q: &mut VecDeque<ID>,
     optimize: bool,
 ) {
-    let new_state = match &mut c.clock_manager {
-        Some(mag) => {
-            // clocked component
-            if mag.clock_triggered() {
-                mag.reset_clock_hist();
-                (c.eval)(&c.input_pin_values, c.state)
-            } else {
-                c.state
-            }
-        }
-        None => {
-            if !c.active {
-                // a component is off until it is completely connected
-                false
-            } else {
-                (c.eval)(&c.input_pin_values, c.state)
-            }
-        }
-    };
+    let new_state = c.next_state();
     // println!("{} {} : {}", c.name, c.label, c.state);
 
     if optimize && (new_state == c.state) {
@@ -279,18 +722,22 @@ pub(crate) fn set_expressions(
 
 impl fmt::Display for Gate {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let state_str = if self.state {
-            "\x1b[32mON\x1b[0m" // Green text for ON
+        let state_str = if self.width > 1 {
+            format!("\x1b[36m{}\x1b[0m", self.state) // Cyan value for buses
         } else {
-            "\x1b[31mOFF\x1b[0m" // Red text for OFF
+            match self.state.bit(0) {
+                Signal::One => "\x1b[32mON\x1b[0m".to_string(), // Green text for ON
+                Signal::Zero => "\x1b[31mOFF\x1b[0m".to_string(), // Red text for OFF
+                s => format!("\x1b[33m{}\x1b[0m", s),           // Yellow for X/Z
+            }
         };
 
         write!(
             f,
             "{} ({} input{}) - Symbol: \x1b[33m{}\x1b[0m - State: {}\n{}",
             self.name,
-            self.n_inp,
-            if self.n_inp == 1 { "" } else { "s" },
+            self.num_inputs(),
+            if self.num_inputs() == 1 { "" } else { "s" },
             self.symbol,
             state_str,
             self.input_pin_exprs.join("  \n")
@@ -0,0 +1,708 @@
+// Heuristic two-level minimization of wide functions.
+//
+// Functions are covers: lists of cubes (products of literals) over up to
+// 64 inputs, so no truth table is enumerated. `espresso` follows the
+// Espresso loop: expand every cube as far as the off-set allows, drop
+// redundant cubes, then repeatedly reduce, expand and drop again while
+// the number of terms, then of literals, keeps falling. The result is a
+// cover of prime implicants none of which can be removed, not a
+// guaranteed minimum like `quine_mccluskey`.
+//
+// Complements and containment checks use the usual recursive splitting
+// on the most binate input, which stays cheap for the functions met in
+// practice but is exponential in the worst case.
+
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    circuit::BCircuit,
+    quine_mccluskey::{is_dont_care, qm_simplify_one},
+    signal::{Signal, Word},
+    table::Table,
+    types::{CompType, ID, NULL},
+};
+
+pub const MAX_CUBE_INPUTS: usize = 64;
+// inputs past which `compare_minimizers` skips Quine-McCluskey
+pub const MAX_QM_INPUTS: usize = 12;
+// inputs a gate may have for `BCircuit::output_cover` to tabulate it
+pub const MAX_GATE_INPUTS: usize = 8;
+
+// bit i of `care` is set if input i appears in the product, bit i of
+// `val` is then its value. Other bits of `val` are 0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Cube {
+    pub care: u64,
+    pub val: u64,
+}
+
+impl Cube {
+    pub const UNIVERSE: Cube = Cube { care: 0, val: 0 };
+
+    pub fn literal(input: usize, positive: bool) -> Cube {
+        Cube {
+            care: 1 << input,
+            val: (positive as u64) << input,
+        }
+    }
+    // from one char per input: '0', '1' or a don't-care
+    pub fn from_chars(chars: &[char]) -> Option<Cube> {
+        let mut c = Cube::UNIVERSE;
+        for (i, ch) in chars.iter().enumerate() {
+            match ch {
+                '0' => c.care |= 1 << i,
+                '1' => {
+                    c.care |= 1 << i;
+                    c.val |= 1 << i;
+                }
+                ch if is_dont_care(*ch) => {}
+                _ => return None,
+            }
+        }
+        Some(c)
+    }
+    pub fn literals(&self) -> u32 {
+        self.care.count_ones()
+    }
+    pub fn contains(&self, o: &Cube) -> bool {
+        self.care & !o.care == 0 && (self.val ^ o.val) & self.care == 0
+    }
+    pub fn intersect(&self, o: &Cube) -> Option<Cube> {
+        if (self.val ^ o.val) & self.care & o.care != 0 {
+            return None;
+        }
+        Some(Cube {
+            care: self.care | o.care,
+            val: self.val | o.val,
+        })
+    }
+    // the smallest cube containing both
+    fn supercube(&self, o: &Cube) -> Cube {
+        let care = self.care & o.care & !(self.val ^ o.val);
+        Cube {
+            care,
+            val: self.val & care,
+        }
+    }
+    // the cube restricted to `c`, with the inputs of `c` dropped
+    fn cofactor(&self, c: &Cube) -> Option<Cube> {
+        self.intersect(c)?;
+        Some(Cube {
+            care: self.care & !c.care,
+            val: self.val & !c.care,
+        })
+    }
+}
+
+fn cofactor(f: &[Cube], c: &Cube) -> Vec<Cube> {
+    f.iter().filter_map(|k| k.cofactor(c)).collect()
+}
+
+// the input appearing in both polarities in most cubes, if any
+fn binate_input(f: &[Cube]) -> Option<usize> {
+    (0..MAX_CUBE_INPUTS)
+        .filter_map(|i| {
+            let pos = f.iter().filter(|c| (c.care & c.val) >> i & 1 == 1).count();
+            let neg = f.iter().filter(|c| (c.care & !c.val) >> i & 1 == 1).count();
+            (pos > 0 && neg > 0).then_some((pos + neg, i))
+        })
+        .max()
+        .map(|(_, i)| i)
+}
+
+fn tautology(f: &[Cube]) -> bool {
+    if f.iter().any(|c| c.care == 0) {
+        return true;
+    }
+    // a cover that is unate in every input is only a tautology if it
+    // has the universe
+    match binate_input(f) {
+        Some(i) => {
+            tautology(&cofactor(f, &Cube::literal(i, false)))
+                && tautology(&cofactor(f, &Cube::literal(i, true)))
+        }
+        None => false,
+    }
+}
+
+// drops the cubes contained in another
+fn absorb(mut f: Vec<Cube>) -> Vec<Cube> {
+    f.sort_by_key(|c| c.literals());
+    f.dedup();
+    let mut res: Vec<Cube> = Vec::new();
+    for c in f {
+        if !res.iter().any(|k| k.contains(&c)) {
+            res.push(c);
+        }
+    }
+    res
+}
+
+pub fn complement(f: &[Cube]) -> Vec<Cube> {
+    if f.iter().any(|c| c.care == 0) {
+        return Vec::new();
+    }
+    if let [c] = f {
+        return (0..MAX_CUBE_INPUTS)
+            .filter(|i| c.care >> i & 1 == 1)
+            .map(|i| Cube::literal(i, c.val >> i & 1 == 0))
+            .collect();
+    }
+    let i = match binate_input(f) {
+        Some(i) => i,
+        // unate: split on any input
+        None => match f.iter().map(|c| c.care).fold(0, |a, b| a | b) {
+            0 => return vec![Cube::UNIVERSE],
+            care => care.trailing_zeros() as usize,
+        },
+    };
+    let mut res = Vec::new();
+    for positive in [false, true] {
+        let lit = Cube::literal(i, positive);
+        for c in complement(&cofactor(f, &lit)) {
+            res.push(c.intersect(&lit).unwrap());
+        }
+    }
+    // join the halves that only differ in input i
+    let joined = res
+        .iter()
+        .map(|c| {
+            let other = Cube {
+                care: c.care,
+                val: c.val ^ 1 << i,
+            };
+            if c.care >> i & 1 == 1 && res.contains(&other) {
+                c.supercube(&other)
+            } else {
+                *c
+            }
+        })
+        .collect();
+    absorb(joined)
+}
+
+// the cubes of `f` in `g`
+pub fn intersect_covers(f: &[Cube], g: &[Cube]) -> Vec<Cube> {
+    let cubes = f
+        .iter()
+        .flat_map(|a| g.iter().filter_map(|b| a.intersect(b)))
+        .collect();
+    absorb(cubes)
+}
+
+pub fn covers(f: &[Cube], c: &Cube) -> bool {
+    tautology(&cofactor(f, c))
+}
+
+// (terms, literals)
+pub fn cost(f: &[Cube]) -> (usize, u32) {
+    (f.len(), f.iter().map(|c| c.literals()).sum())
+}
+
+// raises the literals of every cube while it stays off the off-set,
+// first the ones letting it swallow most of the other cubes
+fn expand(mut f: Vec<Cube>, off: &[Cube]) -> Vec<Cube> {
+    f.sort_by_key(|c| c.literals());
+    let mut res: Vec<Cube> = Vec::new();
+    for (n, c) in f.iter().enumerate() {
+        if res.iter().any(|k| k.contains(c)) {
+            continue;
+        }
+        let mut c = *c;
+        loop {
+            let best = (0..MAX_CUBE_INPUTS)
+                .filter(|i| c.care >> i & 1 == 1)
+                .map(|i| Cube {
+                    care: c.care & !(1 << i),
+                    val: c.val & !(1 << i),
+                })
+                .filter(|r| off.iter().all(|o| r.intersect(o).is_none()))
+                .max_by_key(|r| {
+                    let swallowed = f[n + 1..].iter().filter(|k| r.contains(k)).count();
+                    (swallowed, std::cmp::Reverse(r.care.reverse_bits()))
+                });
+            match best {
+                Some(r) => c = r,
+                None => break,
+            }
+        }
+        res.retain(|k| !c.contains(k));
+        res.push(c);
+    }
+    res
+}
+
+// drops the cubes covered by the others and the don't-cares, largest
+// ones last
+fn irredundant(mut f: Vec<Cube>, dc: &[Cube]) -> Vec<Cube> {
+    f.sort_by_key(|c| std::cmp::Reverse(c.literals()));
+    let mut i = 0;
+    while i < f.len() {
+        let others = f
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .map(|(_, c)| *c)
+            .chain(dc.iter().cloned())
+            .collect::<Vec<Cube>>();
+        if covers(&others, &f[i]) {
+            f.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+    f
+}
+
+// shrinks every cube to the smallest one still covering what only it
+// covers, so that the next expansion can move it elsewhere
+fn reduce(mut f: Vec<Cube>, dc: &[Cube]) -> Vec<Cube> {
+    f.sort_by_key(|c| c.literals());
+    let mut i = 0;
+    while i < f.len() {
+        let c = f[i];
+        let others = f
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .map(|(_, c)| *c)
+            .chain(dc.iter().cloned())
+            .collect::<Vec<Cube>>();
+        let rest = complement(&cofactor(&others, &c));
+        match rest.split_first() {
+            None => {
+                f.remove(i);
+            }
+            Some((first, tail)) => {
+                let sc = tail.iter().fold(*first, |a, b| a.supercube(b));
+                f[i] = c.intersect(&sc).unwrap();
+                i += 1;
+            }
+        }
+    }
+    f
+}
+
+// a cover of `on`, free to include `dc`. Minterms in both are in `on`.
+pub fn espresso(on: &[Cube], dc: &[Cube]) -> Vec<Cube> {
+    let dc = intersect_covers(dc, &complement(on));
+    let all = on.iter().chain(&dc).cloned().collect::<Vec<Cube>>();
+    let off = complement(&all);
+    let mut f = irredundant(expand(absorb(on.to_vec()), &off), &dc);
+    loop {
+        let next = irredundant(expand(reduce(f.clone(), &dc), &off), &dc);
+        if cost(&next) >= cost(&f) {
+            break;
+        }
+        f = next;
+    }
+    // fewest literals first, then in order of the inputs
+    f.sort_by_key(|c| {
+        (
+            c.literals(),
+            std::cmp::Reverse(c.care.reverse_bits()),
+            c.val,
+        )
+    });
+    f
+}
+
+// in the format of `qm_simplify_one`, e.g. "!A.B+C"
+pub fn cover_to_expr(f: &[Cube], inps: &[&str]) -> String {
+    if f.is_empty() {
+        return "0".to_string();
+    }
+    f.iter()
+        .map(|c| {
+            if c.care == 0 {
+                return "1".to_string();
+            }
+            (0..inps.len())
+                .filter(|i| c.care >> i & 1 == 1)
+                .map(|i| format!("{}{}", if c.val >> i & 1 == 0 { "!" } else { "" }, inps[i]))
+                .collect::<Vec<String>>()
+                .join(".")
+        })
+        .collect::<Vec<String>>()
+        .join("+")
+}
+
+// the on-set and don't-care cubes of column `out`, input i being the
+// column `inps[i]`. Rows are read like `qm_simplify_one` does.
+pub fn table_cubes(
+    t: &Table<char>,
+    inps: &[&str],
+    out: &str,
+) -> Result<(Vec<Cube>, Vec<Cube>), String> {
+    if inps.len() > MAX_CUBE_INPUTS {
+        return Err(format!(
+            "{} inputs, at most {} can be minimized",
+            inps.len(),
+            MAX_CUBE_INPUTS
+        ));
+    }
+    let (mut on, mut dc) = (Vec::new(), Vec::new());
+    for i in 0..t.rows.len() {
+        let set = match *t.get_val_at(i, out) {
+            '1' => &mut on,
+            c if is_dont_care(c) => &mut dc,
+            _ => continue,
+        };
+        let chars = inps
+            .iter()
+            .map(|v| *t.get_val_at(i, v))
+            .collect::<Vec<char>>();
+        set.extend(Cube::from_chars(&chars));
+    }
+    Ok((on, dc))
+}
+
+pub fn espresso_simplify_one(t: &Table<char>, inps: &[&str], out: &str) -> Result<String, String> {
+    let (on, dc) = table_cubes(t, inps, out)?;
+    Ok(cover_to_expr(&espresso(&on, &dc), inps))
+}
+
+// (terms, literals) of a sum of products written like "!A.B+C"
+pub fn sop_cost(exp: &str) -> (usize, usize) {
+    if exp == "0" {
+        return (0, 0);
+    }
+    let terms = exp.split('+').collect::<Vec<&str>>();
+    let literals = terms
+        .iter()
+        .filter(|t| **t != "1")
+        .map(|t| t.split('.').count())
+        .sum();
+    (terms.len(), literals)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MinimizerReport {
+    pub inputs: usize,
+    pub espresso: String,
+    // None past MAX_QM_INPUTS inputs
+    pub qm: Option<String>,
+}
+
+impl fmt::Display for MinimizerReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let line = |f: &mut fmt::Formatter<'_>, name: &str, exp: &str| {
+            let (terms, literals) = sop_cost(exp);
+            writeln!(
+                f,
+                "{:<8} {:>5} terms {:>5} literals  {}",
+                name, terms, literals, exp
+            )
+        };
+        line(f, "espresso", &self.espresso)?;
+        match &self.qm {
+            Some(exp) => line(f, "qm", exp),
+            None => writeln!(f, "qm       skipped, {} inputs", self.inputs),
+        }
+    }
+}
+
+// minimizes column `out` of `t` with both minimizers
+pub fn compare_minimizers(
+    t: &Table<char>,
+    inps: &[&str],
+    out: &str,
+) -> Result<MinimizerReport, String> {
+    Ok(MinimizerReport {
+        inputs: inps.len(),
+        espresso: espresso_simplify_one(t, inps, out)?,
+        qm: (inps.len() <= MAX_QM_INPUTS)
+            .then(|| qm_simplify_one(t, &inps.to_vec(), out))
+            .transpose()?,
+    })
+}
+
+impl BCircuit {
+    // the on-set of the single bit component `id` over the inputs and
+    // states it depends on, sorted by name, built gate by gate from the
+    // circuit rather than from a truth table. Every gate is tabulated
+    // over its own inputs, so gates must not have more than
+    // MAX_GATE_INPUTS inputs, and must output 0 or 1 when they are.
+    pub fn output_cover(&self, id: ID) -> Result<(Vec<String>, Vec<Cube>), String> {
+        // the inputs and states the cone of `id` reads, and its gates
+        // inputs first
+        let mut leaves = Vec::new();
+        let mut order = Vec::new();
+        let mut stack = vec![(id, false)];
+        let mut seen = HashMap::<ID, bool>::new();
+        while let Some((cid, done)) = stack.pop() {
+            let c = match self.components.get(&cid) {
+                Some(c) => c.borrow(),
+                None => return Err(format!("No element with id_{}", cid)),
+            };
+            if done {
+                seen.insert(cid, true);
+                order.push(cid);
+                continue;
+            }
+            match seen.get(&cid) {
+                Some(true) => continue,
+                Some(false) => {
+                    return Err(format!("{} is on a combinational loop", self.describe(cid)))
+                }
+                None => {}
+            }
+            if c.width() != 1 {
+                return Err(format!("{} is not a single bit", self.describe(cid)));
+            }
+            if c.comp_type != CompType::Combinational {
+                seen.insert(cid, true);
+                leaves.push(cid);
+                continue;
+            }
+            let srcs = &c.input_pin_sources[1..];
+            if srcs.len() > MAX_GATE_INPUTS {
+                return Err(format!(
+                    "{} has more than {} inputs",
+                    self.describe(cid),
+                    MAX_GATE_INPUTS
+                ));
+            }
+            if srcs.contains(&NULL) {
+                return Err(format!(
+                    "{} is not completely connected",
+                    self.describe(cid)
+                ));
+            }
+            seen.insert(cid, false);
+            stack.push((cid, true));
+            stack.extend(srcs.iter().map(|s| (*s, false)));
+        }
+        if leaves.len() > MAX_CUBE_INPUTS {
+            return Err(format!(
+                "{} depends on more than {} inputs",
+                self.describe(id),
+                MAX_CUBE_INPUTS
+            ));
+        }
+        let name = |id: &ID| self.components[id].borrow().state_expr.clone();
+        leaves.sort_by_key(name);
+        let names = leaves.iter().map(name).collect::<Vec<String>>();
+
+        let mut cover = HashMap::<ID, Vec<Cube>>::new();
+        for (i, leaf) in leaves.iter().enumerate() {
+            cover.insert(*leaf, vec![Cube::literal(i, true)]);
+        }
+        for gid in order {
+            let g = self.components[&gid].borrow();
+            let srcs = &g.input_pin_sources[1..];
+            let ins = srcs.iter().map(|s| cover[s].clone()).collect::<Vec<_>>();
+            let outs = srcs
+                .iter()
+                .map(|s| complement(&cover[s]))
+                .collect::<Vec<_>>();
+            let eval = g.eval_fn();
+            let mut pins = (0..=srcs.len())
+                .map(|p| g.pin_value(p))
+                .collect::<Vec<Word>>();
+            let mut f = Vec::new();
+            for n in 0..1u32 << srcs.len() {
+                for (k, pin) in pins.iter_mut().skip(1).enumerate() {
+                    *pin = Word::from(n >> k & 1 == 1);
+                }
+                match eval(&pins, g.state).bit(0) {
+                    Signal::One => {}
+                    Signal::Zero => continue,
+                    _ => {
+                        return Err(format!(
+                            "{} doesn't output 0 or 1 for every input",
+                            self.describe(gid)
+                        ))
+                    }
+                }
+                let mut product = vec![Cube::UNIVERSE];
+                for k in 0..srcs.len() {
+                    let lit = if n >> k & 1 == 1 { &ins[k] } else { &outs[k] };
+                    product = intersect_covers(&product, lit);
+                }
+                f.extend(product);
+            }
+            cover.insert(gid, absorb(f));
+        }
+        Ok((names, cover.remove(&id).unwrap()))
+    }
+
+    // minimizes the single bit component `id` as a function of the
+    // inputs and states it depends on, see `output_cover`
+    pub fn minimize_output(&self, id: ID) -> Result<MinimizerReport, String> {
+        let (names, on) = self.output_cover(id)?;
+        let inps = names.iter().map(|n| n.as_str()).collect::<Vec<&str>>();
+        let qm = (inps.len() <= MAX_QM_INPUTS).then(|| {
+            // the cover as partially specified rows
+            let mut t = Table::<char>::new();
+            let mut cols = names.clone();
+            cols.push(String::new());
+            t.set_columns(cols).unwrap();
+            for c in &on {
+                let mut row = (0..inps.len())
+                    .map(|i| match (c.care >> i & 1, c.val >> i & 1) {
+                        (0, _) => '-',
+                        (_, 0) => '0',
+                        _ => '1',
+                    })
+                    .collect::<Vec<char>>();
+                row.push('1');
+                t.rows.push(row);
+            }
+            qm_simplify_one(&t, &inps, "")
+        });
+        let qm = qm.transpose()?;
+        Ok(MinimizerReport {
+            inputs: inps.len(),
+            espresso: cover_to_expr(&espresso(&on, &[]), &inps),
+            qm,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        compare_minimizers, complement, cost, covers, espresso, sop_cost, table_cubes, Cube,
+    };
+    use crate::{
+        circuit::BCircuit,
+        quine_mccluskey::tests::{seven_segment, SEVEN_SEGMENT_ON},
+        signal::Signal::Zero,
+    };
+
+    fn cube(s: &str) -> Cube {
+        Cube::from_chars(&s.chars().collect::<Vec<char>>()).unwrap()
+    }
+    fn eval(f: &[Cube], m: u64) -> bool {
+        f.iter().any(|c| (m ^ c.val) & c.care == 0)
+    }
+
+    #[test]
+    fn cube_ops() {
+        let f = [cube("1-0"), cube("01-")];
+        let nf = complement(&f);
+        for m in 0..8 {
+            assert_ne!(eval(&f, m), eval(&nf, m), "minterm {}", m);
+        }
+        assert!(complement(&[Cube::UNIVERSE]).is_empty());
+        assert_eq!(complement(&[]), [Cube::UNIVERSE]);
+        assert!(covers(&[cube("1-"), cube("0-")], &Cube::UNIVERSE));
+        assert!(!covers(&[cube("1-"), cube("01")], &Cube::UNIVERSE));
+        assert!(covers(&[cube("1-"), cube("01")], &cube("-1")));
+    }
+
+    #[test]
+    fn minimize_cubes() {
+        // cyclic function of 3 inputs, as minterms
+        let on = [0, 1, 2, 5, 6, 7].map(|m: u64| Cube { care: 7, val: m });
+        let f = espresso(&on, &[]);
+        assert_eq!(cost(&f), (3, 6));
+        for m in 0..8 {
+            assert_eq!(eval(&f, m), eval(&on, m));
+        }
+
+        // 40 inputs: x0.x1 + x0.!x1 + x2.x3.x39 + x2.x3.!x39.x5 = x0 + x2.x3.x39 + x2.x3.x5
+        let lit = |i: usize, p: bool| Cube::literal(i, p);
+        let prod = |ls: &[Cube]| {
+            ls.iter()
+                .fold(Cube::UNIVERSE, |a, b| a.intersect(b).unwrap())
+        };
+        let on = [
+            prod(&[lit(0, true), lit(1, true)]),
+            prod(&[lit(0, true), lit(1, false)]),
+            prod(&[lit(2, true), lit(3, true), lit(39, true)]),
+            prod(&[lit(2, true), lit(3, true), lit(39, false), lit(5, true)]),
+        ];
+        let f = espresso(&on, &[]);
+        assert_eq!(cost(&f), (3, 7));
+        assert!(f.contains(&lit(0, true)));
+
+        // a don't-care lets x0.x1 grow to x0
+        let f = espresso(&[cube("11")], &[cube("10")]);
+        assert_eq!(f, [cube("1-")]);
+        // the on-set wins over the don't-cares
+        let f = espresso(&[cube("11"), cube("01")], &[cube("-1"), cube("00")]);
+        assert_eq!(f, [cube("-1")]);
+    }
+
+    #[test]
+    fn compare_on_tables() {
+        // BCD to seven segment, 10 to 15 never occur
+        let inps = ["A", "B", "C", "D"];
+        let segs = ["a", "b", "c", "d", "e", "f", "g"];
+        let t = seven_segment(true);
+        for (s, seg) in segs.iter().enumerate() {
+            let report = compare_minimizers(&t, &inps, seg).unwrap();
+            let qm = report.qm.as_ref().unwrap();
+            // espresso is within a term of the exact minimum here
+            let (e, q) = (sop_cost(&report.espresso), sop_cost(qm));
+            assert!(e.0 <= q.0 + 1, "{}: {}", seg, report);
+            let (f, dc) = table_cubes(&t, &inps, seg).unwrap();
+            let f = espresso(&f, &dc);
+            for (m, outs) in SEVEN_SEGMENT_ON.iter().enumerate() {
+                let m = (0..4).fold(0, |a, i| a | (m as u64 >> i & 1) << (3 - i));
+                assert_eq!(eval(&f, m), outs.as_bytes()[s] == b'1', "{} at {}", seg, m);
+            }
+        }
+        let report = compare_minimizers(&t, &inps, "a").unwrap();
+        assert_eq!(sop_cost(&report.espresso), (4, 6));
+        let text = report.to_string();
+        assert!(text.starts_with("espresso     4 terms     6 literals  "));
+        assert!(text.contains("\nqm           4 terms     6 literals  "));
+        assert_eq!(sop_cost("0"), (0, 0));
+        assert_eq!(sop_cost("1"), (1, 0));
+        // one input past MAX_CUBE_INPUTS is an error, not a panic
+        let many = vec!["A"; 65];
+        assert!(table_cubes(&t, &many, "a").is_err());
+        assert!(compare_minimizers(&t, &many, "a").is_err());
+    }
+
+    #[test]
+    fn minimize_circuit() {
+        // F = A.B + A.!B + C.(D + E) + C.D over 20 inputs, 16 unused
+        let mut c = BCircuit::new();
+        let ins = (0..20)
+            .map(|i| c.add_input(&format!("I{:02}", i), Zero))
+            .collect::<Vec<_>>();
+        let [a, b, cc, d, e] = [ins[0], ins[1], ins[2], ins[3], ins[4]];
+        let nb = c.add_component("NOT", "").unwrap();
+        c.connect(nb, 1, b).unwrap();
+        let and = |c: &mut BCircuit, x, y| {
+            let g = c.add_component("AND", "").unwrap();
+            c.connect(g, 1, x).unwrap();
+            c.connect(g, 2, y).unwrap();
+            g
+        };
+        let ab = and(&mut c, a, b);
+        let anb = and(&mut c, a, nb);
+        let de = c.add_component("OR", "").unwrap();
+        c.connect(de, 1, d).unwrap();
+        c.connect(de, 2, e).unwrap();
+        let cde = and(&mut c, cc, de);
+        let cd = and(&mut c, cc, d);
+        let f = c.add_component_with_inputs("OR", "F", 4).unwrap();
+        for (pin, g) in [ab, anb, cde, cd].iter().enumerate() {
+            c.connect(f, pin + 1, *g).unwrap();
+        }
+
+        let (names, on) = c.output_cover(f).unwrap();
+        assert_eq!(names, ["I00", "I01", "I02", "I03", "I04"]);
+        for m in 0..32u64 {
+            let bit = |i: usize| m >> i & 1 == 1;
+            let expected = bit(0) || bit(2) && (bit(3) || bit(4));
+            assert_eq!(eval(&on, m), expected);
+        }
+        let report = c.minimize_output(f).unwrap();
+        assert_eq!(report.espresso, "I00+I02.I03+I02.I04");
+        assert_eq!(sop_cost(report.qm.as_ref().unwrap()), (3, 5));
+
+        // a gate that can output X can't be tabulated
+        let tri = c.add_component("TRI", "").unwrap();
+        c.connect(tri, 1, a).unwrap();
+        c.connect(tri, 2, b).unwrap();
+        assert!(c.output_cover(tri).is_err());
+        let bus = c.add_input("bus", crate::signal::Word::from_u64(0, 2));
+        assert!(c.output_cover(bus).is_err());
+    }
+}
@@ -0,0 +1,267 @@
+// Finite state machines.
+//
+// `BCircuit::extract_fsm` explores every state reachable from the
+// reset state under every input combination (see `state_table`) and
+// returns the explicit machine: states are the values of the clocked
+// components, transitions are labelled with the inputs that take them
+// and the outputs seen before the clock, i.e. a Mealy machine.
+//
+// `Fsm::to_dot` writes it as a Graphviz digraph. States and labels list
+// bits most significant first, in the order of `state_names`,
+// `input_names` and `output_names`, e.g. `01/1`. Dead-end states, which
+// no input takes anywhere else, are drawn in red.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{circuit::BCircuit, signal::Word, table::counter_row};
+
+// state bits past which `unreachable_states` won't enumerate encodings
+pub const MAX_FSM_STATE_BITS: u32 = 16;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transition {
+    pub from: usize,
+    pub to: usize,
+    pub inputs: Vec<Word>,
+    pub outputs: Vec<Word>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fsm {
+    pub state_names: Vec<String>,
+    pub input_names: Vec<String>,
+    pub output_names: Vec<String>,
+    // the reset state first, then in order of discovery
+    pub states: Vec<Vec<Word>>,
+    pub transitions: Vec<Transition>,
+}
+
+fn bits(ws: &[Word]) -> String {
+    ws.iter()
+        .flat_map(|w| w.bits().into_iter().rev().map(|b| b.to_char()))
+        .collect()
+}
+
+impl Fsm {
+    pub fn state_index(&self, state: &[Word]) -> Option<usize> {
+        self.states.iter().position(|s| s == state)
+    }
+    // the transition taken from state `from` on `inputs`
+    pub fn transition(&self, from: usize, inputs: &[Word]) -> Option<&Transition> {
+        self.transitions
+            .iter()
+            .find(|t| t.from == from && t.inputs == inputs)
+    }
+    // reachable states with no transition to another state
+    pub fn dead_ends(&self) -> Vec<usize> {
+        (0..self.states.len())
+            .filter(|s| self.transitions.iter().all(|t| t.from != *s || t.to == *s))
+            .collect()
+    }
+    // the state encodings the machine never reaches
+    pub fn unreachable_states(&self) -> Result<Vec<Vec<Word>>, String> {
+        let widths = self.states[0]
+            .iter()
+            .map(|w| w.width())
+            .collect::<Vec<u8>>();
+        let total = widths.iter().map(|w| *w as u32).sum::<u32>();
+        if total > MAX_FSM_STATE_BITS {
+            return Err(format!(
+                "{} state bits, at most {} can be enumerated",
+                total, MAX_FSM_STATE_BITS
+            ));
+        }
+        Ok((0..1u64 << total)
+            .map(|n| counter_row(n, &widths))
+            .filter(|s| self.state_index(s).is_none())
+            .collect())
+    }
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph fsm {\n    rankdir=LR;\n");
+        out.push_str(&format!(
+            "    label=\"state: {}, inputs / outputs: {} / {}\";\n",
+            self.state_names.join(" "),
+            self.input_names.join(" "),
+            self.output_names.join(" ")
+        ));
+        out.push_str("    init [shape=point];\n");
+        let dead = self.dead_ends();
+        for (i, s) in self.states.iter().enumerate() {
+            let color = if dead.contains(&i) { ", color=red" } else { "" };
+            out.push_str(&format!(
+                "    s{} [label=\"{}\", shape=circle{}];\n",
+                i,
+                bits(s),
+                color
+            ));
+        }
+        out.push_str("    init -> s0;\n");
+        // one edge per pair of states, listing all its labels
+        let mut edges = BTreeMap::<(usize, usize), Vec<String>>::new();
+        for t in &self.transitions {
+            edges.entry((t.from, t.to)).or_default().push(format!(
+                "{}/{}",
+                bits(&t.inputs),
+                bits(&t.outputs)
+            ));
+        }
+        for ((from, to), labels) in edges {
+            out.push_str(&format!(
+                "    s{} -> s{} [label=\"{}\"];\n",
+                from,
+                to,
+                labels.join("\\n")
+            ));
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+impl BCircuit {
+    pub fn extract_fsm(&mut self) -> Result<Fsm, String> {
+        let (t, n) = self.state_table_from(true, None)?;
+        let cols = t.columns();
+        let m = cols.len() - 2 * n - self.outputs.len();
+        let names = |r: std::ops::Range<usize>| cols[r].to_vec();
+        let state_names = names(0..n)
+            .into_iter()
+            .map(|c| c.trim_end_matches("(t)").to_string())
+            .collect();
+        let mut fsm = Fsm {
+            state_names,
+            input_names: names(n..n + m),
+            output_names: names(2 * n + m..cols.len()),
+            states: Vec::new(),
+            transitions: Vec::new(),
+        };
+        let mut index = HashMap::<Vec<Word>, usize>::new();
+        let mut state = |fsm: &mut Fsm, s: &[Word]| {
+            *index.entry(s.to_vec()).or_insert_with(|| {
+                fsm.states.push(s.to_vec());
+                fsm.states.len() - 1
+            })
+        };
+        for row in &t.rows {
+            let from = state(&mut fsm, &row[..n]);
+            let to = state(&mut fsm, &row[n + m..2 * n + m]);
+            fsm.transitions.push(Transition {
+                from,
+                to,
+                inputs: row[n..n + m].to_vec(),
+                outputs: row[2 * n + m..].to_vec(),
+            });
+        }
+        Ok(fsm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        circuit::BCircuit,
+        signal::{
+            Signal::{One, Zero},
+            Word,
+        },
+        types::CLOCK_PIN,
+    };
+
+    // detects "11" on X, A staying set once it has
+    fn detector() -> BCircuit {
+        let mut c = BCircuit::new();
+        let clk = c.add_input("clk", Zero);
+        c.clock(clk);
+        let x = c.add_input("X", Zero);
+        let a = c.add_component("D", "A").unwrap();
+        let b = c.add_component("D", "B").unwrap();
+        // A+ = X.B + A, B+ = X
+        let and = c.add_component("AND", "").unwrap();
+        c.connect(and, 1, x).unwrap();
+        c.connect(and, 2, b).unwrap();
+        let or = c.add_component("OR", "").unwrap();
+        c.connect(or, 1, and).unwrap();
+        c.connect(or, 2, a).unwrap();
+        for (ff, d) in [(a, or), (b, x)] {
+            c.connect(ff, CLOCK_PIN, clk).unwrap();
+            c.connect(ff, 1, d).unwrap();
+        }
+        let z = c.add_component("AND", "Z").unwrap();
+        c.connect(z, 1, x).unwrap();
+        c.connect(z, 2, b).unwrap();
+        c.track_output(z);
+        c
+    }
+
+    #[test]
+    fn extract() {
+        let mut c = detector();
+        c.power_on().unwrap();
+        let fsm = c.extract_fsm().unwrap();
+        assert_eq!(fsm.state_names, ["A", "B"]);
+        assert_eq!(fsm.input_names, ["X"]);
+        assert_eq!(fsm.output_names, ["Z"]);
+        let state = |a: bool, b: bool| vec![Word::from(a), Word::from(b)];
+        assert_eq!(
+            fsm.states,
+            [
+                state(false, false),
+                state(false, true),
+                state(true, true),
+                state(true, false)
+            ]
+        );
+        assert_eq!(fsm.transitions.len(), 8);
+        let t = fsm.transition(1, &[One.into()]).unwrap();
+        assert_eq!((t.to, t.outputs.clone()), (2, vec![One.into()]));
+        assert_eq!(fsm.dead_ends(), Vec::<usize>::new());
+        assert!(fsm.unreachable_states().unwrap().is_empty());
+
+        let dot = fsm.to_dot();
+        assert!(dot.starts_with("digraph fsm {\n"));
+        assert!(dot.contains("    s0 [label=\"00\", shape=circle];\n"));
+        assert!(dot.contains("    init -> s0;\n"));
+        assert!(dot.contains("    s1 -> s2 [label=\"1/1\"];\n"));
+        assert!(dot.contains("    s0 -> s0 [label=\"0/0\"];\n"));
+        assert!(dot.contains("label=\"state: A B, inputs / outputs: X / Z\""));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn dead_ends_and_unreachable() {
+        // a 2 bit counter stuck at 2: A+ = A + B, B+ = !A.!B
+        let mut c = BCircuit::new();
+        let clk = c.add_input("clk", Zero);
+        c.clock(clk);
+        let a = c.add_component("D", "A").unwrap();
+        let b = c.add_component("D", "B").unwrap();
+        let or = c.add_component("OR", "").unwrap();
+        c.connect(or, 1, a).unwrap();
+        c.connect(or, 2, b).unwrap();
+        let nor = c.add_component("NOT", "").unwrap();
+        c.connect(nor, 1, or).unwrap();
+        for (ff, d) in [(a, or), (b, nor)] {
+            c.connect(ff, CLOCK_PIN, clk).unwrap();
+            c.connect(ff, 1, d).unwrap();
+        }
+        // powered on in another state, extraction still starts at 00
+        c.power_on().unwrap();
+        c.reset_states().unwrap();
+        c.pulse_clock().unwrap();
+        c.pulse_clock().unwrap();
+        let fsm = c.extract_fsm().unwrap();
+        assert_eq!(fsm.states.len(), 3);
+        assert_eq!(fsm.input_names, Vec::<String>::new());
+        assert_eq!(fsm.dead_ends(), [2]);
+        assert_eq!(
+            fsm.unreachable_states().unwrap(),
+            [vec![Word::from(One), Word::from(One)]]
+        );
+        assert!(fsm
+            .to_dot()
+            .contains("s2 [label=\"10\", shape=circle, color=red]"));
+        // the circuit is left as it was
+        assert_eq!(c.state(a).unwrap(), One);
+        assert_eq!(c.state(b).unwrap(), Zero);
+    }
+}
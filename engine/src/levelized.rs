@@ -0,0 +1,339 @@
+// Compiled simulation backend.
+//
+// `BCircuit::levelize` flattens a combinational or synchronous circuit
+// into an array of instructions in level order (see `compile`), each
+// reading its pins from and writing its output to a dense value vector.
+// Evaluating the array once settles all combinational logic, with no
+// queue and no per component lookups, which suits large circuits.
+//
+// Clocked components must all be flip-flops triggered by the rising
+// edge of a circuit clock, with no asynchronous pins connected. A `tick`
+// pulses every clock at once, like `pulse_clock`: the next states are
+// computed from the current values and committed together, then the
+// combinational logic is re-evaluated.
+//
+// RAMs start from a copy of their contents, so writes aren't seen by
+// the circuit.
+
+use std::{collections::HashMap, ops::Range};
+
+use crate::{
+    circuit::BCircuit,
+    clock_manager::Trigger,
+    signal::Word,
+    types::{BinaryLogicReducer, CompType, PatternReducer, CLOCK_PIN, ID, NULL},
+};
+
+pub(crate) struct Instr {
+    pub(crate) slot: usize,
+    pub(crate) eval: BinaryLogicReducer,
+    pub(crate) pattern_eval: Option<PatternReducer>,
+    pub(crate) pins: Range<usize>,
+}
+
+pub(crate) struct PinRef {
+    // slot of the emitter, None for an unconnected pin
+    pub(crate) src: Option<usize>,
+    // Some(lsb) for a pin reading a slice of its emitter
+    pub(crate) slice: Option<u8>,
+    pub(crate) width: u8,
+}
+
+pub struct LevelizedCircuit {
+    pub(crate) slots: HashMap<ID, usize>,
+    pub(crate) values: Vec<Word>,
+    // combinational components in level order
+    pub(crate) comb: Vec<Instr>,
+    // clocked components, evaluated on ticks
+    pub(crate) seq: Vec<Instr>,
+    pub(crate) pins: Vec<PinRef>,
+    // ids that may be set from outside, i.e. inputs and clocked components
+    settable: Vec<bool>,
+    scratch: Vec<Word>,
+}
+
+impl LevelizedCircuit {
+    pub fn state(&self, id: ID) -> Option<Word> {
+        self.slots.get(&id).map(|s| self.values[*s])
+    }
+    // sets an input or the state of a clocked component. Call `eval` or
+    // `tick` to propagate it.
+    pub fn set(&mut self, id: ID, val: impl Into<Word>) -> Result<(), String> {
+        let val = val.into();
+        let slot = self.settable_slot(id, val)?;
+        self.values[slot] = val;
+        Ok(())
+    }
+    // slot of `id` if it can be set to `val`
+    pub(crate) fn settable_slot(&self, id: ID, val: Word) -> Result<usize, String> {
+        let slot = match self.slots.get(&id) {
+            Some(s) if self.settable[*s] => *s,
+            Some(_) => return Err(format!("id_{} is not an input or a state", id)),
+            None => return Err(format!("No element with id_{}", id)),
+        };
+        if val.width() != self.values[slot].width() {
+            return Err(format!(
+                "id_{} is {} bit(s) wide, can't set it to the {} bit value {}",
+                id,
+                self.values[slot].width(),
+                val.width(),
+                val
+            ));
+        }
+        Ok(slot)
+    }
+    // settles the combinational logic
+    pub fn eval(&mut self) {
+        for ins in &self.comb {
+            let v = run(ins, &self.pins, &self.values, &mut self.scratch);
+            self.values[ins.slot] = v;
+        }
+    }
+    // a rising edge of every circuit clock
+    pub fn tick(&mut self) {
+        let next = self
+            .seq
+            .iter()
+            .map(|ins| run(ins, &self.pins, &self.values, &mut self.scratch))
+            .collect::<Vec<Word>>();
+        for (ins, v) in self.seq.iter().zip(next) {
+            self.values[ins.slot] = v;
+        }
+        self.eval();
+    }
+}
+
+fn run(ins: &Instr, pins: &[PinRef], values: &[Word], scratch: &mut Vec<Word>) -> Word {
+    scratch.clear();
+    for p in &pins[ins.pins.clone()] {
+        scratch.push(match (p.src, p.slice) {
+            (None, _) => Word::hiz(p.width),
+            (Some(s), Some(lsb)) => values[s].slice(lsb, p.width),
+            (Some(s), None) => values[s],
+        });
+    }
+    (ins.eval)(scratch, values[ins.slot])
+}
+
+impl BCircuit {
+    // compiles the circuit for `LevelizedCircuit`, starting from the
+    // current states. Fails on combinational loops and on clocked
+    // components that are not clocked by a circuit clock.
+    pub fn levelize(&self) -> Result<LevelizedCircuit, String> {
+        let report = self.compile();
+        if let Some(lp) = report.comb_loops.first() {
+            let members = lp.iter().map(|id| self.describe(*id));
+            return Err(format!(
+                "Can't levelize the combinational loop through {}",
+                members.collect::<Vec<String>>().join(", ")
+            ));
+        }
+        let order = report.levels.concat();
+        let slots = order
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (*id, i))
+            .collect::<HashMap<ID, usize>>();
+        let mut lc = LevelizedCircuit {
+            values: Vec::with_capacity(order.len()),
+            comb: Vec::new(),
+            seq: Vec::new(),
+            pins: Vec::new(),
+            settable: Vec::with_capacity(order.len()),
+            scratch: Vec::new(),
+            slots,
+        };
+        for id in &order {
+            let c = self.components[id].borrow();
+            lc.values.push(c.state);
+            lc.settable.push(c.has_independent_state());
+            if c.comp_type == CompType::Input {
+                continue;
+            }
+            if let Some(cm) = &c.clock_manager {
+                if !self.is_clock(c.input_pin_sources[CLOCK_PIN]) {
+                    return Err(format!(
+                        "{} is not clocked by a circuit clock",
+                        self.describe(*id)
+                    ));
+                }
+                // a tick is a rising edge, with no asynchronous events
+                if cm.trigger() != Trigger::RisingEdge {
+                    return Err(format!(
+                        "{} is not triggered by the rising clock edge",
+                        self.describe(*id)
+                    ));
+                }
+                if let Some((pin, _)) = c
+                    .async_pins()
+                    .iter()
+                    .find(|(p, _)| c.input_pin_sources[*p] != NULL)
+                {
+                    return Err(format!(
+                        "Can't levelize the asynchronous pin_{} of {}",
+                        pin,
+                        self.describe(*id)
+                    ));
+                }
+            }
+            let start = lc.pins.len();
+            for pin in 0..c.num_inputs() {
+                let src = c.input_pin_sources[pin];
+                lc.pins.push(PinRef {
+                    src: if src == NULL {
+                        None
+                    } else {
+                        Some(lc.slots[&src])
+                    },
+                    slice: c.pin_slice(pin),
+                    width: c.pin_width(pin),
+                });
+            }
+            let ins = Instr {
+                slot: lc.slots[id],
+                // memories aren't shared with the circuit
+                eval: c.detached_eval_fn(),
+                pattern_eval: c.pattern_eval_fn(),
+                pins: start..lc.pins.len(),
+            };
+            if c.clock_manager.is_some() {
+                lc.seq.push(ins);
+            } else {
+                lc.comb.push(ins);
+            }
+        }
+        lc.eval();
+        Ok(lc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        circuit::BCircuit,
+        signal::{
+            Signal::{One, Zero},
+            Word,
+        },
+        types::{CLOCK_PIN, ID},
+    };
+
+    // n bit ripple carry adder, returns (a bits, b bits, sum bits + carry)
+    fn adder(c: &mut BCircuit, n: usize) -> (Vec<ID>, Vec<ID>, Vec<ID>) {
+        let (mut a, mut b, mut s) = (Vec::new(), Vec::new(), Vec::new());
+        let mut carry = c.add_input("cin", Zero);
+        for i in 0..n {
+            let ai = c.add_input(&format!("a{}", i), Zero);
+            let bi = c.add_input(&format!("b{}", i), Zero);
+            let x = c.add_component("XOR", "").unwrap();
+            let sum = c.add_component("XOR", &format!("s{}", i)).unwrap();
+            let g = c.add_component("AND", "").unwrap();
+            let p = c.add_component("AND", "").unwrap();
+            let co = c.add_component("OR", "").unwrap();
+            c.connect(x, 1, ai).unwrap();
+            c.connect(x, 2, bi).unwrap();
+            c.connect(sum, 1, x).unwrap();
+            c.connect(sum, 2, carry).unwrap();
+            c.connect(g, 1, ai).unwrap();
+            c.connect(g, 2, bi).unwrap();
+            c.connect(p, 1, x).unwrap();
+            c.connect(p, 2, carry).unwrap();
+            c.connect(co, 1, g).unwrap();
+            c.connect(co, 2, p).unwrap();
+            c.track_output(sum);
+            a.push(ai);
+            b.push(bi);
+            s.push(sum);
+            carry = co;
+        }
+        s.push(carry);
+        (a, b, s)
+    }
+
+    #[test]
+    fn matches_interpreter_on_adder() {
+        let mut c = BCircuit::new();
+        let (a, b, s) = adder(&mut c, 8);
+        c.power_on().unwrap();
+        let mut lc = c.levelize().unwrap();
+
+        let mut rng: u64 = 0x2545F4914F6CDD1D;
+        for _ in 0..200 {
+            rng ^= rng << 13;
+            rng ^= rng >> 7;
+            rng ^= rng << 17;
+            for (i, id) in a.iter().chain(&b).enumerate() {
+                let bit = Word::from(rng >> i & 1 == 1);
+                c.set_component_state(*id, bit).unwrap();
+                lc.set(*id, bit).unwrap();
+            }
+            lc.eval();
+            let sum = (rng & 0xFF) + (rng >> 8 & 0xFF);
+            for (i, id) in s.iter().enumerate() {
+                assert_eq!(lc.state(*id), c.state(*id));
+                assert_eq!(lc.state(*id), Some(Word::from(sum >> i & 1 == 1)));
+            }
+        }
+        assert!(lc.set(s[0], One).is_err());
+        assert!(lc.set(a[0], Word::from_u64(0, 2)).is_err());
+    }
+
+    #[test]
+    fn matches_interpreter_on_counter() {
+        // 3 bit synchronous counter
+        let mut c = BCircuit::new();
+        let one = c.add_input("1", One);
+        let clk = c.add_input("clk", Zero);
+        let q = (0..3)
+            .map(|i| c.add_component("JK", &format!("Q{}", i)).unwrap())
+            .collect::<Vec<ID>>();
+        let t1 = c.add_component("BFR", "").unwrap();
+        let t2 = c.add_component("AND", "").unwrap();
+        c.connect(t1, 1, q[0]).unwrap();
+        c.connect(t2, 1, q[0]).unwrap();
+        c.connect(t2, 2, q[1]).unwrap();
+        for (ff, t) in q.iter().zip([one, t1, t2]) {
+            c.connect(*ff, CLOCK_PIN, clk).unwrap();
+            c.connect(*ff, 1, t).unwrap();
+            c.connect(*ff, 2, t).unwrap();
+        }
+        c.clock(clk);
+        c.power_on().unwrap();
+        c.reset_states().unwrap();
+        let mut lc = c.levelize().unwrap();
+
+        for n in 1..=10 {
+            c.pulse_clock().unwrap();
+            lc.tick();
+            let count = q
+                .iter()
+                .enumerate()
+                .map(|(i, id)| (lc.state(*id).unwrap().to_u64().unwrap()) << i)
+                .sum::<u64>();
+            assert_eq!(count, n % 8);
+            for id in &q {
+                assert_eq!(lc.state(*id), c.state(*id));
+            }
+        }
+    }
+
+    #[test]
+    fn rejects() {
+        let mut c = BCircuit::new();
+        let q = c.add_component("NAND", "q").unwrap();
+        let nq = c.add_component("NAND", "nq").unwrap();
+        c.connect(q, 2, nq).unwrap();
+        c.connect(nq, 1, q).unwrap();
+        assert!(c.levelize().err().unwrap().contains("loop"));
+
+        // a ripple counter stage is clocked by another flip-flop
+        let mut c = BCircuit::new();
+        let clk = c.add_input("clk", Zero);
+        let a = c.add_component("JK", "A").unwrap();
+        let b = c.add_component("JK", "B").unwrap();
+        c.connect(a, CLOCK_PIN, clk).unwrap();
+        c.connect(b, CLOCK_PIN, a).unwrap();
+        c.clock(clk);
+        assert!(c.levelize().err().unwrap().contains("id_3 JK (B)"));
+    }
+}
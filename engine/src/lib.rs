@@ -1,14 +1,28 @@
+pub mod bus;
 pub mod circuit;
 pub mod clock_manager;
+pub mod clocks;
+pub mod compile;
 pub mod components;
+pub mod espresso;
+pub mod fsm;
+pub mod levelized;
+pub mod memory;
+pub mod netlist;
+pub mod pattern;
 pub mod quine_mccluskey;
+pub mod signal;
+pub mod state_table;
+pub mod subcircuit;
 pub mod table;
+pub mod timing;
 pub mod types;
 pub mod utils;
+pub mod vectors;
+pub mod waveform;
 mod bootstrap;
 
 // todo:
-// - save/load from file
 // - higher order components - enc/dec/mux etc
 
 // #[cfg(test)]
@@ -0,0 +1,492 @@
+// Memory components.
+//
+// A memory instance stores 2^addr_width words as wide as the component
+// and outputs the word at its ADDR pin:
+//
+// - ROM: combinational, pin 1 ADDR.
+// - RAM: synchronous, clocked by the rising edge. Pins ADDR, DIN, WE:
+//   on an edge, DIN is written to ADDR if WE is high, then ADDR is read.
+// - ARAM: asynchronous, combinational. Same pins as RAM, DIN is written
+//   to ADDR for as long as WE is high.
+//
+// The contents live outside the component state, which is the data
+// output, and can be loaded, inspected and dumped during simulation.
+// Text contents follow Verilog's $readmemh / $readmemb: whitespace
+// separated words, `@<hex address>` to move to another address and `//`
+// comments. `x` and `z` digits give unknown and floating bits.
+
+use std::{cell::RefCell, fmt::Write, fs, rc::Rc};
+
+use crate::{
+    circuit::BCircuit,
+    components::update_component_state,
+    signal::{Signal, Word},
+    types::{CompType, ID, PIN},
+};
+
+pub const DEFAULT_ADDR_WIDTH: u8 = 4;
+pub const MAX_ADDR_WIDTH: u8 = 20;
+
+// reads, and possibly writes, a memory given the pin values of its
+// component. Returns the output.
+pub type MemoryAccess = fn(&mut Memory, &[Word]) -> Word;
+
+// the memory part of a component definition
+#[derive(Clone, Copy)]
+pub struct MemoryDef {
+    pub access: MemoryAccess,
+    pub addr_pin: PIN,
+    pub read_only: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MemoryFormat {
+    Hex,
+    Bin,
+}
+
+#[derive(Clone, Debug)]
+pub struct Memory {
+    addr_width: u8,
+    words: Vec<Word>,
+    read_only: bool,
+}
+
+impl Memory {
+    // contents power on cleared
+    pub fn new(addr_width: u8, data_width: u8, read_only: bool) -> Memory {
+        Memory {
+            addr_width,
+            words: vec![Word::from_u64(0, data_width); 1 << addr_width],
+            read_only,
+        }
+    }
+    pub fn addr_width(&self) -> u8 {
+        self.addr_width
+    }
+    pub fn data_width(&self) -> u8 {
+        self.words[0].width()
+    }
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+    pub fn words(&self) -> &[Word] {
+        &self.words
+    }
+    // unknown for an address that is not fully known
+    pub fn read(&self, addr: Word) -> Word {
+        match addr.to_u64() {
+            Some(a) => self.words[a as usize],
+            None => Word::unknown(self.data_width()),
+        }
+    }
+    // writes `data` at `addr` if `we` is high. If `we` or some address
+    // bits are unknown, the words that may be written keep only the
+    // bits that agree with `data`.
+    pub fn write(&mut self, addr: Word, we: Signal, data: Word) {
+        let we = we.read();
+        if we == Signal::Zero {
+            return;
+        }
+        let data = data.read();
+        if let Some(a) = addr.to_u64() {
+            let w = &mut self.words[a as usize];
+            *w = Word::mux(we, *w, data);
+            return;
+        }
+        let (ones, zeros) = (addr.ones(), addr.zeros());
+        for (a, w) in self.words.iter_mut().enumerate() {
+            let a = a as u64;
+            if a & ones == ones && a & zeros == 0 {
+                *w = Word::mux(Signal::X, *w, data);
+            }
+        }
+    }
+    // stores `vals` from address `start` on
+    pub fn load(&mut self, start: usize, vals: &[Word]) -> Result<(), String> {
+        if start + vals.len() > self.words.len() {
+            return Err(format!(
+                "{} word(s) from address {} don't fit in {} words",
+                vals.len(),
+                start,
+                self.words.len()
+            ));
+        }
+        for (i, v) in vals.iter().enumerate() {
+            if v.width() != self.data_width() {
+                return Err(format!(
+                    "Can't store the {} bit value {} in {} bit words",
+                    v.width(),
+                    v,
+                    self.data_width()
+                ));
+            }
+            self.words[start + i] = *v;
+        }
+        Ok(())
+    }
+    // loads text contents, see the top of this file
+    pub fn load_text(&mut self, text: &str, fmt: MemoryFormat) -> Result<(), String> {
+        let mut at = 0;
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split("//").next().unwrap();
+            for tok in line.split_whitespace() {
+                let res = match tok.strip_prefix('@') {
+                    Some(a) => usize::from_str_radix(a, 16)
+                        .map_err(|_| format!("bad address {}", tok))
+                        .map(|a| at = a),
+                    None => parse_word(tok, fmt, self.data_width())
+                        .and_then(|w| self.load(at, &[w]))
+                        .map(|_| at += 1),
+                };
+                res.map_err(|e| format!("line {}: {}", i + 1, e))?;
+            }
+        }
+        Ok(())
+    }
+    // one word per line, readable by `load_text`
+    pub fn dump(&self, fmt: MemoryFormat) -> String {
+        let mut out = String::new();
+        for w in &self.words {
+            writeln!(out, "{}", format_word(*w, fmt)).unwrap();
+        }
+        out
+    }
+}
+
+// `tok` as a word of `width` bits, most significant digit first
+fn parse_word(tok: &str, fmt: MemoryFormat, width: u8) -> Result<Word, String> {
+    let bits_per_digit = match fmt {
+        MemoryFormat::Hex => 4,
+        MemoryFormat::Bin => 1,
+    };
+    let mut bits = Vec::new();
+    for c in tok.chars().rev().filter(|c| *c != '_') {
+        let digit = match c {
+            'x' | 'X' => [Signal::X; 4],
+            'z' | 'Z' => [Signal::Z; 4],
+            _ => match c.to_digit(1 << bits_per_digit) {
+                Some(d) => [0, 1, 2, 3].map(|i| Signal::from(d >> i & 1 == 1)),
+                None => return Err(format!("bad word {}", tok)),
+            },
+        };
+        bits.extend_from_slice(&digit[..bits_per_digit]);
+    }
+    if bits.is_empty()
+        || bits[(width as usize).min(bits.len())..]
+            .iter()
+            .any(|b| *b != Signal::Zero)
+    {
+        return Err(format!("{} is not a {} bit word", tok, width));
+    }
+    bits.resize(width as usize, Signal::Zero);
+    Ok(Word::from_bits(&bits))
+}
+
+fn format_word(w: Word, fmt: MemoryFormat) -> String {
+    match fmt {
+        MemoryFormat::Bin => w.bits().iter().rev().map(|b| b.to_char()).collect(),
+        MemoryFormat::Hex => {
+            let bits = w.bits();
+            bits.chunks(4)
+                .rev()
+                .map(|d| {
+                    if d.iter().all(|b| *b == Signal::Z) {
+                        'z'
+                    } else if d.iter().any(|b| !b.is_known()) {
+                        'x'
+                    } else {
+                        let v = d
+                            .iter()
+                            .rev()
+                            .fold(0, |v, b| v << 1 | (*b == Signal::One) as u32);
+                        char::from_digit(v, 16).unwrap()
+                    }
+                })
+                .collect()
+        }
+    }
+}
+
+// access functions of the built-in memories
+pub(crate) fn rom_access(m: &mut Memory, v: &[Word]) -> Word {
+    m.read(v[1])
+}
+
+pub(crate) fn ram_access(m: &mut Memory, v: &[Word]) -> Word {
+    m.write(v[1], v[3].bit(0), v[2]);
+    m.read(v[1])
+}
+
+impl BCircuit {
+    // adds a memory component, e.g. a RAM, with 2^addr_width words of
+    // data_width bits
+    pub fn add_memory(
+        &mut self,
+        typ: &str,
+        label: &str,
+        addr_width: u8,
+        data_width: u8,
+    ) -> Result<ID, String> {
+        if addr_width == 0 || addr_width > MAX_ADDR_WIDTH {
+            return Err(format!(
+                "Address width must be between 1 and {}, got {}",
+                MAX_ADDR_WIDTH, addr_width
+            ));
+        }
+        let id = self.add_component_with_width(typ, label, data_width)?;
+        let mut c = self.components.get(&id).unwrap().borrow_mut();
+        if c.memory().is_none() {
+            drop(c);
+            self.components.remove(&id);
+            return Err(format!("{} is not a memory", typ));
+        }
+        c.resize_memory(addr_width);
+        Ok(id)
+    }
+    // adds a ROM holding `contents`, with as many address bits as they need
+    pub fn add_rom(&mut self, label: &str, data_width: u8, contents: &[u64]) -> Result<ID, String> {
+        let addr_width = (usize::BITS - contents.len().saturating_sub(1).leading_zeros()) as u8;
+        let id = self.add_memory("ROM", label, addr_width.max(1), data_width)?;
+        self.load_memory(id, 0, contents)?;
+        Ok(id)
+    }
+    // stores `contents` from address `start` on
+    pub fn load_memory(&mut self, id: ID, start: usize, contents: &[u64]) -> Result<(), String> {
+        let width = self.memory_of(id)?.borrow().data_width();
+        let vals = contents
+            .iter()
+            .map(|v| {
+                if width < 64 && v >> width != 0 {
+                    Err(format!("{:#x} is not a {} bit word", v, width))
+                } else {
+                    Ok(Word::from_u64(*v, width))
+                }
+            })
+            .collect::<Result<Vec<Word>, String>>()?;
+        self.memory_of(id)?.borrow_mut().load(start, &vals)?;
+        self.refresh_memory(id)
+    }
+    // loads text contents in the $readmemh / $readmemb style
+    pub fn load_memory_text(
+        &mut self,
+        id: ID,
+        text: &str,
+        fmt: MemoryFormat,
+    ) -> Result<(), String> {
+        self.memory_of(id)?.borrow_mut().load_text(text, fmt)?;
+        self.refresh_memory(id)
+    }
+    pub fn load_memory_file(
+        &mut self,
+        id: ID,
+        path: &str,
+        fmt: MemoryFormat,
+    ) -> Result<(), String> {
+        let text =
+            fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+        self.load_memory_text(id, &text, fmt)
+            .map_err(|e| format!("{}: {}", path, e))
+    }
+    pub fn read_memory(&self, id: ID, addr: usize) -> Result<Word, String> {
+        let m = self.memory_of(id)?;
+        let m = m.borrow();
+        m.words()
+            .get(addr)
+            .cloned()
+            .ok_or(format!("Address {} is out of range for id_{}", addr, id))
+    }
+    pub fn write_memory(
+        &mut self,
+        id: ID,
+        addr: usize,
+        val: impl Into<Word>,
+    ) -> Result<(), String> {
+        self.memory_of(id)?.borrow_mut().load(addr, &[val.into()])?;
+        self.refresh_memory(id)
+    }
+    // a copy of the whole contents
+    pub fn memory_contents(&self, id: ID) -> Result<Vec<Word>, String> {
+        Ok(self.memory_of(id)?.borrow().words().to_vec())
+    }
+    pub fn dump_memory(&self, id: ID, fmt: MemoryFormat) -> Result<String, String> {
+        Ok(self.memory_of(id)?.borrow().dump(fmt))
+    }
+    pub fn save_memory(&self, id: ID, path: &str, fmt: MemoryFormat) -> Result<(), String> {
+        let text = self.dump_memory(id, fmt)?;
+        fs::write(path, text).map_err(|e| format!("Could not write {}: {}", path, e))
+    }
+
+    pub(crate) fn memory_of(&self, id: ID) -> Result<Rc<RefCell<Memory>>, String> {
+        match self.components.get(&id) {
+            Some(c) => c
+                .borrow()
+                .memory()
+                .ok_or(format!("{} is not a memory", self.describe(id))),
+            None => Err(format!("No element with id_{}", id)),
+        }
+    }
+    // combinational memories output the new contents right away,
+    // synchronous ones on the next edge
+    fn refresh_memory(&mut self, id: ID) -> Result<(), String> {
+        let comb = self.components[&id].borrow().comp_type == CompType::Combinational;
+        if comb && self.active {
+            self.graph_act(update_component_state, &vec![id])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        circuit::BCircuit,
+        memory::MemoryFormat,
+        signal::{
+            Signal::{One, Zero},
+            Word,
+        },
+        types::CLOCK_PIN,
+    };
+
+    #[test]
+    fn rom() {
+        let mut c = BCircuit::new();
+        let addr = c.add_input("A", Word::from_u64(0, 3));
+        let rom = c.add_rom("R", 8, &[0x10, 0x21, 0x32, 0x43, 0x54]).unwrap();
+        c.connect(rom, 1, addr).unwrap();
+        c.power_on().unwrap();
+        assert_eq!(c.state(rom).unwrap(), Word::from_u64(0x10, 8));
+        c.set_component_state(addr, Word::from_u64(3, 3)).unwrap();
+        assert_eq!(c.state(rom).unwrap(), Word::from_u64(0x43, 8));
+        c.set_component_state(addr, Word::from_u64(6, 3)).unwrap();
+        assert_eq!(c.state(rom).unwrap(), Word::from_u64(0, 8));
+        c.set_component_state(addr, Word::parse("3'b0x1").unwrap())
+            .unwrap();
+        assert_eq!(c.state(rom).unwrap(), Word::unknown(8));
+
+        c.set_component_state(addr, Word::from_u64(6, 3)).unwrap();
+        c.load_memory_text(rom, "// comment\n@6 Ab\n1_1x0", MemoryFormat::Hex)
+            .unwrap_err();
+        c.load_memory_text(rom, "// comment\n@6 Ab 1x", MemoryFormat::Hex)
+            .unwrap();
+        assert_eq!(c.state(rom).unwrap(), Word::from_u64(0xab, 8));
+        assert_eq!(
+            c.read_memory(rom, 7).unwrap(),
+            Word::parse("8'b0001XXXX").unwrap()
+        );
+        let dump = c.dump_memory(rom, MemoryFormat::Hex).unwrap();
+        assert_eq!(
+            dump.lines().collect::<Vec<&str>>(),
+            ["10", "21", "32", "43", "54", "00", "ab", "1x"]
+        );
+        let bin = c.dump_memory(rom, MemoryFormat::Bin).unwrap();
+        assert_eq!(bin.lines().nth(1), Some("00100001"));
+
+        let mut d = BCircuit::new();
+        let other = d.add_memory("ROM", "", 3, 8).unwrap();
+        d.load_memory_text(other, &bin, MemoryFormat::Bin).unwrap();
+        assert_eq!(d.memory_contents(other), c.memory_contents(rom));
+
+        assert!(c.load_memory(rom, 7, &[1, 2]).is_err());
+        assert!(c.load_memory(rom, 0, &[0x100]).is_err());
+        assert!(c.add_memory("AND", "", 3, 8).is_err());
+        assert!(c.add_memory("ROM", "", 0, 8).is_err());
+    }
+
+    #[test]
+    fn rams() {
+        let mut c = BCircuit::new();
+        let clk = c.add_input("clk", Zero);
+        let addr = c.add_input("A", Word::from_u64(2, 4));
+        let din = c.add_input("D", Word::from_u64(0x5a, 8));
+        let we = c.add_input("WE", Zero);
+        let ram = c.add_memory("RAM", "M", 4, 8).unwrap();
+        let aram = c.add_memory("ARAM", "N", 4, 8).unwrap();
+        c.connect(ram, CLOCK_PIN, clk).unwrap();
+        for id in [ram, aram] {
+            c.connect_named(id, "ADDR", addr).unwrap();
+            c.connect_named(id, "DIN", din).unwrap();
+            c.connect_named(id, "WE", we).unwrap();
+        }
+        c.clock(clk);
+        c.power_on().unwrap();
+
+        c.set_component_state(we, One).unwrap();
+        // the asynchronous RAM writes right away
+        assert_eq!(c.read_memory(aram, 2).unwrap(), Word::from_u64(0x5a, 8));
+        assert_eq!(c.state(aram).unwrap(), Word::from_u64(0x5a, 8));
+        assert_eq!(c.read_memory(ram, 2).unwrap(), Word::from_u64(0, 8));
+        c.pulse_clock().unwrap();
+        assert_eq!(c.read_memory(ram, 2).unwrap(), Word::from_u64(0x5a, 8));
+        assert_eq!(c.state(ram).unwrap(), Word::from_u64(0x5a, 8));
+
+        c.set_component_state(we, Zero).unwrap();
+        c.set_component_state(addr, Word::from_u64(3, 4)).unwrap();
+        assert_eq!(c.state(aram).unwrap(), Word::from_u64(0, 8));
+        // the synchronous RAM reads on the edge
+        assert_eq!(c.state(ram).unwrap(), Word::from_u64(0x5a, 8));
+        c.pulse_clock().unwrap();
+        assert_eq!(c.state(ram).unwrap(), Word::from_u64(0, 8));
+
+        // writing with an unknown address bit may hit 2 and 3
+        c.set_component_state(addr, Word::parse("4'b001X").unwrap())
+            .unwrap();
+        c.set_component_state(din, Word::from_u64(0x0f, 8)).unwrap();
+        c.set_component_state(we, One).unwrap();
+        assert_eq!(
+            c.read_memory(aram, 2).unwrap(),
+            Word::parse("8'b0X0X1X1X").unwrap()
+        );
+        assert_eq!(
+            c.read_memory(aram, 3).unwrap(),
+            Word::parse("8'b0000XXXX").unwrap()
+        );
+        assert_eq!(c.read_memory(aram, 1).unwrap(), Word::from_u64(0, 8));
+
+        // levelized simulation starts from a copy of the RAM contents
+        c.set_component_state(we, Zero).unwrap();
+        c.set_component_state(addr, Word::from_u64(9, 4)).unwrap();
+        c.write_memory(ram, 9, Word::from_u64(0x77, 8)).unwrap();
+        let mut lc = c.levelize().unwrap();
+        lc.tick();
+        assert_eq!(lc.state(ram).unwrap(), Word::from_u64(0x77, 8));
+        lc.set(we, One).unwrap();
+        lc.set(din, Word::from_u64(0x66, 8)).unwrap();
+        lc.tick();
+        lc.set(we, Zero).unwrap();
+        lc.tick();
+        assert_eq!(lc.state(ram).unwrap(), Word::from_u64(0x66, 8));
+        assert_eq!(lc.state(aram).unwrap(), Word::from_u64(0x66, 8));
+        // and doesn't write into the circuit's
+        assert_eq!(c.read_memory(ram, 9).unwrap(), Word::from_u64(0x77, 8));
+        assert_eq!(c.read_memory(aram, 9).unwrap(), Word::from_u64(0, 8));
+        assert!(c.pattern_sim().is_err());
+
+        assert!(c.write_memory(ram, 16, Word::from_u64(0, 8)).is_err());
+        assert!(c.write_memory(ram, 1, Word::from_u64(0, 4)).is_err());
+    }
+
+    #[test]
+    fn memory_netlist() {
+        let mut c = BCircuit::new();
+        let addr = c.add_input("A", Word::from_u64(1, 2));
+        let rom = c.add_rom("R", 12, &[0xabc, 0xdef, 0x123]).unwrap();
+        c.connect(rom, 1, addr).unwrap();
+        c.track_output(rom);
+        c.power_on().unwrap();
+        let text = c.to_netlist().unwrap();
+        assert!(text.contains(&format!("memory {} 2 abc def 123 000\n", rom)));
+
+        let mut d = BCircuit::new();
+        d.load_netlist(&text).unwrap();
+        assert_eq!(d.memory_contents(rom), c.memory_contents(rom));
+        assert_eq!(d.state(rom).unwrap(), Word::from_u64(0xdef, 12));
+        d.power_on().unwrap();
+        d.set_component_state(addr, Word::from_u64(2, 2)).unwrap();
+        assert_eq!(d.state(rom).unwrap(), Word::from_u64(0x123, 12));
+        // contents are not shared
+        d.write_memory(rom, 0, Word::from_u64(1, 12)).unwrap();
+        assert_eq!(c.read_memory(rom, 0).unwrap(), Word::from_u64(0xabc, 12));
+    }
+}
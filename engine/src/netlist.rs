@@ -0,0 +1,612 @@
+// On-disk netlist format for `BCircuit`.
+//
+// The format is line oriented text. The first non-comment line is the
+// header `bsim-netlist <version>`, every following line is one record:
+//
+//   component <id> <type> <state> <label>
+//   pins <id> <width of pin 0> <width of pin 1> ...
+//   delay <id> <rise> <fall>
+//   memory <id> <address width> <word 0> <word 1> ...
+//   connect <receiver_id> <pin> <emitter_id> [<lsb>]
+//   output <id>
+//   clock <id> <period> <phase> <high>
+//
+// - Lines starting with `#` and blank lines are ignored.
+// - `<type>` is a key of `component_definitions` ("Input" for inputs).
+// - `<state>` is one of `0`, `1`, `X`, `Z` for single bit components and
+//   `<width>'b<bits>` (most significant first) for buses, so the state
+//   also gives the component width. It is stored for every component so
+//   that loading restores the exact snapshot without re-running the circuit.
+// - `pins` is only written for components whose input pins differ in
+//   number or width from what their type and width imply, e.g. mergers
+//   and gates with extra inputs.
+// - `delay` is only written for components whose propagation delays
+//   differ from their type's.
+// - `memory` is written for every memory component, with its words in
+//   hex, see `memory`.
+// - `clock` gives the waveform of a clock, see `clocks`. Before version 5
+//   it only had the id, which is read as the default waveform of `clock`.
+// - `<lsb>` is present if the pin reads a slice of the emitter's output.
+// - Fields are separated by any run of spaces or tabs.
+// - `<label>` is the remainder of the line after the one space ending
+//   `<state>`, verbatim (may be empty).
+// - `component` records must precede the records that refer to them.
+//
+// IDs are preserved across a save/load round trip.
+
+use std::{cell::RefCell, fs};
+
+use crate::{
+    circuit::BCircuit,
+    components::{set_expressions, Gate},
+    memory::{MemoryFormat, MAX_ADDR_WIDTH},
+    signal::{Word, MAX_WIDTH},
+    types::{CompType, Time, ID, NULL, PIN},
+};
+
+// 2: bus widths, `pins` records and sliced connections
+// 3: `delay` records
+// 4: `memory` records
+// 5: clock waveforms
+pub const NETLIST_VERSION: u32 = 5;
+const HEADER: &str = "bsim-netlist";
+
+struct ComponentRecord {
+    id: ID,
+    typ: String,
+    state: Word,
+    label: String,
+}
+
+impl BCircuit {
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let text = self.to_netlist()?;
+        fs::write(path, text).map_err(|e| format!("Could not write {}: {}", path, e))
+    }
+
+    pub fn load(&mut self, path: &str) -> Result<(), String> {
+        let text =
+            fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+        self.load_netlist(&text)
+    }
+
+    pub fn to_netlist(&self) -> Result<String, String> {
+        let mut ids = self.components().keys().cloned().collect::<Vec<ID>>();
+        ids.sort();
+
+        let mut out = format!("{} {}\n", HEADER, NETLIST_VERSION);
+        let mut conns = Vec::new();
+        for id in &ids {
+            let c = self.components().get(id).unwrap().borrow();
+            if c.name.is_empty() || c.name.contains(char::is_whitespace) {
+                return Err(format!(
+                    "id_{}: type name '{}' can't be written to a netlist",
+                    id, c.name
+                ));
+            }
+            if c.label.contains('\n') {
+                return Err(format!("id_{}: labels can't span multiple lines", id));
+            }
+            out.push_str(&format!(
+                "component {} {} {} {}\n",
+                id,
+                c.name,
+                c.state.to_bin_string(),
+                c.label
+            ));
+            let def = self.component_definitions.get(&c.name);
+            // drivers of a bus are added back as they are connected
+            let extra_pins = !c.multi_driver
+                && def.is_some_and(|d| c.num_inputs() != d.default_inputs as usize + 1);
+            if *c.pin_widths() != c.default_pin_widths() || extra_pins {
+                let widths = c.pin_widths().iter().map(|w| w.to_string());
+                out.push_str(&format!(
+                    "pins {} {}\n",
+                    id,
+                    widths.collect::<Vec<String>>().join(" ")
+                ));
+            }
+            let def_delays = def.map(|d| (d.rise_delay, d.fall_delay));
+            if def_delays.is_some_and(|d| d != (c.rise_delay, c.fall_delay)) {
+                out.push_str(&format!("delay {} {} {}\n", id, c.rise_delay, c.fall_delay));
+            }
+            if let Some(m) = c.memory() {
+                let m = m.borrow();
+                let words = m
+                    .dump(MemoryFormat::Hex)
+                    .split_whitespace()
+                    .collect::<Vec<&str>>()
+                    .join(" ");
+                out.push_str(&format!("memory {} {} {}\n", id, m.addr_width(), words));
+            }
+            for (pin, src) in c.input_pin_sources.iter().enumerate() {
+                if *src != NULL {
+                    conns.push((*id, pin, *src, c.pin_slice(pin)));
+                }
+            }
+        }
+        for (rec, pin, em, slice) in conns {
+            match slice {
+                Some(lsb) => out.push_str(&format!("connect {} {} {} {}\n", rec, pin, em, lsb)),
+                None => out.push_str(&format!("connect {} {} {}\n", rec, pin, em)),
+            }
+        }
+        let mut outs = self.outputs.iter().cloned().collect::<Vec<ID>>();
+        outs.sort();
+        for id in outs {
+            out.push_str(&format!("output {}\n", id));
+        }
+        for clk in self.clocks() {
+            out.push_str(&format!(
+                "clock {} {} {} {}\n",
+                clk.id, clk.period, clk.phase, clk.high
+            ));
+        }
+        Ok(out)
+    }
+
+    // Replaces the contents of this circuit with the ones described by
+    // `text`. Component definitions are kept, so custom gates registered
+    // with `define_gate` can be referenced by the netlist. On error the
+    // circuit is left untouched. The loaded circuit still has to be
+    // powered on.
+    pub fn load_netlist(&mut self, text: &str) -> Result<(), String> {
+        let mut ckt = BCircuit::new();
+        ckt.component_definitions = self.component_definitions.clone();
+
+        let mut version = None;
+        let mut conns: Vec<(usize, ID, PIN, ID, Option<u8>)> = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let lno = i + 1;
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            if version.is_none() {
+                version = Some(parse_header(trimmed).map_err(|e| format!("line {}: {}", lno, e))?);
+                continue;
+            }
+            let keyword = trimmed.split_whitespace().next().unwrap();
+            let res = match keyword {
+                // the label keeps its trailing whitespace
+                "component" => parse_component(line).and_then(|rec| ckt.insert_record(rec)),
+                "pins" => {
+                    parse_pins(trimmed).and_then(|(id, widths)| ckt.set_record_pins(id, &widths))
+                }
+                "delay" => parse_times(trimmed, 2)
+                    .and_then(|(id, t)| ckt.set_component_delay(id, t[0], t[1])),
+                "memory" => parse_memory(trimmed).and_then(|(id, addr_width, words)| {
+                    ckt.set_record_memory(id, addr_width, words)
+                }),
+                "connect" => parse_connect(trimmed).map(|(rec, pin, em, slice)| {
+                    conns.push((lno, rec, pin, em, slice));
+                }),
+                "output" => parse_ids(trimmed, 1).and_then(|v| {
+                    if ckt.track_output(v[0]) {
+                        Ok(())
+                    } else {
+                        Err(format!("output refers to unknown id_{}", v[0]))
+                    }
+                }),
+                "clock" => parse_clock(trimmed)
+                    .and_then(|(id, waveform)| ckt.set_record_clock(id, &waveform)),
+                _ => Err(format!("unknown record '{}'", keyword)),
+            };
+            res.map_err(|e| format!("line {}: {}", lno, e))?;
+        }
+        if version.is_none() {
+            return Err("Empty netlist".into());
+        }
+
+        // wire up with the saved states already in place, so that the
+        // receivers pick up the exact pin values they had when saved.
+        for (lno, rec, pin, em, slice) in conns {
+            ckt.do_connect(rec, pin, em, slice)
+                .map_err(|e| format!("line {}: {}", lno, e))?;
+        }
+        let all = ckt.components().keys().cloned().collect();
+        ckt.graph_act(set_expressions, &all)?;
+
+        ckt.component_definitions = std::mem::take(&mut self.component_definitions);
+        *self = ckt;
+        Ok(())
+    }
+
+    fn insert_record(&mut self, rec: ComponentRecord) -> Result<(), String> {
+        if self.components().contains_key(&rec.id) {
+            return Err(format!("duplicate component id_{}", rec.id));
+        }
+        if rec.id <= 0 {
+            return Err(format!("invalid component id_{}", rec.id));
+        }
+        let mut gate = if rec.typ == "Input" {
+            Gate::make_input(&rec.label, rec.state)
+        } else {
+            match self.make_component(&rec.typ, &rec.label) {
+                Ok(g) => g,
+                Err(_) => {
+                    return Err(format!(
+                        "gate type '{}' is not in component_definitions",
+                        rec.typ
+                    ))
+                }
+            }
+        };
+        gate.id = rec.id;
+        if gate.width() != rec.state.width() {
+            gate.set_width(rec.state.width());
+        }
+        gate.set_state(rec.state);
+        if gate.comp_type == CompType::Input {
+            self.inputs.insert(rec.label, rec.id);
+        }
+        self.components.insert(rec.id, RefCell::new(gate));
+        self.last_id = self.last_id.max(rec.id);
+        Ok(())
+    }
+
+    fn set_record_pins(&mut self, id: ID, widths: &[u8]) -> Result<(), String> {
+        let mut c = match self.components().get(&id) {
+            Some(c) => c.borrow_mut(),
+            None => return Err(format!("pins refers to unknown id_{}", id)),
+        };
+        if widths.len() < c.num_inputs() {
+            return Err(format!(
+                "id_{} has {} pins, got {} widths",
+                id,
+                c.num_inputs(),
+                widths.len()
+            ));
+        }
+        let extra = widths.len() - c.num_inputs();
+        c.add_input_pins(extra);
+        c.set_pin_widths(widths);
+        Ok(())
+    }
+
+    fn set_record_clock(&mut self, id: ID, waveform: &[Time]) -> Result<(), String> {
+        let label = match self.components.get(&id) {
+            Some(c) if c.borrow().comp_type == CompType::Input => c.borrow().label.clone(),
+            Some(_) => return Err(format!("clock id_{} is not an input", id)),
+            None => return Err(format!("clock refers to unknown id_{}", id)),
+        };
+        if let [period, _, high] = *waveform {
+            if high == 0 || high >= period {
+                return Err(format!("bad waveform for clock {}", label));
+            }
+        }
+        self.clock(id);
+        if let [period, phase, high] = *waveform {
+            let clk = self.clocks.iter_mut().find(|c| c.id == id).unwrap();
+            clk.period = period;
+            clk.phase = phase;
+            clk.high = high;
+        }
+        Ok(())
+    }
+
+    fn set_record_memory(&mut self, id: ID, addr_width: u8, words: &str) -> Result<(), String> {
+        let mut c = match self.components().get(&id) {
+            Some(c) => c.borrow_mut(),
+            None => return Err(format!("memory refers to unknown id_{}", id)),
+        };
+        if c.memory().is_none() {
+            return Err(format!("id_{} is not a memory", id));
+        }
+        c.resize_memory(addr_width);
+        let mem = c.memory().unwrap();
+        let mut contents = mem.borrow_mut();
+        contents.load_text(words, MemoryFormat::Hex)
+    }
+}
+
+fn parse_header(line: &str) -> Result<u32, String> {
+    let mut toks = line.split_whitespace();
+    if toks.next() != Some(HEADER) {
+        return Err(format!("expected '{} <version>' header", HEADER));
+    }
+    let version = toks
+        .next()
+        .and_then(|v| v.parse::<u32>().ok())
+        .ok_or("missing netlist version")?;
+    if version > NETLIST_VERSION {
+        return Err(format!(
+            "netlist version {} is newer than supported version {}",
+            version, NETLIST_VERSION
+        ));
+    }
+    Ok(version)
+}
+
+// the first `n` whitespace separated fields of `line`, and what follows
+// the one whitespace character ending the last of them
+fn split_fields(line: &str, n: usize) -> (Vec<&str>, &str) {
+    let mut toks = Vec::new();
+    let mut rest = line.trim_start();
+    while toks.len() < n && !rest.is_empty() {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        toks.push(&rest[..end]);
+        rest = &rest[end..];
+        if toks.len() < n {
+            rest = rest.trim_start();
+        }
+    }
+    let mut chars = rest.chars();
+    chars.next();
+    (toks, chars.as_str())
+}
+
+fn parse_component(line: &str) -> Result<ComponentRecord, String> {
+    let (toks, label) = split_fields(line, 4);
+    if toks.len() < 4 {
+        return Err("expected 'component <id> <type> <state> <label>'".into());
+    }
+    let id = toks[1]
+        .parse::<ID>()
+        .map_err(|_| format!("bad component id '{}'", toks[1]))?;
+    let state = Word::parse(toks[3]).ok_or_else(|| format!("bad state '{}'", toks[3]))?;
+    Ok(ComponentRecord {
+        id,
+        typ: toks[2].to_string(),
+        state,
+        label: label.to_string(),
+    })
+}
+
+fn parse_connect(line: &str) -> Result<(ID, PIN, ID, Option<u8>), String> {
+    let n = line.split_whitespace().count() - 1;
+    let v = parse_ids(line, if n == 4 { 4 } else { 3 })?;
+    let slice = match v.get(3) {
+        Some(lsb) => Some(u8::try_from(*lsb).map_err(|_| format!("bad slice '{}'", lsb))?),
+        None => None,
+    };
+    Ok((v[0], v[1] as PIN, v[2], slice))
+}
+
+fn parse_pins(line: &str) -> Result<(ID, Vec<u8>), String> {
+    let v = parse_ids(line, line.split_whitespace().count() - 1)?;
+    if v.len() < 2 {
+        return Err("expected 'pins <id> <widths>...'".into());
+    }
+    let widths = v[1..]
+        .iter()
+        .map(|w| match u8::try_from(*w) {
+            Ok(w) if w > 0 && w <= MAX_WIDTH => Ok(w),
+            _ => Err(format!("bad pin width '{}'", w)),
+        })
+        .collect::<Result<Vec<u8>, String>>()?;
+    Ok((v[0], widths))
+}
+
+fn parse_memory(line: &str) -> Result<(ID, u8, &str), String> {
+    let (toks, words) = split_fields(line, 3);
+    if toks.len() < 3 {
+        return Err("expected 'memory <id> <address width> <words>...'".into());
+    }
+    let id = toks[1]
+        .parse::<ID>()
+        .map_err(|_| format!("bad memory id '{}'", toks[1]))?;
+    let addr_width = match toks[2].parse::<u8>() {
+        Ok(w) if w > 0 && w <= MAX_ADDR_WIDTH => w,
+        _ => return Err(format!("bad address width '{}'", toks[2])),
+    };
+    Ok((id, addr_width, words))
+}
+
+// `clock <id>` or `clock <id> <period> <phase> <high>`
+fn parse_clock(line: &str) -> Result<(ID, Vec<Time>), String> {
+    match line.split_whitespace().count() {
+        2 => parse_times(line, 0),
+        _ => parse_times(line, 3),
+    }
+}
+
+// `<keyword> <id> <time>...` with `n` times
+fn parse_times(line: &str, n: usize) -> Result<(ID, Vec<Time>), String> {
+    let toks = line.split_whitespace().skip(1).collect::<Vec<&str>>();
+    if toks.len() != n + 1 {
+        return Err(format!("expected {} numeric fields", n + 1));
+    }
+    let id = toks[0]
+        .parse::<ID>()
+        .map_err(|_| format!("bad number '{}'", toks[0]))?;
+    let times = toks[1..]
+        .iter()
+        .map(|t| t.parse::<Time>().map_err(|_| format!("bad time '{}'", t)))
+        .collect::<Result<Vec<Time>, String>>()?;
+    Ok((id, times))
+}
+
+fn parse_ids(line: &str, n: usize) -> Result<Vec<ID>, String> {
+    let toks = line.split_whitespace().skip(1).collect::<Vec<&str>>();
+    if toks.len() != n {
+        return Err(format!("expected {} numeric fields", n));
+    }
+    toks.iter()
+        .map(|t| t.parse::<ID>().map_err(|_| format!("bad number '{}'", t)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        circuit::BCircuit,
+        signal::{Signal, Word},
+        types::CLOCK_PIN,
+    };
+
+    fn counter() -> BCircuit {
+        let mut c = BCircuit::new();
+        let one = c.add_input("1", Signal::One);
+        let clk = c.add_input("clk", Signal::Zero);
+        let q = c.add_component("JK", "Q1").unwrap();
+        let qq = c.add_component("JK", "Q2").unwrap();
+        let n = c.add_component("NOT", "").unwrap();
+        c.connect(q, 1, one).unwrap();
+        c.connect(q, 2, one).unwrap();
+        c.connect(qq, 1, one).unwrap();
+        c.connect(qq, 2, one).unwrap();
+        c.connect(q, CLOCK_PIN, clk).unwrap();
+        c.connect(n, 1, q).unwrap();
+        c.connect(qq, CLOCK_PIN, n).unwrap();
+        c.track_output(q);
+        c.track_output(qq);
+        c.clock(clk);
+        c.power_on().unwrap();
+        c.pulse_clock().unwrap();
+        c
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut c = counter();
+        // leave a hole in the id space
+        let tmp = c.add_component("AND", "tmp").unwrap();
+        c.remove_component(tmp).unwrap();
+        let a = c.add_component("AND", "with spaces  in label").unwrap();
+        c.connect(a, 1, tmp - 1).unwrap();
+
+        let text = c.to_netlist().unwrap();
+        let mut d = BCircuit::new();
+        d.load_netlist(&text).unwrap();
+        assert_eq!(d.to_netlist().unwrap(), text);
+        assert_eq!(d.get_clk_id(), c.get_clk_id());
+        assert_eq!(
+            d.get_component(&a).unwrap().borrow().label,
+            "with spaces  in label"
+        );
+        let states = |c: &BCircuit| {
+            c.components()
+                .iter()
+                .map(|(id, g)| (*id, g.borrow().state))
+                .collect::<HashMap<_, _>>()
+        };
+        assert_eq!(states(&c), states(&d));
+
+        // new components don't collide with loaded ids
+        let b = d.add_component("OR", "").unwrap();
+        assert!(b > a);
+    }
+
+    #[test]
+    fn loaded_circuit_simulates() {
+        let mut c = counter();
+        let mut d = BCircuit::new();
+        d.load_netlist(&c.to_netlist().unwrap()).unwrap();
+        d.power_on().unwrap();
+        for _ in 0..3 {
+            c.pulse_clock().unwrap();
+            d.pulse_clock().unwrap();
+            let outs = c.outputs.iter().map(|id| c.state(*id)).collect::<Vec<_>>();
+            let douts = c.outputs.iter().map(|id| d.state(*id)).collect::<Vec<_>>();
+            assert_eq!(outs, douts);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let mut c = BCircuit::new();
+        let err = c
+            .load_netlist("bsim-netlist 1\ncomponent 1 MUX 0 m\n")
+            .unwrap_err();
+        assert!(err.contains("line 2") && err.contains("MUX"));
+        assert!(c.load_netlist("bsim-netlist 99\n").is_err());
+        assert!(c.load_netlist("component 1 AND 0 \n").is_err());
+        assert!(c
+            .load_netlist("bsim-netlist 1\ncomponent 1 AND 0 \nconnect 1 1 7\n")
+            .is_err());
+        assert!(c.components().is_empty());
+    }
+
+    #[test]
+    fn indented_records() {
+        let mut c = BCircuit::new();
+        c.load_netlist("bsim-netlist 5\n  component 1 Input 1 a b \n\toutput 1\n")
+            .unwrap();
+        assert_eq!(c.get_component(&1).unwrap().borrow().label, "a b ");
+        assert!(c.outputs.contains(&1));
+
+        // tabs and runs of spaces between fields
+        let mut c = BCircuit::new();
+        let text = "bsim-netlist 5\ncomponent  1\tInput   1\ta\ncomponent\t2 NOT 0 \n";
+        c.load_netlist(&format!("{}connect 2  1\t1\noutput\t 2\n", text))
+            .unwrap();
+        assert_eq!(c.get_component(&1).unwrap().borrow().label, "a");
+        assert_eq!(c.get_component(&2).unwrap().borrow().label, "");
+        assert!(c.outputs.contains(&2));
+        c.power_on().unwrap();
+        assert_eq!(c.state(2).unwrap().to_string(), "0");
+    }
+
+    #[test]
+    fn buses_round_trip() {
+        let mut c = BCircuit::new();
+        c.power_on().unwrap();
+        let a = c.add_input("A", Word::from_u64(0b1001, 4));
+        let bits = c.add_splitter("a", a).unwrap();
+        let m = c.add_merger("m", 3).unwrap();
+        c.set_component_delay(m, 2, 3).unwrap();
+        c.connect(m, 1, bits[3]).unwrap();
+        c.connect_slice(m, 2, a, 1).unwrap();
+
+        let text = c.to_netlist().unwrap();
+        assert!(text.contains("4'b1001"));
+        assert!(text.contains(&format!("pins {} 1 1 1 1", m)));
+        assert!(text.contains(&format!("delay {} 2 3", m)));
+        let mut d = BCircuit::new();
+        d.load_netlist(&text).unwrap();
+        assert_eq!(d.to_netlist().unwrap(), text);
+        d.power_on().unwrap();
+        d.set_component_state(a, Word::from_u64(0b1110, 4)).unwrap();
+        assert_eq!(d.state(m).unwrap().to_string(), "X11");
+
+        // delays are times, wider than ids
+        c.set_component_delay(m, 3_000_000_000, 1).unwrap();
+        let text = c.to_netlist().unwrap();
+        d.load_netlist(&text).unwrap();
+        assert_eq!(d.to_netlist().unwrap(), text);
+        let bad = text.replace(
+            &format!("delay {} 3000000000 1", m),
+            &format!("delay {} -3 1", m),
+        );
+        assert!(d.load_netlist(&bad).unwrap_err().contains("bad time '-3'"));
+    }
+
+    #[test]
+    fn clocks_round_trip() {
+        let mut c = BCircuit::new();
+        let a = c.add_clock("a", 10, 3, 20).unwrap();
+        let b = c.add_input("b", Signal::Zero);
+        c.clock(b);
+        let text = c.to_netlist().unwrap();
+        assert!(text.contains(&format!("clock {} 10 3 2\n", a)));
+        assert!(text.contains(&format!("clock {} 2 1 1\n", b)));
+        let mut d = BCircuit::new();
+        d.load_netlist(&text).unwrap();
+        assert_eq!(d.clocks(), c.clocks());
+        // version 4 clocks only have the id
+        d.load_netlist(&format!(
+            "bsim-netlist 4\ncomponent {} Input 0 b\nclock {}\n",
+            b, b
+        ))
+        .unwrap();
+        assert_eq!(d.clocks(), &c.clocks()[1..]);
+        assert!(d
+            .load_netlist("bsim-netlist 5\ncomponent 1 Input 0 c\nclock 1 4 0 4\n")
+            .is_err());
+
+        // waveforms are times, wider than ids
+        let mut c = BCircuit::new();
+        c.add_clock("clk", 5_000_000_000, 0, 50).unwrap();
+        let text = c.to_netlist().unwrap();
+        d.load_netlist(&text).unwrap();
+        assert_eq!(d.clocks(), c.clocks());
+        // a bad waveform leaves no clock behind
+        let mut e = BCircuit::new();
+        let clk = e.add_input("c", Signal::Zero);
+        assert!(e.set_record_clock(clk, &[4, 0, 0]).is_err());
+        assert!(e.clocks().is_empty());
+    }
+}
@@ -0,0 +1,660 @@
+// Bit-parallel pattern simulation.
+//
+// Every bit of every net is stored as a `Lane` holding that bit for 64
+// independent input patterns, so one pass over the levelized circuit
+// (see `levelized`) evaluates 64 rows of a truth table at once. Gates
+// with a `pattern_eval` compute whole lanes with a few bitwise
+// operations, the others fall back to `eval` once per pattern.
+//
+// Used by `BCircuit::gen_bus_table` and for equivalence checking of
+// two circuits with the same inputs and outputs.
+
+use std::{fmt, ops::Not};
+
+use crate::{
+    circuit::BCircuit,
+    levelized::{Instr, LevelizedCircuit},
+    signal::{Signal, Word},
+    table::counter_row,
+    types::ID,
+};
+
+// patterns simulated per pass
+pub const PATTERNS: usize = 64;
+
+// circuits with more input bits are not checked exhaustively
+const MAX_EXHAUSTIVE_BITS: u32 = 24;
+
+// One bit of a net across 64 patterns, bit p of each mask belonging to
+// pattern p. A pattern is 1 in `ones`, 0 in `zeros`, Z in `z` and X
+// in none of them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Lane {
+    pub ones: u64,
+    pub zeros: u64,
+    pub z: u64,
+}
+
+const FLOATING: [Lane; 64] = [Lane {
+    ones: 0,
+    zeros: 0,
+    z: u64::MAX,
+}; 64];
+
+impl Lane {
+    pub fn splat(s: Signal) -> Lane {
+        let mut l = Lane::default();
+        for p in 0..PATTERNS {
+            l.set(p, s);
+        }
+        l
+    }
+    pub fn get(&self, p: usize) -> Signal {
+        if self.ones >> p & 1 == 1 {
+            Signal::One
+        } else if self.zeros >> p & 1 == 1 {
+            Signal::Zero
+        } else if self.z >> p & 1 == 1 {
+            Signal::Z
+        } else {
+            Signal::X
+        }
+    }
+    pub fn set(&mut self, p: usize, s: Signal) {
+        let m = 1 << p;
+        self.ones &= !m;
+        self.zeros &= !m;
+        self.z &= !m;
+        match s {
+            Signal::One => self.ones |= m,
+            Signal::Zero => self.zeros |= m,
+            Signal::Z => self.z |= m,
+            Signal::X => {}
+        }
+    }
+    // the value seen by gate inputs, floating patterns read as unknown
+    pub fn read(self) -> Lane {
+        Lane { z: 0, ..self }
+    }
+    pub fn and(self, other: Lane) -> Lane {
+        Lane {
+            ones: self.ones & other.ones,
+            zeros: self.zeros | other.zeros,
+            z: 0,
+        }
+    }
+    pub fn or(self, other: Lane) -> Lane {
+        Lane {
+            ones: self.ones | other.ones,
+            zeros: self.zeros & other.zeros,
+            z: 0,
+        }
+    }
+    pub fn xor(self, other: Lane) -> Lane {
+        let known = (self.ones | self.zeros) & (other.ones | other.zeros);
+        let v = self.ones ^ other.ones;
+        Lane {
+            ones: v & known,
+            zeros: !v & known,
+            z: 0,
+        }
+    }
+    // tri-state buffer with enable `en`
+    pub fn tri(self, en: Lane) -> Lane {
+        Lane {
+            ones: self.ones & en.ones,
+            zeros: self.zeros & en.ones,
+            z: en.zeros,
+        }
+    }
+    // two drivers of the same net, see `bus::resolve_drivers`
+    pub fn resolve(self, other: Lane) -> Lane {
+        Lane {
+            ones: self.ones & (other.ones | other.z) | other.ones & self.z,
+            zeros: self.zeros & (other.zeros | other.z) | other.zeros & self.z,
+            z: self.z & other.z,
+        }
+    }
+}
+
+impl Not for Lane {
+    type Output = Lane;
+    fn not(self) -> Lane {
+        Lane {
+            ones: self.zeros,
+            zeros: self.ones,
+            z: 0,
+        }
+    }
+}
+
+fn word_at(lanes: &[Lane], p: usize) -> Word {
+    Word::from_fn(lanes.len() as u8, |i| lanes[i].get(p))
+}
+
+fn set_word_at(lanes: &mut [Lane], p: usize, val: Word) {
+    for (i, l) in lanes.iter_mut().enumerate() {
+        l.set(p, val.bit(i));
+    }
+}
+
+// A levelized circuit whose values are lanes, see `BCircuit::pattern_sim`.
+pub struct PatternCircuit {
+    lc: LevelizedCircuit,
+    // first lane of every slot
+    offsets: Vec<usize>,
+    lanes: Vec<Lane>,
+    clocked: bool,
+}
+
+impl PatternCircuit {
+    fn slot_lanes(&self, slot: usize) -> &[Lane] {
+        let at = self.offsets[slot];
+        &self.lanes[at..at + self.lc.values[slot].width() as usize]
+    }
+    pub fn lanes(&self, id: ID) -> Option<&[Lane]> {
+        self.lc.slots.get(&id).map(|s| self.slot_lanes(*s))
+    }
+    // value of `id` in pattern `p`
+    pub fn get(&self, id: ID, p: usize) -> Option<Word> {
+        self.lanes(id).map(|l| word_at(l, p))
+    }
+    // sets an input or the state of a clocked component in pattern `p`.
+    // Call `eval` or `tick` to propagate it.
+    pub fn set(&mut self, id: ID, p: usize, val: impl Into<Word>) -> Result<(), String> {
+        let val = val.into();
+        let slot = self.lc.settable_slot(id, val)?;
+        let at = self.offsets[slot];
+        set_word_at(&mut self.lanes[at..at + val.width() as usize], p, val);
+        Ok(())
+    }
+    // settles the combinational logic of every pattern
+    pub fn eval(&mut self) {
+        for i in 0..self.lc.comb.len() {
+            let out = self.run(&self.lc.comb[i]);
+            self.commit(self.lc.comb[i].slot, &out);
+        }
+    }
+    // a rising edge of every circuit clock in every pattern
+    pub fn tick(&mut self) {
+        let next = self
+            .lc
+            .seq
+            .iter()
+            .map(|ins| self.run(ins))
+            .collect::<Vec<Vec<Lane>>>();
+        for (i, out) in next.iter().enumerate() {
+            self.commit(self.lc.seq[i].slot, out);
+        }
+        self.eval();
+    }
+    fn commit(&mut self, slot: usize, out: &[Lane]) {
+        let at = self.offsets[slot];
+        self.lanes[at..at + out.len()].copy_from_slice(out);
+    }
+    fn run(&self, ins: &Instr) -> Vec<Lane> {
+        let pins = self.lc.pins[ins.pins.clone()]
+            .iter()
+            .map(|p| match p.src {
+                None => &FLOATING[..p.width as usize],
+                Some(s) => {
+                    let l = self.slot_lanes(s);
+                    let lsb = p.slice.unwrap_or(0) as usize;
+                    &l[lsb..lsb + p.width as usize]
+                }
+            })
+            .collect::<Vec<&[Lane]>>();
+        let mut out = self.slot_lanes(ins.slot).to_vec();
+        match ins.pattern_eval {
+            Some(f) => f(&pins, &mut out),
+            None => {
+                for p in 0..PATTERNS {
+                    let words = pins.iter().map(|l| word_at(l, p)).collect::<Vec<Word>>();
+                    let val = (ins.eval)(&words, word_at(&out, p));
+                    set_word_at(&mut out, p, val);
+                }
+            }
+        }
+        out
+    }
+    // sets `inps` to the values of each row, settles, clocks the circuit
+    // if it has a clock and returns the values of `outs` for each row,
+    // like a single row of `BCircuit::gen_bus_table`.
+    pub(crate) fn run_rows(
+        &mut self,
+        inps: &[ID],
+        outs: &[ID],
+        rows: &[Vec<Word>],
+    ) -> Result<Vec<Vec<Word>>, String> {
+        for (p, row) in rows.iter().enumerate() {
+            for (id, val) in inps.iter().zip(row) {
+                self.set(*id, p, *val)?;
+            }
+        }
+        self.eval();
+        if self.clocked {
+            self.tick();
+        }
+        Ok((0..rows.len())
+            .map(|p| outs.iter().map(|id| self.get(*id, p).unwrap()).collect())
+            .collect())
+    }
+}
+
+// An input assignment on which two circuits disagree.
+#[derive(Debug, PartialEq)]
+pub struct Counterexample {
+    pub inputs: Vec<(String, Word)>,
+    // (label, value in the checked circuit, value in the other one) of
+    // every output that differs
+    pub outputs: Vec<(String, Word, Word)>,
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|(n, v)| format!("{}={}", n, v))
+            .collect::<Vec<String>>();
+        write!(f, "{}:", inputs.join(", "))?;
+        for (n, a, b) in &self.outputs {
+            write!(f, " {} is {} instead of {}", n, a, b)?;
+        }
+        Ok(())
+    }
+}
+
+struct Ports {
+    inps: Vec<ID>,
+    outs: Vec<ID>,
+    // (name, width) of inputs and outputs
+    in_names: Vec<(String, u8)>,
+    out_names: Vec<(String, u8)>,
+}
+
+impl BCircuit {
+    // compiles the circuit for `PatternCircuit`, every pattern starting
+    // from the current states. Fails like `levelize`, and on RAMs.
+    pub fn pattern_sim(&self) -> Result<PatternCircuit, String> {
+        // patterns would all write to the same contents
+        for (id, c) in &self.components {
+            if c.borrow()
+                .memory()
+                .is_some_and(|m| !m.borrow().is_read_only())
+            {
+                return Err(format!(
+                    "Can't simulate patterns through the RAM {}",
+                    self.describe(*id)
+                ));
+            }
+        }
+        let lc = self.levelize()?;
+        let mut offsets = Vec::with_capacity(lc.values.len());
+        let mut lanes = Vec::new();
+        for v in &lc.values {
+            offsets.push(lanes.len());
+            lanes.extend(v.bits().into_iter().map(Lane::splat));
+        }
+        Ok(PatternCircuit {
+            lc,
+            offsets,
+            lanes,
+            clocked: !self.clocks.is_empty(),
+        })
+    }
+
+    fn pattern_ports(&self) -> Ports {
+        let (inps, outs) = self.ports();
+        let name = |id: &ID, f: fn(&crate::components::Gate) -> String| {
+            let c = self.components[id].borrow();
+            (f(&c), c.width())
+        };
+        Ports {
+            in_names: inps
+                .iter()
+                .map(|id| name(id, |c| c.state_expr.clone()))
+                .collect(),
+            out_names: outs
+                .iter()
+                .map(|id| name(id, |c| c.label.clone()))
+                .collect(),
+            inps,
+            outs,
+        }
+    }
+
+    fn matching_ports(&self, other: &BCircuit) -> Result<(Ports, Ports), String> {
+        let (a, b) = (self.pattern_ports(), other.pattern_ports());
+        let show = |names: &[(String, u8)]| {
+            names
+                .iter()
+                .map(|(n, w)| {
+                    if *w == 1 {
+                        n.clone()
+                    } else {
+                        format!("{}[{}]", n, w)
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        if a.in_names != b.in_names {
+            return Err(format!(
+                "Inputs differ: [{}] and [{}]",
+                show(&a.in_names),
+                show(&b.in_names)
+            ));
+        }
+        if a.out_names != b.out_names {
+            return Err(format!(
+                "Outputs differ: [{}] and [{}]",
+                show(&a.out_names),
+                show(&b.out_names)
+            ));
+        }
+        Ok((a, b))
+    }
+
+    // runs both circuits on `rows` (at most `PATTERNS`), returning the
+    // first row on which their outputs differ
+    fn compare_rows(
+        (a, pa): (&mut PatternCircuit, &Ports),
+        (b, pb): (&mut PatternCircuit, &Ports),
+        rows: &[Vec<Word>],
+    ) -> Result<Option<Counterexample>, String> {
+        let ra = a.run_rows(&pa.inps, &pa.outs, rows)?;
+        let rb = b.run_rows(&pb.inps, &pb.outs, rows)?;
+        for ((row, oa), ob) in rows.iter().zip(ra).zip(rb) {
+            if oa == ob {
+                continue;
+            }
+            return Ok(Some(Counterexample {
+                inputs: pa
+                    .in_names
+                    .iter()
+                    .map(|n| n.0.clone())
+                    .zip(row.clone())
+                    .collect(),
+                outputs: pa
+                    .out_names
+                    .iter()
+                    .zip(oa.iter().zip(&ob))
+                    .filter(|(_, (x, y))| x != y)
+                    .map(|(n, (x, y))| (n.0.clone(), *x, *y))
+                    .collect(),
+            }));
+        }
+        Ok(None)
+    }
+
+    // checks that `other` computes the same outputs as this circuit for
+    // every input combination. Inputs and states are matched by name,
+    // outputs by label. Returns an input assignment on which they differ,
+    // if any.
+    pub fn check_equivalence(&self, other: &BCircuit) -> Result<Option<Counterexample>, String> {
+        let (pa, pb) = self.matching_ports(other)?;
+        let widths = pa.in_names.iter().map(|n| n.1).collect::<Vec<u8>>();
+        let total_bits = widths.iter().map(|w| *w as u32).sum::<u32>();
+        if total_bits > MAX_EXHAUSTIVE_BITS {
+            return Err(format!(
+                "{} input bits are too many for an exhaustive check, use random_equivalence",
+                total_bits
+            ));
+        }
+        let (mut a, mut b) = (self.pattern_sim()?, other.pattern_sim()?);
+        let total = 1u64 << total_bits;
+        for start in (0..total).step_by(PATTERNS) {
+            let rows = (start..total.min(start + PATTERNS as u64))
+                .map(|n| counter_row(n, &widths))
+                .collect::<Vec<Vec<Word>>>();
+            let res = BCircuit::compare_rows((&mut a, &pa), (&mut b, &pb), &rows)?;
+            if res.is_some() {
+                return Ok(res);
+            }
+        }
+        Ok(None)
+    }
+
+    // like `check_equivalence`, but on `patterns` random input
+    // combinations generated from `seed`
+    pub fn random_equivalence(
+        &self,
+        other: &BCircuit,
+        patterns: usize,
+        seed: u64,
+    ) -> Result<Option<Counterexample>, String> {
+        let (pa, pb) = self.matching_ports(other)?;
+        let (mut a, mut b) = (self.pattern_sim()?, other.pattern_sim()?);
+        // xorshift64, which gets stuck at 0
+        let mut rng = seed.max(1);
+        let mut next = || {
+            rng ^= rng << 13;
+            rng ^= rng >> 7;
+            rng ^= rng << 17;
+            rng
+        };
+        for start in (0..patterns).step_by(PATTERNS) {
+            let rows = (start..patterns.min(start + PATTERNS))
+                .map(|_| {
+                    pa.in_names
+                        .iter()
+                        .map(|(_, w)| Word::from_u64(next(), *w))
+                        .collect()
+                })
+                .collect::<Vec<Vec<Word>>>();
+            let res = BCircuit::compare_rows((&mut a, &pa), (&mut b, &pb), &rows)?;
+            if res.is_some() {
+                return Ok(res);
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        circuit::BCircuit,
+        signal::{
+            Signal::{One, Zero, X, Z},
+            Word,
+        },
+        types::{CLOCK_PIN, ID},
+    };
+
+    use super::Lane;
+
+    #[test]
+    fn lanes() {
+        let mut a = Lane::splat(One);
+        a.set(1, Zero);
+        a.set(2, X);
+        a.set(3, Z);
+        let b = Lane::splat(One);
+        assert_eq!(
+            (0..4).map(|p| a.get(p)).collect::<Vec<_>>(),
+            vec![One, Zero, X, Z]
+        );
+        let and = a.and(b);
+        let xor = a.xor(b);
+        let res = a.resolve(Lane::splat(Z));
+        assert_eq!(
+            (0..4).map(|p| and.get(p)).collect::<Vec<_>>(),
+            vec![One, Zero, X, X]
+        );
+        assert_eq!(
+            (0..4).map(|p| xor.get(p)).collect::<Vec<_>>(),
+            vec![Zero, One, X, X]
+        );
+        assert_eq!(
+            (0..4).map(|p| res.get(p)).collect::<Vec<_>>(),
+            vec![One, Zero, X, Z]
+        );
+        assert_eq!(a.resolve(b).get(1), X);
+    }
+
+    #[test]
+    fn table_matches_interpreter() {
+        // tri-state bus, an unconnected pin, a 2 bit bus and a flip-flop
+        let mut c = BCircuit::new();
+        let a = c.add_input("A", Zero);
+        let e = c.add_input("E", Zero);
+        let v = c.add_input("V", Word::from_u64(0, 2));
+        let clk = c.add_input("clk", Zero);
+        let t = c.add_component("TRI", "").unwrap();
+        let bus = c.add_component("BUS", "D").unwrap();
+        let open = c.add_component("AND", "O").unwrap();
+        let wide = c.add_component_with_width("XOR", "W", 2).unwrap();
+        let ff = c.add_component("JK", "Q").unwrap();
+        c.connect(t, 1, a).unwrap();
+        c.connect(t, 2, e).unwrap();
+        c.connect_driver(bus, t).unwrap();
+        c.connect(open, 1, a).unwrap();
+        c.connect(wide, 1, v).unwrap();
+        c.connect(wide, 2, v).unwrap();
+        c.connect(ff, CLOCK_PIN, clk).unwrap();
+        c.connect(ff, 1, a).unwrap();
+        c.connect(ff, 2, e).unwrap();
+        for id in [bus, open, wide, ff] {
+            c.track_output(id);
+        }
+        c.clock(clk);
+        c.power_on().unwrap();
+
+        let fast = c.gen_bus_table().unwrap();
+        let slow = c.gen_bus_table_serial().unwrap();
+        assert_eq!(fast.columns(), slow.columns());
+        assert_eq!(fast.rows, slow.rows);
+        assert_eq!(fast.rows.len(), 32);
+        assert!(fast.rows.iter().any(|r| r[4] == Word::from(Z)));
+        assert!(fast.rows.iter().any(|r| r[5] == Word::from(X)));
+    }
+
+    // a XOR b from four NANDs, the last one replaced by `last`
+    fn nand_xor(last: &str) -> BCircuit {
+        let mut c = BCircuit::new();
+        let a = c.add_input("A", Zero);
+        let b = c.add_input("B", Zero);
+        let n = c.add_component("NAND", "").unwrap();
+        let na = c.add_component("NAND", "").unwrap();
+        let nb = c.add_component("NAND", "").unwrap();
+        let f = c.add_component(last, "F").unwrap();
+        c.connect(n, 1, a).unwrap();
+        c.connect(n, 2, b).unwrap();
+        c.connect(na, 1, a).unwrap();
+        c.connect(na, 2, n).unwrap();
+        c.connect(nb, 1, n).unwrap();
+        c.connect(nb, 2, b).unwrap();
+        c.connect(f, 1, na).unwrap();
+        c.connect(f, 2, nb).unwrap();
+        c.track_output(f);
+        c.power_on().unwrap();
+        c
+    }
+
+    #[test]
+    fn equivalence() {
+        let mut xor = BCircuit::new();
+        let a = xor.add_input("A", Zero);
+        let b = xor.add_input("B", Zero);
+        let f = xor.add_component("XOR", "F").unwrap();
+        xor.connect(f, 1, a).unwrap();
+        xor.connect(f, 2, b).unwrap();
+        xor.track_output(f);
+        xor.power_on().unwrap();
+
+        assert_eq!(nand_xor("NAND").check_equivalence(&xor), Ok(None));
+        let cex = nand_xor("AND").check_equivalence(&xor).unwrap().unwrap();
+        assert_eq!(
+            cex.inputs,
+            vec![
+                ("A".to_string(), Word::from(Zero)),
+                ("B".to_string(), Word::from(Zero))
+            ]
+        );
+        assert_eq!(cex.to_string(), "A=0, B=0: F is 1 instead of 0");
+
+        xor.set_component_label(f, "G").unwrap();
+        assert!(nand_xor("NAND")
+            .check_equivalence(&xor)
+            .unwrap_err()
+            .contains("Outputs differ"));
+    }
+
+    // 16 bit AND, either as a bus gate or bit by bit
+    fn wide_and(bitwise: bool, wrong_bit: Option<usize>) -> BCircuit {
+        let mut c = BCircuit::new();
+        let a = c.add_input("A", Word::from_u64(0, 16));
+        let b = c.add_input("B", Word::from_u64(0, 16));
+        let f = if bitwise {
+            let sa = c.add_splitter("a", a).unwrap();
+            let sb = c.add_splitter("b", b).unwrap();
+            let m = c.add_merger("F", 16).unwrap();
+            for i in 0..16 {
+                let g = if wrong_bit == Some(i) { "OR" } else { "AND" };
+                let g = c.add_component(g, "").unwrap();
+                c.connect(g, 1, sa[i]).unwrap();
+                c.connect(g, 2, sb[i]).unwrap();
+                c.connect(m, i + 1, g).unwrap();
+            }
+            m
+        } else {
+            let g = c.add_component_with_width("AND", "F", 16).unwrap();
+            c.connect(g, 1, a).unwrap();
+            c.connect(g, 2, b).unwrap();
+            g
+        };
+        c.track_output(f);
+        c.power_on().unwrap();
+        c
+    }
+
+    #[test]
+    fn random_equivalence() {
+        let reference = wide_and(false, None);
+        let ok = wide_and(true, None);
+        assert_eq!(ok.random_equivalence(&reference, 1000, 7), Ok(None));
+        assert!(ok
+            .check_equivalence(&reference)
+            .unwrap_err()
+            .contains("too many"));
+
+        let bad = wide_and(true, Some(9));
+        let cex = bad
+            .random_equivalence(&reference, 1000, 7)
+            .unwrap()
+            .unwrap();
+        let (_, got, want) = cex.outputs[0];
+        assert_eq!(got.bit(9), One);
+        assert_eq!(want.bit(9), Zero);
+        assert_eq!(got.slice(0, 9), want.slice(0, 9));
+    }
+
+    #[test]
+    fn wide_truth_table() {
+        // parity of 16 inputs
+        let mut c = BCircuit::new();
+        let ins = (0..16)
+            .map(|i| c.add_input(&format!("I{:02}", i), Zero))
+            .collect::<Vec<ID>>();
+        let mut acc = ins[0];
+        for i in &ins[1..] {
+            let x = c.add_component("XOR", "").unwrap();
+            c.connect(x, 1, acc).unwrap();
+            c.connect(x, 2, *i).unwrap();
+            acc = x;
+        }
+        c.set_component_label(acc, "P").unwrap();
+        c.track_output(acc);
+        c.power_on().unwrap();
+
+        let t = c.gen_bus_table().unwrap();
+        assert_eq!(t.rows.len(), 1 << 16);
+        for (n, row) in t.rows.iter().enumerate() {
+            assert_eq!(row[16], Word::from(n.count_ones() % 2 == 1));
+        }
+        // the circuit itself is left alone
+        assert_eq!(c.state(ins[15]), Some(Word::from(Zero)));
+    }
+}
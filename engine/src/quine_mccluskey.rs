@@ -1,10 +1,32 @@
-use std::collections::{BTreeMap, HashSet};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt,
+    hash::Hash,
+};
 
-use crate::table::Table;
+use crate::{circuit::BCircuit, table::Table};
 
 const DONT_CARE: char = '_';
+// partial products Petrick's method may keep before falling back to a
+// greedy cover
+pub const MAX_PETRICK_PRODUCTS: usize = 4096;
+// minterms are numbered as u16
+pub const MAX_QM_INPUT_BITS: usize = 16;
 
-pub fn qm_simplify_many(t: &Table<char>, inps: &Vec<&str>, outs: &Vec<&str>) -> Vec<String> {
+// the minterms an implicant covers, the first input being the most
+// significant bit, and its inputs
+type Implicant = (Vec<u16>, Vec<char>);
+
+// don't-care outputs, and inputs standing for both values
+pub(crate) fn is_dont_care(c: char) -> bool {
+    matches!(c, 'X' | 'x' | '-' | DONT_CARE)
+}
+
+pub fn qm_simplify_many(
+    t: &Table<char>,
+    inps: &Vec<&str>,
+    outs: &Vec<&str>,
+) -> Result<Vec<String>, String> {
     // returns simplified expressions of vars in outs (from corresponding
     // column in truth table) in terms of input vars
     return outs
@@ -13,8 +35,345 @@ pub fn qm_simplify_many(t: &Table<char>, inps: &Vec<&str>, outs: &Vec<&str>) ->
         .collect();
 }
 
-pub fn qm_simplify_one(t: &Table<char>, inps: &Vec<&str>, out: &str) -> String {
-    let mut grp = grp_by_ones(t, inps, out);
+pub fn qm_simplify_one(t: &Table<char>, inps: &Vec<&str>, out: &str) -> Result<String, String> {
+    check_input_bits(inps.len())?;
+    let (on, dc) = classify(t, inps, out);
+    let cover = prime_cover(inps.len(), &on, &dc);
+    if cover.is_empty() {
+        return Ok("0".to_string());
+    }
+    Ok(cover
+        .iter()
+        .map(|v| product(inps, v))
+        .collect::<Vec<String>>()
+        .join("+"))
+}
+
+pub fn qm_simplify_many_pos(
+    t: &Table<char>,
+    inps: &Vec<&str>,
+    outs: &Vec<&str>,
+) -> Result<Vec<String>, String> {
+    outs.iter()
+        .map(|out| qm_simplify_one_pos(t, inps, out))
+        .collect()
+}
+
+// a minimal product of sums, e.g. "(A+!B).C", from a minimal sum of
+// products of the zeros of `out`
+pub fn qm_simplify_one_pos(t: &Table<char>, inps: &Vec<&str>, out: &str) -> Result<String, String> {
+    let (on, dc) = classify(t, inps, out);
+    let off = zeros(inps.len(), &on, &dc)?;
+    let cover = prime_cover(inps.len(), &off, &dc);
+    if cover.is_empty() {
+        return Ok("1".to_string());
+    }
+    Ok(cover
+        .iter()
+        .map(|v| {
+            let sum = literal_names(inps, &v.1, true);
+            match sum.len() {
+                0 => "0".to_string(),
+                1 => sum[0].clone(),
+                _ => format!("({})", sum.join("+")),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("."))
+}
+
+// minterms of `n` inputs must fit in a u16
+fn check_input_bits(n: usize) -> Result<(), String> {
+    if n > MAX_QM_INPUT_BITS {
+        return Err(format!(
+            "{} input bits, at most {} can be numbered",
+            n, MAX_QM_INPUT_BITS
+        ));
+    }
+    Ok(())
+}
+
+// the minterms of `n` inputs in neither `on` nor `dc`
+fn zeros(n: usize, on: &BTreeSet<u16>, dc: &BTreeSet<u16>) -> Result<BTreeSet<u16>, String> {
+    check_input_bits(n)?;
+    Ok((0..1u32 << n)
+        .map(|m| m as u16)
+        .filter(|m| !on.contains(m) && !dc.contains(m))
+        .collect())
+}
+
+// the literals of an implicant, complemented for a sum term
+fn literal_names(inps: &[&str], ins: &[char], complement: bool) -> Vec<String> {
+    (0..inps.len())
+        .filter(|i| ins[*i] != DONT_CARE)
+        .map(|i| {
+            let neg = (ins[i] == '0') != complement;
+            format!("{}{}", if neg { "!" } else { "" }, inps[i])
+        })
+        .collect()
+}
+
+// the minterms, maxterms and don't-cares of an output, in ascending
+// order
+#[derive(Clone, Debug, PartialEq)]
+pub struct CanonicalForms {
+    pub minterms: Vec<u16>,
+    pub maxterms: Vec<u16>,
+    pub dont_cares: Vec<u16>,
+}
+
+fn term_list(ms: &[u16]) -> String {
+    ms.iter()
+        .map(|m| m.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+impl CanonicalForms {
+    // e.g. "Σm(1, 3)+d(4)"
+    pub fn sum_of_minterms(&self) -> String {
+        let mut s = format!("Σm({})", term_list(&self.minterms));
+        if !self.dont_cares.is_empty() {
+            s.push_str(&format!("+d({})", term_list(&self.dont_cares)));
+        }
+        s
+    }
+    // e.g. "ΠM(0, 2).d(4)"
+    pub fn product_of_maxterms(&self) -> String {
+        let mut s = format!("ΠM({})", term_list(&self.maxterms));
+        if !self.dont_cares.is_empty() {
+            s.push_str(&format!(".d({})", term_list(&self.dont_cares)));
+        }
+        s
+    }
+}
+
+// minterms are numbered with the first input as the most significant
+// bit, and read like `qm_simplify_one` does
+pub fn canonical_forms(
+    t: &Table<char>,
+    inps: &Vec<&str>,
+    out: &str,
+) -> Result<CanonicalForms, String> {
+    let (on, dc) = classify(t, inps, out);
+    Ok(CanonicalForms {
+        maxterms: zeros(inps.len(), &on, &dc)?.into_iter().collect(),
+        minterms: on.into_iter().collect(),
+        dont_cares: dc.into_iter().collect(),
+    })
+}
+
+impl BCircuit {
+    // the canonical forms of every bit of every tracked output, named
+    // like the columns of `gen_truth_table`
+    pub fn canonical_forms(&mut self) -> Result<Vec<(String, CanonicalForms)>, String> {
+        let (inps, _) = self.ports();
+        let n = self
+            .input_widths(&inps)
+            .iter()
+            .map(|w| *w as usize)
+            .sum::<usize>();
+        check_input_bits(n)?;
+        let t = self.gen_truth_table()?;
+        let cols = t.columns().to_vec();
+        let names = cols[..n].iter().map(|c| c.as_str()).collect::<Vec<&str>>();
+        cols[n..]
+            .iter()
+            .map(|out| Ok((out.clone(), canonical_forms(&t, &names, out)?)))
+            .collect()
+    }
+}
+
+// outputs a shared cover can tag an implicant with
+pub const MAX_SHARED_OUTPUTS: usize = 64;
+
+// a product term of a shared cover and the outputs ORing it
+#[derive(Clone, Debug, PartialEq)]
+pub struct SharedTerm {
+    pub term: String,
+    pub outputs: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MultiOutputCover {
+    pub outputs: Vec<String>,
+    // a sum of products per output, like `qm_simplify_one`'s
+    pub exprs: Vec<String>,
+    // every distinct product term, in order
+    pub terms: Vec<SharedTerm>,
+}
+
+impl MultiOutputCover {
+    pub fn shared_terms(&self) -> Vec<&SharedTerm> {
+        self.terms.iter().filter(|t| t.outputs.len() > 1).collect()
+    }
+}
+
+impl fmt::Display for MultiOutputCover {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (out, exp) in self.outputs.iter().zip(&self.exprs) {
+            writeln!(f, "{} = {}", out, exp)?;
+        }
+        let shared = self.shared_terms();
+        writeln!(f, "{} terms, {} shared", self.terms.len(), shared.len())?;
+        for t in shared {
+            writeln!(f, "{}: {}", t.term, t.outputs.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+fn product(inps: &[&str], imp: &Implicant) -> String {
+    let exp = literal_names(inps, &imp.1, false);
+    if exp.is_empty() {
+        return "1".to_string();
+    }
+    exp.join(".")
+}
+
+// minimizes `outs` together: implicants are tagged with the outputs they
+// are an implicant of, and the cover picked has the fewest distinct
+// product terms, then literals, a term ORed into several outputs
+// counting once. Each output then drops the terms it doesn't need.
+pub fn qm_simplify_shared(
+    t: &Table<char>,
+    inps: &Vec<&str>,
+    outs: &Vec<&str>,
+) -> Result<MultiOutputCover, String> {
+    if outs.len() > MAX_SHARED_OUTPUTS {
+        return Err(format!(
+            "{} outputs, at most {} can share terms",
+            outs.len(),
+            MAX_SHARED_OUTPUTS
+        ));
+    }
+    check_input_bits(inps.len())?;
+    let (ons, dcs): (Vec<_>, Vec<_>) = outs.iter().map(|out| classify(t, inps, out)).unzip();
+    let mut primes = tagged_primes(inps.len(), &ons, &dcs);
+    primes.sort();
+    let (imps, tags): (Vec<Implicant>, Vec<u64>) = primes.into_iter().unzip();
+    let elems = ons
+        .iter()
+        .enumerate()
+        .flat_map(|(k, on)| on.iter().map(move |m| (k, *m)))
+        .collect::<Vec<(usize, u16)>>();
+    let covering = |(k, m): &(usize, u16)| {
+        (0..imps.len())
+            .filter(|i| tags[*i] >> k & 1 == 1 && imps[*i].0.contains(m))
+            .collect::<Vec<usize>>()
+    };
+    let chosen = select_primes(&imps, &elems, covering);
+
+    let mut used = BTreeMap::<usize, Vec<String>>::new();
+    let mut exprs = Vec::new();
+    for (k, on) in ons.iter().enumerate() {
+        let mut terms = chosen
+            .iter()
+            .cloned()
+            .filter(|i| tags[*i] >> k & 1 == 1)
+            .collect::<Vec<usize>>();
+        // drop the terms whose minterms the others cover, most literals
+        // first
+        let mut by_literals = terms.clone();
+        by_literals.sort_by_key(|i| std::cmp::Reverse(literals(&imps[*i])));
+        for i in by_literals {
+            let needed = imps[i].0.iter().any(|m| {
+                on.contains(m) && !terms.iter().any(|j| *j != i && imps[*j].0.contains(m))
+            });
+            if !needed {
+                terms.retain(|j| *j != i);
+            }
+        }
+        for i in &terms {
+            used.entry(*i).or_default().push(outs[k].to_string());
+        }
+        if terms.is_empty() {
+            exprs.push("0".to_string());
+            continue;
+        }
+        exprs.push(
+            terms
+                .iter()
+                .map(|i| product(inps, &imps[*i]))
+                .collect::<Vec<String>>()
+                .join("+"),
+        );
+    }
+    Ok(MultiOutputCover {
+        outputs: outs.iter().map(|o| o.to_string()).collect(),
+        exprs,
+        terms: used
+            .into_iter()
+            .map(|(i, outputs)| SharedTerm {
+                term: product(inps, &imps[i]),
+                outputs,
+            })
+            .collect(),
+    })
+}
+
+// the multiple output prime implicants of the on-sets `ons` with the
+// don't-cares `dcs`: bit k of the tag of an implicant is set if it only
+// covers 1s and don't-cares of output k. An implicant is prime unless
+// merging it into a larger one keeps its tag.
+fn tagged_primes(n: usize, ons: &[BTreeSet<u16>], dcs: &[BTreeSet<u16>]) -> Vec<(Implicant, u64)> {
+    let tag_of = |m: &u16| {
+        (0..ons.len())
+            .filter(|k| ons[*k].contains(m) || dcs[*k].contains(m))
+            .fold(0u64, |t, k| t | 1 << k)
+    };
+    let mut level = ons
+        .iter()
+        .chain(dcs)
+        .flatten()
+        .cloned()
+        .collect::<BTreeSet<u16>>()
+        .into_iter()
+        .map(|m| {
+            let irow = (0..n)
+                .map(|i| if m >> (n - 1 - i) & 1 == 1 { '1' } else { '0' })
+                .collect();
+            ((vec![m], irow), tag_of(&m))
+        })
+        .collect::<Vec<(Implicant, u64)>>();
+    let mut primes = Vec::new();
+    while !level.is_empty() {
+        let ones = |imp: &Implicant| imp.1.iter().filter(|c| **c == '1').count();
+        let mut merged = vec![false; level.len()];
+        let mut next = BTreeMap::<Vec<char>, (Vec<u16>, u64)>::new();
+        for i in 0..level.len() {
+            for j in 0..level.len() {
+                let ((a, ta), (b, tb)) = (&level[i], &level[j]);
+                let tag = ta & tb;
+                if ones(b) != ones(a) + 1 || tag == 0 || !differ_by_one_entry(&a.1, &b.1) {
+                    continue;
+                }
+                merged[i] |= tag == *ta;
+                merged[j] |= tag == *tb;
+                let mut ms = a.0.iter().chain(&b.0).cloned().collect::<Vec<u16>>();
+                ms.sort();
+                next.insert(collate_rows(&a.1, &b.1), (ms, tag));
+            }
+        }
+        primes.extend(
+            level
+                .into_iter()
+                .zip(merged)
+                .filter(|(_, m)| !m)
+                .map(|(p, _)| p),
+        );
+        level = next
+            .into_iter()
+            .map(|(irow, (ms, tag))| ((ms, irow), tag))
+            .collect();
+    }
+    primes
+}
+
+// the prime implicants to OR for the minterms `on`, free to cover `dc`,
+// sorted
+fn prime_cover(n: usize, on: &BTreeSet<u16>, dc: &BTreeSet<u16>) -> Vec<Implicant> {
+    let mut grp = grp_by_ones(n, on, dc);
     let mut unpaired = HashSet::<(u16, usize)>::new();
     let mut prime_implicants = HashSet::new();
 
@@ -67,28 +426,124 @@ pub fn qm_simplify_one(t: &Table<char>, inps: &Vec<&str>, out: &str) -> String {
         }
         grp = nxt_grp_table;
     }
-    //todo: find essential prime implicants
+    let minterms = on.iter().cloned().collect::<Vec<u16>>();
+    let mut cover = min_cover(prime_implicants.into_iter().collect(), &minterms);
+    cover.sort();
+    cover
+}
 
-    let mut simplified_exp = String::new();
-    prime_implicants
+fn literals(imp: &Implicant) -> usize {
+    imp.1.iter().filter(|c| **c != DONT_CARE).count()
+}
+
+// picks the primes to OR: the essential ones, alone covering some
+// minterm, then a cover of the remaining minterms with the fewest terms
+// and then literals, found by Petrick's method. Past
+// MAX_PETRICK_PRODUCTS partial products the rest is covered greedily
+// instead, each time by the prime covering most minterms left, with the
+// fewest literals on ties.
+fn min_cover(mut primes: Vec<Implicant>, minterms: &[u16]) -> Vec<Implicant> {
+    primes.sort();
+    let covering = |m: &u16| {
+        (0..primes.len())
+            .filter(|i| primes[*i].0.contains(m))
+            .collect::<Vec<usize>>()
+    };
+    select_primes(&primes, minterms, covering)
+        .into_iter()
+        .map(|i| primes[i].clone())
+        .collect()
+}
+
+// `min_cover` for any kind of element to cover, `covering` listing the
+// primes covering one
+fn select_primes<T: Copy + Eq + Hash>(
+    primes: &[Implicant],
+    elems: &[T],
+    covering: impl Fn(&T) -> Vec<usize>,
+) -> BTreeSet<usize> {
+    let mut chosen = BTreeSet::new();
+    for e in elems {
+        if let [i] = covering(e)[..] {
+            chosen.insert(i);
+        }
+    }
+    let left = elems
         .iter()
-        .map(|v| {
-            let mut exp = Vec::new();
-            let ins = &v.1;
-            for i in 0..inps.len() {
-                if ins[i] == DONT_CARE {
-                    continue;
-                }
-                exp.push(format!(
-                    "{}{}",
-                    if ins[i] == '0' { "!" } else { "" },
-                    inps[i]
-                ));
+        .filter(|e| !covering(e).iter().any(|i| chosen.contains(i)))
+        .cloned()
+        .collect::<Vec<T>>();
+    let rest = match petrick(primes, &left, &covering) {
+        Some(rest) => rest,
+        None => greedy_cover(primes, &left, &covering),
+    };
+    chosen.extend(rest);
+    chosen
+}
+
+// multiplies out the product of sums "some prime covering e" over the
+// elements, dropping products that contain another
+fn petrick<T>(
+    primes: &[Implicant],
+    elems: &[T],
+    covering: impl Fn(&T) -> Vec<usize>,
+) -> Option<Vec<usize>> {
+    let mut products = vec![BTreeSet::<usize>::new()];
+    for e in elems {
+        let sum = covering(e);
+        let mut next = Vec::new();
+        for p in &products {
+            if sum.iter().any(|i| p.contains(i)) {
+                next.push(p.clone());
+                continue;
+            }
+            for i in &sum {
+                let mut q = p.clone();
+                q.insert(*i);
+                next.push(q);
             }
-            exp.join(".")
+        }
+        next.sort_by_key(|p| p.len());
+        products.clear();
+        for p in next {
+            if !products.iter().any(|k| k.is_subset(&p)) {
+                products.push(p);
+            }
+        }
+        if products.len() > MAX_PETRICK_PRODUCTS {
+            return None;
+        }
+    }
+    products
+        .into_iter()
+        .min_by_key(|p| {
+            (
+                p.len(),
+                p.iter().map(|i| literals(&primes[*i])).sum::<usize>(),
+            )
         })
-        .collect::<Vec<String>>()
-        .join("+")
+        .map(|p| p.into_iter().collect())
+}
+
+fn greedy_cover<T: Copy + Eq + Hash>(
+    primes: &[Implicant],
+    elems: &[T],
+    covering: impl Fn(&T) -> Vec<usize>,
+) -> Vec<usize> {
+    let mut left = elems
+        .iter()
+        .map(|e| (*e, covering(e)))
+        .collect::<HashMap<T, Vec<usize>>>();
+    let mut chosen = Vec::new();
+    while !left.is_empty() {
+        let gain = |i: usize| left.values().filter(|sum| sum.contains(&i)).count();
+        let best = (0..primes.len())
+            .max_by_key(|i| (gain(*i), usize::MAX - literals(&primes[*i])))
+            .unwrap();
+        left.retain(|_, sum| !sum.contains(&best));
+        chosen.push(best);
+    }
+    chosen
 }
 
 fn form_nxt_table_grp_from_rows(
@@ -152,41 +607,284 @@ fn collate_rows(a: &Vec<char>, b: &Vec<char>) -> Vec<char> {
     k
 }
 
-fn grp_by_ones(
-    t: &Table<char>,
-    inps: &Vec<&str>,
-    out: &str,
-) -> BTreeMap<u16, Vec<(Vec<u16>, Vec<char>)>> {
-    let mut grps = BTreeMap::<u16, Vec<(Vec<u16>, Vec<char>)>>::new();
-    // for each row where output is 1, put it in the group corresp to number of 1's
-    // in its inputs
-    for i in 0..t.rows.len() {
-        let tval = t.get_val_at(i, out);
-        if *tval == '1' {
-            let num_ones = inps.iter().fold(0, |v, w| {
-                let tval = t.get_val_at(i, *w);
-                if *tval == '1' {
-                    v + 1
-                } else {
-                    v
+// the minterms whose output is 1 and the ones whose output is a
+// don't-care. Inputs that are don't-cares stand for both values, rows
+// with an output of 0 and unlisted minterms are 0, and a minterm listed
+// both as a 1 and as a don't-care is a 1.
+fn classify(t: &Table<char>, inps: &Vec<&str>, out: &str) -> (BTreeSet<u16>, BTreeSet<u16>) {
+    let mut on = BTreeSet::new();
+    let mut dont_cares = BTreeSet::new();
+    'rows: for i in 0..t.rows.len() {
+        let set = match *t.get_val_at(i, out) {
+            '1' => &mut on,
+            c if is_dont_care(c) => &mut dont_cares,
+            _ => continue,
+        };
+        let mut minterms = vec![0u16];
+        for w in inps {
+            minterms = match *t.get_val_at(i, w) {
+                '0' => minterms.iter().map(|m| m << 1).collect(),
+                '1' => minterms.iter().map(|m| m << 1 | 1).collect(),
+                c if is_dont_care(c) => {
+                    minterms.iter().flat_map(|m| [m << 1, m << 1 | 1]).collect()
                 }
-            });
-            let irow = inps.iter().map(|v| t.get_val_at(i, *v).clone()).collect();
-            if !grps.contains_key(&num_ones) {
-                grps.insert(num_ones, Vec::new());
-            }
-            grps.get_mut(&num_ones)
-                .unwrap()
-                .push((vec![i as u16], irow));
+                _ => continue 'rows,
+            };
         }
+        set.extend(minterms);
+    }
+    let dont_cares = dont_cares.difference(&on).cloned().collect();
+    (on, dont_cares)
+}
+
+// groups the minterms `on` and `dc` of `n` inputs by their number of
+// ones
+fn grp_by_ones(n: usize, on: &BTreeSet<u16>, dc: &BTreeSet<u16>) -> BTreeMap<u16, Vec<Implicant>> {
+    let mut grps = BTreeMap::<u16, Vec<Implicant>>::new();
+    for m in on.union(dc) {
+        let irow = (0..n)
+            .map(|i| if m >> (n - 1 - i) & 1 == 1 { '1' } else { '0' })
+            .collect();
+        grps.entry(m.count_ones() as u16)
+            .or_default()
+            .push((vec![*m], irow));
     }
     grps
 }
 
-mod tests {
-    use crate::table::Table;
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::{circuit::BCircuit, table::Table};
+
+    use super::{
+        canonical_forms, greedy_cover, qm_simplify_many, qm_simplify_many_pos, qm_simplify_one,
+        qm_simplify_one_pos, qm_simplify_shared,
+    };
+    use crate::signal::Signal::Zero;
+
+    fn bit(m: u16, i: usize) -> char {
+        if m >> (2 - i) & 1 == 1 {
+            '1'
+        } else {
+            '0'
+        }
+    }
+
+    // value of a sum of products for the input values `vals`
+    fn eval_sop(exp: &str, inps: &[&str], vals: &[char]) -> char {
+        let lit = |l: &str| {
+            let (neg, name) = match l.strip_prefix('!') {
+                Some(n) => (true, n),
+                None => (false, l),
+            };
+            let i = inps.iter().position(|v| *v == name).unwrap();
+            (vals[i] == '1') != neg
+        };
+        let one = exp
+            .split('+')
+            .any(|t| t == "1" || (t != "0" && t.split('.').all(lit)));
+        if one {
+            '1'
+        } else {
+            '0'
+        }
+    }
 
-    use super::qm_simplify_many;
+    fn table(inps: &[&str], out: &str, f: impl Fn(usize) -> bool) -> Table<char> {
+        let mut t = Table::<char>::new();
+        let mut cols = inps.iter().map(|v| v.to_string()).collect::<Vec<String>>();
+        cols.push(out.to_string());
+        t.set_columns(cols).unwrap();
+        let n = inps.len();
+        for m in 0..1 << n {
+            let mut row = (0..n)
+                .map(|i| if m >> (n - 1 - i) & 1 == 1 { '1' } else { '0' })
+                .collect::<Vec<char>>();
+            row.push(if f(m) { '1' } else { '0' });
+            t.rows.push(row);
+        }
+        t
+    }
+
+    // segments a..g lit for the BCD digits 0 to 9
+    pub(crate) const SEVEN_SEGMENT_ON: [&str; 10] = [
+        "1111110", "0110000", "1101101", "1111001", "0110011", "1011011", "1011111", "1110000",
+        "1111111", "1111011",
+    ];
+
+    // BCD to seven segment over inputs A B C D, outputs a..g. Rows 10 to 15
+    // are don't-cares if `with_dont_cares`, otherwise left out.
+    pub(crate) fn seven_segment(with_dont_cares: bool) -> Table<char> {
+        let mut t = Table::<char>::new();
+        let cols = ["A", "B", "C", "D", "a", "b", "c", "d", "e", "f", "g"];
+        t.set_columns(cols.map(|v| v.to_string()).to_vec()).unwrap();
+        let last = if with_dont_cares { 16 } else { 10 };
+        for m in 0..last {
+            let mut row = (0..4)
+                .map(|i| if m >> (3 - i) & 1 == 1 { '1' } else { '0' })
+                .collect::<Vec<char>>();
+            match SEVEN_SEGMENT_ON.get(m) {
+                Some(outs) => row.extend(outs.chars()),
+                None => row.extend(['X'; 7]),
+            }
+            t.rows.push(row);
+        }
+        t
+    }
+
+    // a single row table over `inps`, F being 1 when they all are
+    fn one_row(inps: &[String]) -> Table<char> {
+        let mut t = Table::<char>::new();
+        let mut cols = inps.to_vec();
+        cols.push("F".into());
+        t.set_columns(cols).unwrap();
+        t.rows.push(vec!['1'; inps.len() + 1]);
+        t
+    }
+
+    fn check(t: &Table<char>, inps: &[&str], out: &str, exp: &str) {
+        for row in &t.rows {
+            let vals = inps
+                .iter()
+                .map(|v| row[t.columns().iter().position(|c| c == v).unwrap()]);
+            let vals = vals.collect::<Vec<char>>();
+            let expected = row[t.columns().iter().position(|c| c == out).unwrap()];
+            if expected == 'X' {
+                continue;
+            }
+            assert_eq!(
+                eval_sop(exp, inps, &vals),
+                expected,
+                "{} at {:?}",
+                exp,
+                vals
+            );
+        }
+    }
+
+    #[test]
+    fn minimal_cover() {
+        let inps = ["A", "B", "C"];
+        // cyclic: 6 primes, none essential, 3 needed
+        let t = table(&inps, "F", |m| [0, 1, 2, 5, 6, 7].contains(&m));
+        let exp = qm_simplify_one(&t, &inps.to_vec(), "F").unwrap();
+        check(&t, &inps, "F", &exp);
+        assert_eq!(exp.split('+').count(), 3);
+        assert_eq!(exp.matches('.').count(), 3);
+
+        // the consensus term B.C is redundant
+        let t = table(&inps, "F", |m| [1, 3, 6, 7].contains(&m));
+        let exp = qm_simplify_one(&t, &inps.to_vec(), "F").unwrap();
+        assert_eq!(exp, "!A.C+A.B");
+
+        let inps = ["A", "B", "C", "D"];
+        let t = table(&inps, "F", |m| {
+            [0, 2, 5, 6, 7, 8, 10, 12, 13, 14, 15].contains(&m)
+        });
+        let exp = qm_simplify_one(&t, &inps.to_vec(), "F").unwrap();
+        check(&t, &inps, "F", &exp);
+        // !B.!D and B.D are essential, 6 and 12 need one more term each
+        assert_eq!(exp.split('+').count(), 4);
+
+        // the greedy fallback still covers everything
+        let primes = ["00_", "0_0", "_01", "_10", "1_1", "11_"]
+            .map(|p| {
+                let ins = p.chars().collect::<Vec<char>>();
+                let ms: Vec<u16> = (0..8u16)
+                    .filter(|m| (0..3).all(|i| ins[i] == '_' || ins[i] == bit(*m, i)))
+                    .collect();
+                (ms, ins)
+            })
+            .to_vec();
+        let covering = |m: &u16| (0..6).filter(|i| primes[*i].0.contains(m)).collect();
+        let cover = greedy_cover(&primes, &[0, 1, 2, 5, 6, 7], covering);
+        for m in [0, 1, 2, 5, 6, 7] {
+            assert!(cover.iter().any(|i| primes[*i].0.contains(&m)));
+        }
+        assert!(cover.len() <= 4);
+
+        let t = table(&inps, "F", |_| true);
+        assert_eq!(qm_simplify_one(&t, &inps.to_vec(), "F").unwrap(), "1");
+        let t = table(&inps, "F", |_| false);
+        assert_eq!(qm_simplify_one(&t, &inps.to_vec(), "F").unwrap(), "0");
+    }
+
+    #[test]
+    fn product_of_sums() {
+        let inps = ["A", "B", "C"];
+        let t = table(&inps, "F", |m| [1, 3, 6, 7].contains(&m));
+        assert_eq!(
+            qm_simplify_one_pos(&t, &inps.to_vec(), "F").unwrap(),
+            "(A+C).(!A+B)"
+        );
+        // single literal sums need no parentheses
+        let t = table(&inps, "F", |m| m >= 5);
+        assert_eq!(
+            qm_simplify_one_pos(&t, &inps.to_vec(), "F").unwrap(),
+            "A.(B+C)"
+        );
+        let t = table(&inps, "F", |_| true);
+        assert_eq!(qm_simplify_one_pos(&t, &inps.to_vec(), "F").unwrap(), "1");
+        let t = table(&inps, "F", |_| false);
+        assert_eq!(qm_simplify_one_pos(&t, &inps.to_vec(), "F").unwrap(), "0");
+
+        // don't-cares are used for the zeros too, unlisted rows are 0
+        let mut t = Table::<char>::new();
+        t.set_columns(vec!["A".into(), "B".into(), "F".into(), "G".into()])
+            .unwrap();
+        t.rows = vec![
+            vec!['0', '0', '1', '0'],
+            vec!['0', '1', 'X', '1'],
+            vec!['1', '0', '0', '1'],
+        ];
+        assert_eq!(
+            qm_simplify_many_pos(&t, &vec!["A", "B"], &vec!["F", "G"]).unwrap(),
+            ["!A", "(A+B).(!A+!B)"]
+        );
+
+        let forms = canonical_forms(&t, &vec!["A", "B"], "F").unwrap();
+        assert_eq!(forms.minterms, [0]);
+        assert_eq!(forms.maxterms, [2, 3]);
+        assert_eq!(forms.dont_cares, [1]);
+        assert_eq!(forms.sum_of_minterms(), "Σm(0)+d(1)");
+        assert_eq!(forms.product_of_maxterms(), "ΠM(2, 3).d(1)");
+        let forms = canonical_forms(&t, &vec!["A", "B"], "G").unwrap();
+        assert_eq!(forms.sum_of_minterms(), "Σm(1, 2)");
+        assert_eq!(forms.product_of_maxterms(), "ΠM(0, 3)");
+
+        // past 16 inputs maxterms can't be numbered
+        let names = (0..17).map(|i| format!("I{}", i)).collect::<Vec<String>>();
+        let (t, inps) = (one_row(&names), names.iter().map(|v| v.as_str()).collect());
+        assert!(canonical_forms(&t, &inps, "F").is_err());
+        assert!(qm_simplify_one_pos(&t, &inps, "F").is_err());
+    }
+
+    #[test]
+    fn circuit_canonical_forms() {
+        // half adder, plus a 2 bit output
+        let mut c = BCircuit::new();
+        let a = c.add_input("A", Zero);
+        let b = c.add_input("B", Zero);
+        let s = c.add_component("XOR", "S").unwrap();
+        let carry = c.add_component("AND", "C").unwrap();
+        for g in [s, carry] {
+            c.connect(g, 1, a).unwrap();
+            c.connect(g, 2, b).unwrap();
+            c.track_output(g);
+        }
+        let sum = c.add_merger("N", 2).unwrap();
+        c.connect(sum, 1, s).unwrap();
+        c.connect(sum, 2, carry).unwrap();
+        c.track_output(sum);
+        let forms = c.canonical_forms().unwrap();
+        let names = forms.iter().map(|f| f.0.as_str()).collect::<Vec<&str>>();
+        assert_eq!(names, ["C", "N[1]", "N[0]", "S"]);
+        assert_eq!(forms[0].1.sum_of_minterms(), "Σm(3)");
+        assert_eq!(forms[0].1.product_of_maxterms(), "ΠM(0, 1, 2)");
+        assert_eq!(forms[3].1.sum_of_minterms(), "Σm(1, 2)");
+        assert_eq!(forms[1].1, forms[0].1);
+        assert_eq!(forms[2].1, forms[3].1);
+    }
 
     #[test]
     fn qm() {
@@ -213,9 +911,99 @@ mod tests {
 
         tt.set_rows(rows).unwrap();
         let outs = vec!["a", "b", "c", "d", "e", "f", "g"];
-        let res = qm_simplify_many(&tt, &vec!["A", "B", "C", "D"], &outs);
+        let res = qm_simplify_many(&tt, &vec!["A", "B", "C", "D"], &outs).unwrap();
         for i in 0..outs.len() {
             println!("{} = {};", outs[i], res[i]);
         }
+        for (out, exp) in outs.iter().zip(&res) {
+            check(&tt, &["A", "B", "C", "D"], out, exp);
+        }
+    }
+
+    #[test]
+    fn dont_cares() {
+        // BCD to seven segment, 10 to 15 never occur
+        let inps = ["A", "B", "C", "D"];
+        let segs = ["a", "b", "c", "d", "e", "f", "g"];
+        let t = seven_segment(true);
+        let res = qm_simplify_many(&t, &inps.to_vec(), &segs.to_vec()).unwrap();
+        for (out, exp) in segs.iter().zip(&res) {
+            check(&t, &inps, out, exp);
+        }
+        assert_eq!(res[0], "!B.!D+C+B.D+A");
+        assert_eq!(res[1], "!B+!C.!D+C.D");
+
+        // partially specified rows: F is 1 for 1--, a don't-care for 01-
+        let mut t = Table::<char>::new();
+        t.set_columns(["A", "B", "C", "F"].map(|v| v.to_string()).to_vec())
+            .unwrap();
+        t.rows.push("1--1".chars().collect());
+        t.rows.push("01XX".chars().collect());
+        t.rows.push("0010".chars().collect());
+        let inps = ["A", "B", "C"];
+        assert_eq!(qm_simplify_one(&t, &inps.to_vec(), "F").unwrap(), "A");
+        // the don't-cares help: 011 joins A.B.C into B.C
+        t.rows[0] = "1111".chars().collect();
+        assert_eq!(qm_simplify_one(&t, &inps.to_vec(), "F").unwrap(), "B.C");
+        // a row with unknown inputs is skipped
+        t.rows.push("Z001".chars().collect());
+        assert_eq!(qm_simplify_one(&t, &inps.to_vec(), "F").unwrap(), "B.C");
+
+        // past 16 inputs minterms can't be numbered
+        let names = (0..17).map(|i| format!("I{}", i)).collect::<Vec<String>>();
+        let (t, inps) = (one_row(&names), names.iter().map(|v| v.as_str()).collect());
+        assert!(qm_simplify_one(&t, &inps, "F").is_err());
+        assert!(qm_simplify_many(&t, &inps, &vec!["F"]).is_err());
+    }
+
+    #[test]
+    fn shared_terms() {
+        // alone F = !A.B+B.C, sharing A.B.C with G saves a term
+        let inps = ["A", "B", "C"];
+        let mut t = table(&inps, "F", |m| [2, 3, 7].contains(&m));
+        t.set_columns(["A", "B", "C", "F", "G"].map(|v| v.to_string()).to_vec())
+            .unwrap();
+        for (m, row) in t.rows.iter_mut().enumerate() {
+            row.push(if m == 7 { '1' } else { '0' });
+        }
+        let cover = qm_simplify_shared(&t, &inps.to_vec(), &vec!["F", "G"]).unwrap();
+        assert_eq!(cover.exprs, ["!A.B+A.B.C", "A.B.C"]);
+        assert_eq!(cover.terms.len(), 2);
+        let shared = cover.shared_terms();
+        assert_eq!(shared.len(), 1);
+        assert_eq!(shared[0].term, "A.B.C");
+        assert_eq!(shared[0].outputs, ["F", "G"]);
+        assert_eq!(
+            cover.to_string(),
+            "F = !A.B+A.B.C\nG = A.B.C\n2 terms, 1 shared\nA.B.C: F G\n"
+        );
+        // one output past MAX_SHARED_OUTPUTS is an error, not a panic
+        assert!(qm_simplify_shared(&t, &inps.to_vec(), &vec!["F"; 65]).is_err());
+        let names = (0..17).map(|i| format!("I{}", i)).collect::<Vec<String>>();
+        let many = names.iter().map(|v| v.as_str()).collect();
+        assert!(qm_simplify_shared(&one_row(&names), &many, &vec!["F"]).is_err());
+
+        // BCD to seven segment
+        let inps = ["A", "B", "C", "D"];
+        let segs = ["a", "b", "c", "d", "e", "f", "g"];
+        let t = seven_segment(false);
+        let cover = qm_simplify_shared(&t, &inps.to_vec(), &segs.to_vec()).unwrap();
+        for (out, exp) in segs.iter().zip(&cover.exprs) {
+            check(&t, &inps, out, exp);
+        }
+        let alone = qm_simplify_many(&t, &inps.to_vec(), &segs.to_vec())
+            .unwrap()
+            .iter()
+            .flat_map(|e| e.split('+').map(|t| t.to_string()).collect::<Vec<String>>())
+            .collect::<std::collections::BTreeSet<String>>();
+        assert!(cover.terms.len() < alone.len(), "{}", cover);
+        assert!(!cover.shared_terms().is_empty());
+        // every term is listed with the outputs ORing it
+        for term in &cover.terms {
+            for (out, exp) in segs.iter().zip(&cover.exprs) {
+                let ors = exp.split('+').any(|t| t == term.term);
+                assert_eq!(ors, term.outputs.contains(&out.to_string()));
+            }
+        }
     }
 }
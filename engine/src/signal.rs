@@ -0,0 +1,412 @@
+use std::{fmt, ops::Not};
+
+// Four valued logic level carried by every pin and component state.
+// `X` is an unknown (uninitialised, conflicting or unresolved) value,
+// `Z` is high impedance, i.e. nothing drives the net. Gate inputs
+// read `Z` as `X`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum Signal {
+    Zero,
+    One,
+    #[default]
+    X,
+    Z,
+}
+
+impl Signal {
+    pub fn is_high(self) -> bool {
+        self == Signal::One
+    }
+    pub fn is_low(self) -> bool {
+        self == Signal::Zero
+    }
+    pub fn is_known(self) -> bool {
+        self == Signal::Zero || self == Signal::One
+    }
+    // the value seen by a gate input: an undriven net is unknown
+    pub fn read(self) -> Signal {
+        if self == Signal::Z {
+            Signal::X
+        } else {
+            self
+        }
+    }
+    pub fn and(self, other: Signal) -> Signal {
+        match (self.read(), other.read()) {
+            (Signal::Zero, _) | (_, Signal::Zero) => Signal::Zero,
+            (Signal::One, Signal::One) => Signal::One,
+            _ => Signal::X,
+        }
+    }
+    pub fn or(self, other: Signal) -> Signal {
+        match (self.read(), other.read()) {
+            (Signal::One, _) | (_, Signal::One) => Signal::One,
+            (Signal::Zero, Signal::Zero) => Signal::Zero,
+            _ => Signal::X,
+        }
+    }
+    pub fn xor(self, other: Signal) -> Signal {
+        match (self.read(), other.read()) {
+            (Signal::X, _) | (_, Signal::X) => Signal::X,
+            (a, b) => Signal::from(a != b),
+        }
+    }
+    // picks `a` if `sel` is 0 and `b` if it is 1. With an unknown
+    // select, the result is known only if both choices agree.
+    pub fn mux(sel: Signal, a: Signal, b: Signal) -> Signal {
+        match sel.read() {
+            Signal::Zero => a.read(),
+            Signal::One => b.read(),
+            _ if a.read() == b.read() => a.read(),
+            _ => Signal::X,
+        }
+    }
+    pub fn to_char(self) -> char {
+        match self {
+            Signal::Zero => '0',
+            Signal::One => '1',
+            Signal::X => 'X',
+            Signal::Z => 'Z',
+        }
+    }
+    pub fn from_char(c: char) -> Option<Signal> {
+        match c {
+            '0' => Some(Signal::Zero),
+            '1' => Some(Signal::One),
+            'X' | 'x' => Some(Signal::X),
+            'Z' | 'z' => Some(Signal::Z),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Signal {
+    fn from(b: bool) -> Self {
+        if b {
+            Signal::One
+        } else {
+            Signal::Zero
+        }
+    }
+}
+
+impl Not for Signal {
+    type Output = Signal;
+    fn not(self) -> Signal {
+        match self.read() {
+            Signal::Zero => Signal::One,
+            Signal::One => Signal::Zero,
+            _ => Signal::X,
+        }
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_char())
+    }
+}
+
+// Value of an N bit bus (1 <= N <= 64), every bit carrying a `Signal`.
+// Bits are stored packed: `x` and `z` mark unknown and floating bits,
+// `val` holds the remaining known bits. Bit 0 is the least significant.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Word {
+    width: u8,
+    val: u64,
+    x: u64,
+    z: u64,
+}
+
+pub const MAX_WIDTH: u8 = 64;
+
+fn width_mask(width: u8) -> u64 {
+    if width >= MAX_WIDTH {
+        u64::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
+
+impl Word {
+    pub fn from_u64(val: u64, width: u8) -> Word {
+        assert!(width > 0 && width <= MAX_WIDTH, "bad bus width {}", width);
+        Word {
+            width,
+            val: val & width_mask(width),
+            x: 0,
+            z: 0,
+        }
+    }
+    pub fn splat(s: Signal, width: u8) -> Word {
+        let mut w = Word::from_u64(0, width);
+        let m = width_mask(width);
+        match s {
+            Signal::Zero => {}
+            Signal::One => w.val = m,
+            Signal::X => w.x = m,
+            Signal::Z => w.z = m,
+        }
+        w
+    }
+    pub fn unknown(width: u8) -> Word {
+        Word::splat(Signal::X, width)
+    }
+    pub fn hiz(width: u8) -> Word {
+        Word::splat(Signal::Z, width)
+    }
+    // builds a word from bit values, least significant first
+    pub fn from_bits(bits: &[Signal]) -> Word {
+        Word::from_fn(bits.len() as u8, |i| bits[i])
+    }
+    pub fn from_fn(width: u8, f: impl Fn(usize) -> Signal) -> Word {
+        let mut w = Word::from_u64(0, width);
+        for i in 0..width as usize {
+            w.set_bit(i, f(i));
+        }
+        w
+    }
+    pub fn width(&self) -> u8 {
+        self.width
+    }
+    pub fn bit(&self, i: usize) -> Signal {
+        let b = 1 << i;
+        if self.z & b != 0 {
+            Signal::Z
+        } else if self.x & b != 0 {
+            Signal::X
+        } else {
+            Signal::from(self.val & b != 0)
+        }
+    }
+    pub fn set_bit(&mut self, i: usize, s: Signal) {
+        let b = 1 << i;
+        self.val &= !b;
+        self.x &= !b;
+        self.z &= !b;
+        match s {
+            Signal::Zero => {}
+            Signal::One => self.val |= b,
+            Signal::X => self.x |= b,
+            Signal::Z => self.z |= b,
+        }
+    }
+    pub fn bits(&self) -> Vec<Signal> {
+        (0..self.width as usize).map(|i| self.bit(i)).collect()
+    }
+    // numeric value if no bit is unknown or floating
+    pub fn to_u64(&self) -> Option<u64> {
+        if self.is_known() {
+            Some(self.val)
+        } else {
+            None
+        }
+    }
+    pub fn is_known(&self) -> bool {
+        self.x | self.z == 0
+    }
+    // known and non zero, i.e. logic high for a single bit
+    pub fn is_high(&self) -> bool {
+        self.is_known() && self.val != 0
+    }
+    pub(crate) fn ones(&self) -> u64 {
+        self.val & !(self.x | self.z)
+    }
+    pub(crate) fn zeros(&self) -> u64 {
+        !self.val & !(self.x | self.z) & width_mask(self.width)
+    }
+    fn from_masks(width: u8, ones: u64, zeros: u64) -> Word {
+        let m = width_mask(width);
+        Word {
+            width,
+            val: ones & m,
+            x: m & !(ones | zeros),
+            z: 0,
+        }
+    }
+    // the value seen by gate inputs, floating bits read as unknown
+    pub fn read(&self) -> Word {
+        Word {
+            width: self.width,
+            val: self.val,
+            x: self.x | self.z,
+            z: 0,
+        }
+    }
+    pub fn and(&self, other: Word) -> Word {
+        Word::from_masks(
+            self.width,
+            self.ones() & other.ones(),
+            self.zeros() | other.zeros(),
+        )
+    }
+    pub fn or(&self, other: Word) -> Word {
+        Word::from_masks(
+            self.width,
+            self.ones() | other.ones(),
+            self.zeros() & other.zeros(),
+        )
+    }
+    pub fn xor(&self, other: Word) -> Word {
+        let known = !(self.x | self.z | other.x | other.z);
+        let v = self.val ^ other.val;
+        Word::from_masks(self.width, v & known, !v & known)
+    }
+    // bitwise `Signal::mux` of `a` and `b`
+    pub fn mux(sel: Signal, a: Word, b: Word) -> Word {
+        Word::from_fn(a.width(), |i| Signal::mux(sel, a.bit(i), b.bit(i)))
+    }
+    // `width` bits starting at bit `lsb`
+    pub fn slice(&self, lsb: u8, width: u8) -> Word {
+        let m = width_mask(width);
+        Word {
+            width,
+            val: (self.val >> lsb) & m,
+            x: (self.x >> lsb) & m,
+            z: (self.z >> lsb) & m,
+        }
+    }
+    // joins `parts` into one word, the first part taking the least
+    // significant bits
+    pub fn concat(parts: &[Word]) -> Word {
+        let width = parts.iter().map(|p| p.width as u32).sum::<u32>();
+        assert!(
+            width > 0 && width <= MAX_WIDTH as u32,
+            "bad bus width {}",
+            width
+        );
+        let mut w = Word::from_u64(0, width as u8);
+        let mut at = 0;
+        for p in parts {
+            w.val |= p.val << at;
+            w.x |= p.x << at;
+            w.z |= p.z << at;
+            at += p.width as u32;
+        }
+        w
+    }
+    // textual form used in netlists: a single char for one bit,
+    // `<width>'b<bits>` (most significant first) otherwise.
+    pub fn to_bin_string(&self) -> String {
+        if self.width == 1 {
+            return self.bit(0).to_string();
+        }
+        format!("{}'b{}", self.width, self.bits_msb_first())
+    }
+    pub fn parse(s: &str) -> Option<Word> {
+        let (width, bits) = match s.split_once("'b") {
+            Some((w, b)) => (w.parse::<u8>().ok()?, b),
+            None => (1, s),
+        };
+        let bits = bits
+            .chars()
+            .rev()
+            .map(Signal::from_char)
+            .collect::<Option<Vec<Signal>>>()?;
+        if width == 0 || width > MAX_WIDTH || bits.len() != width as usize {
+            return None;
+        }
+        Some(Word::from_bits(&bits))
+    }
+    fn bits_msb_first(&self) -> String {
+        (0..self.width as usize)
+            .rev()
+            .map(|i| self.bit(i).to_char())
+            .collect()
+    }
+}
+
+impl Default for Word {
+    fn default() -> Self {
+        Word::unknown(1)
+    }
+}
+
+impl From<Signal> for Word {
+    fn from(s: Signal) -> Self {
+        Word::splat(s, 1)
+    }
+}
+
+impl From<bool> for Word {
+    fn from(b: bool) -> Self {
+        Word::from(Signal::from(b))
+    }
+}
+
+impl PartialEq<Signal> for Word {
+    fn eq(&self, other: &Signal) -> bool {
+        self.width == 1 && self.bit(0) == *other
+    }
+}
+
+impl Not for Word {
+    type Output = Word;
+    fn not(self) -> Word {
+        Word::from_masks(self.width, self.zeros(), self.ones())
+    }
+}
+
+// single bits print as their level, fully known buses as numbers and
+// anything else bit by bit, most significant first.
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.width == 1 {
+            return write!(f, "{}", self.bit(0));
+        }
+        match self.to_u64() {
+            Some(v) => write!(f, "{}", v),
+            None => write!(f, "{}", self.bits_msb_first()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Signal::{self, One, Zero, X, Z},
+        Word,
+    };
+
+    #[test]
+    fn kleene_ops() {
+        assert_eq!(Zero.and(X), Zero);
+        assert_eq!(One.and(Z), X);
+        assert_eq!(One.or(X), One);
+        assert_eq!(Zero.or(Z), X);
+        assert_eq!(One.xor(Zero), One);
+        assert_eq!(One.xor(X), X);
+        assert_eq!(!Z, X);
+        assert_eq!(Signal::mux(X, One, One), One);
+        assert_eq!(Signal::mux(X, One, Zero), X);
+        for s in [Zero, One, X, Z] {
+            assert_eq!(Signal::from_char(s.to_char()), Some(s));
+        }
+    }
+
+    #[test]
+    fn words() {
+        let a = Word::from_bits(&[One, Zero, X, Z]);
+        assert_eq!(a.width(), 4);
+        assert_eq!(a.to_string(), "ZX01");
+        assert_eq!(a.to_bin_string(), "4'bZX01");
+        assert_eq!(Word::parse("4'bZX01"), Some(a));
+        assert_eq!(Word::parse("Z"), Some(Word::from(Z)));
+        assert_eq!(Word::parse("3'b01"), None);
+
+        let ones = Word::splat(One, 4);
+        let zeros = Word::splat(Zero, 4);
+        assert_eq!(a.and(zeros), zeros);
+        assert_eq!(a.and(ones).bits(), vec![One, Zero, X, X]);
+        assert_eq!(a.or(ones), ones);
+        assert_eq!((!a).bits(), vec![Zero, One, X, X]);
+        assert_eq!(a.xor(ones).bits(), vec![Zero, One, X, X]);
+
+        let n = Word::from_u64(0xA5, 8);
+        assert_eq!(n.to_string(), "165");
+        assert_eq!(n.slice(4, 4).to_u64(), Some(0xA));
+        assert_eq!(n.slice(0, 1), One);
+        assert_eq!(Word::from_u64(u64::MAX, 64).to_u64(), Some(u64::MAX));
+        assert!(n.is_high() && !Word::from_u64(0, 8).is_high());
+    }
+}
@@ -0,0 +1,345 @@
+// State tables of synchronous circuits.
+//
+// `BCircuit::gen_state_table` explores the states reachable from the
+// current one, `extract_fsm` (see `fsm`) the ones reachable from the
+// reset state, every clocked component cleared to 0 by
+// `BCircuit::reset_states`. For every present state and every input
+// combination it lists the next state after a pulse of the clocks, and
+// the outputs before it, i.e. Mealy outputs. Columns are, in order: the
+// present states `Q(t)`, the inputs, the next states `Q(t+1)`, the
+// tracked outputs, then optionally the inputs a chosen flip-flop type
+// would need for every state transition, e.g. `J_Q` and `K_Q`, X where
+// either value works. The circuit is restored afterwards.
+
+use std::collections::{HashSet, VecDeque};
+
+use crate::{
+    circuit::BCircuit,
+    components::update_component_state,
+    signal::{Signal, Word},
+    table::{counter_row, Table},
+    types::{CompType, ID},
+};
+
+pub const MAX_STATE_TABLE_ROWS: usize = 1 << 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Excitation {
+    D,
+    T,
+    JK,
+    SR,
+}
+
+impl Excitation {
+    pub fn inputs(self) -> &'static [&'static str] {
+        match self {
+            Excitation::D => &["D"],
+            Excitation::T => &["T"],
+            Excitation::JK => &["J", "K"],
+            Excitation::SR => &["S", "R"],
+        }
+    }
+    // the inputs taking the flip-flop from `q` to `next`
+    pub fn inputs_for(self, q: Signal, next: Signal) -> Vec<Signal> {
+        use Signal::{One, Zero, X};
+        if !q.is_known() || !next.is_known() {
+            return vec![X; self.inputs().len()];
+        }
+        let hold_or = |s: Signal, other: Signal| if s == other { X } else { Zero };
+        match (self, q) {
+            (Excitation::D, _) => vec![next],
+            (Excitation::T, _) => vec![Signal::from(q != next)],
+            (Excitation::JK, Zero) => vec![next, X],
+            (Excitation::JK, _) => vec![X, !next],
+            (Excitation::SR, Zero) => vec![next, hold_or(next, Zero)],
+            (Excitation::SR, _) => vec![hold_or(next, One), !next],
+        }
+    }
+    // `inputs_for` every bit of a register
+    fn words_for(self, q: Word, next: Word) -> Vec<Word> {
+        let bits = (0..q.width() as usize)
+            .map(|i| self.inputs_for(q.bit(i), next.bit(i)))
+            .collect::<Vec<Vec<Signal>>>();
+        (0..self.inputs().len())
+            .map(|j| Word::from_fn(q.width(), |i| bits[i][j]))
+            .collect()
+    }
+}
+
+impl BCircuit {
+    pub fn gen_state_table(
+        &mut self,
+        excitation: Option<Excitation>,
+    ) -> Result<Table<Word>, String> {
+        self.state_table_from(false, excitation).map(|(t, _)| t)
+    }
+    // the state table starting from the current states, or from the
+    // reset ones if `from_reset`, with the number of states
+    pub(crate) fn state_table_from(
+        &mut self,
+        from_reset: bool,
+        excitation: Option<Excitation>,
+    ) -> Result<(Table<Word>, usize), String> {
+        if self.clocks.is_empty() {
+            return Err("The circuit has no clock".into());
+        }
+        if !self.active {
+            self.power_on()?;
+        }
+        let (ports, outs) = self.ports();
+        let (states, inps): (Vec<ID>, Vec<ID>) = ports
+            .into_iter()
+            .partition(|id| self.components[id].borrow().comp_type != CompType::Input);
+        if states.is_empty() {
+            return Err("The circuit has no clocked components".into());
+        }
+        if let Some(id) = states
+            .iter()
+            .find(|id| self.components[id].borrow().memory().is_some())
+        {
+            return Err(format!(
+                "{} is a memory, its contents can't be tabulated",
+                self.describe(*id)
+            ));
+        }
+
+        let mut t = Table::<Word>::new();
+        let label = |id: &ID| self.components[id].borrow().label.clone();
+        let mut cols = states
+            .iter()
+            .map(|id| format!("{}(t)", label(id)))
+            .collect::<Vec<String>>();
+        cols.extend(inps.iter().map(label));
+        cols.extend(states.iter().map(|id| format!("{}(t+1)", label(id))));
+        cols.extend(outs.iter().map(label));
+        if let Some(e) = excitation {
+            for id in &states {
+                cols.extend(e.inputs().iter().map(|i| format!("{}_{}", i, label(id))));
+            }
+        }
+        t.set_columns(cols).unwrap();
+
+        let snapshot = states
+            .iter()
+            .chain(&inps)
+            .map(|id| (*id, self.state(*id).unwrap()))
+            .collect::<Vec<(ID, Word)>>();
+        if from_reset {
+            self.reset_states()?;
+        }
+        let res = self.explore_states(&mut t, &states, &inps, &outs, excitation);
+        let mut childs = Vec::new();
+        for (id, val) in snapshot {
+            childs.extend(self.drive(id, val)?);
+        }
+        self.graph_act(update_component_state, &childs)?;
+        res.map(|_| (t, states.len()))
+    }
+
+    fn explore_states(
+        &mut self,
+        t: &mut Table<Word>,
+        states: &[ID],
+        inps: &[ID],
+        outs: &[ID],
+        excitation: Option<Excitation>,
+    ) -> Result<(), String> {
+        let widths = self.input_widths(inps);
+        let total_bits = widths.iter().map(|w| *w as u32).sum::<u32>();
+        if 1u64 << total_bits.min(63) > MAX_STATE_TABLE_ROWS as u64 {
+            return Err(format!("Too many input combinations: 2^{}", total_bits));
+        }
+        let start = states
+            .iter()
+            .map(|id| self.state(*id).unwrap())
+            .collect::<Vec<Word>>();
+        let mut seen = HashSet::from([start.clone()]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(present) = queue.pop_front() {
+            for n in 0..1u64 << total_bits {
+                if t.rows.len() == MAX_STATE_TABLE_ROWS {
+                    return Err(format!("State table exceeds {} rows", MAX_STATE_TABLE_ROWS));
+                }
+                let row_inps = counter_row(n, &widths);
+                let mut childs = Vec::new();
+                for (id, val) in states
+                    .iter()
+                    .zip(&present)
+                    .chain(inps.iter().zip(&row_inps))
+                {
+                    childs.extend(self.drive(*id, *val)?);
+                }
+                self.graph_act(update_component_state, &childs)?;
+                let out_vals = outs
+                    .iter()
+                    .map(|id| self.state(*id).unwrap())
+                    .collect::<Vec<Word>>();
+                self.pulse_clock()?;
+                let next = states
+                    .iter()
+                    .map(|id| self.state(*id).unwrap())
+                    .collect::<Vec<Word>>();
+
+                let mut row = present.clone();
+                row.extend(row_inps);
+                row.extend(&next);
+                row.extend(out_vals);
+                if let Some(e) = excitation {
+                    for (q, n) in present.iter().zip(&next) {
+                        row.extend(e.words_for(*q, *n));
+                    }
+                }
+                t.rows.push(row);
+                if seen.insert(next.clone()) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Excitation;
+    use crate::{
+        circuit::BCircuit,
+        signal::{
+            Signal::{One, Zero, X},
+            Word,
+        },
+        types::CLOCK_PIN,
+    };
+
+    #[test]
+    fn excitations() {
+        let cases = [
+            (
+                Excitation::D,
+                [[Zero], [One], [Zero], [One]].map(|v| v.to_vec()),
+            ),
+            (
+                Excitation::T,
+                [[Zero], [One], [One], [Zero]].map(|v| v.to_vec()),
+            ),
+            (
+                Excitation::JK,
+                [[Zero, X], [One, X], [X, One], [X, Zero]].map(|v| v.to_vec()),
+            ),
+            (
+                Excitation::SR,
+                [[Zero, X], [One, Zero], [Zero, One], [X, Zero]].map(|v| v.to_vec()),
+            ),
+        ];
+        for (e, expected) in cases {
+            let got = [(Zero, Zero), (Zero, One), (One, Zero), (One, One)]
+                .map(|(q, n)| e.inputs_for(q, n));
+            assert_eq!(got.to_vec(), expected, "{:?}", e);
+        }
+        assert_eq!(Excitation::JK.inputs_for(X, One), [X, X]);
+    }
+
+    #[test]
+    fn mod3_counter() {
+        // D flip-flops counting 0, 1, 2 while EN is high
+        let mut c = BCircuit::new();
+        let clk = c.add_input("clk", Zero);
+        c.clock(clk);
+        let en = c.add_input("EN", One);
+        let a = c.add_component("D", "A").unwrap();
+        let b = c.add_component("D", "B").unwrap();
+        // A+ = EN ? B : A, B+ = EN ? !A.!B : B
+        let na = c.add_component("NOT", "").unwrap();
+        let nb = c.add_component("NOT", "").unwrap();
+        let nor = c.add_component("AND", "").unwrap();
+        c.connect(na, 1, a).unwrap();
+        c.connect(nb, 1, b).unwrap();
+        c.connect(nor, 1, na).unwrap();
+        c.connect(nor, 2, nb).unwrap();
+        let nen = c.add_component("NOT", "").unwrap();
+        c.connect(nen, 1, en).unwrap();
+        let mut mux = |hold, next| {
+            let (h, n, o) = (
+                c.add_component("AND", "").unwrap(),
+                c.add_component("AND", "").unwrap(),
+                c.add_component("OR", "").unwrap(),
+            );
+            c.connect(h, 1, nen).unwrap();
+            c.connect(h, 2, hold).unwrap();
+            c.connect(n, 1, en).unwrap();
+            c.connect(n, 2, next).unwrap();
+            c.connect(o, 1, h).unwrap();
+            c.connect(o, 2, n).unwrap();
+            o
+        };
+        let ma = mux(a, b);
+        let mb = mux(b, nor);
+        c.connect(a, CLOCK_PIN, clk).unwrap();
+        c.connect(a, 1, ma).unwrap();
+        c.connect(b, CLOCK_PIN, clk).unwrap();
+        c.connect(b, 1, mb).unwrap();
+        let f = c.add_component("AND", "F").unwrap();
+        c.connect(f, 1, a).unwrap();
+        c.connect(f, 2, en).unwrap();
+        c.track_output(f);
+        c.power_on().unwrap();
+        c.reset_states().unwrap();
+        c.pulse_clock().unwrap();
+        assert_eq!(c.state(b).unwrap(), One);
+
+        let t = c.gen_state_table(Some(Excitation::JK)).unwrap();
+        assert_eq!(
+            t.columns(),
+            &["A(t)", "B(t)", "EN", "A(t+1)", "B(t+1)", "F", "J_A", "K_A", "J_B", "K_B"]
+        );
+        let rows = t
+            .rows
+            .iter()
+            .map(|r| r.iter().map(|w| w.to_string()).collect::<String>())
+            .collect::<Vec<String>>();
+        // starting from state 01, state 11 is never reached
+        assert_eq!(
+            rows,
+            [
+                "0100100XX0",
+                "0111001XX1",
+                "100100X00X",
+                "101001X10X",
+                "0000000X0X",
+                "0010100X1X",
+            ]
+        );
+        // the circuit is left as it was
+        assert_eq!(c.state(b).unwrap(), One);
+        assert_eq!(c.state(en).unwrap(), One);
+
+        let t = c.gen_state_table(None).unwrap();
+        assert_eq!(t.columns().len(), 6);
+        assert!(BCircuit::new().gen_state_table(None).is_err());
+    }
+
+    #[test]
+    fn register_excitation() {
+        let mut c = BCircuit::new();
+        let clk = c.add_input("clk", Zero);
+        c.clock(clk);
+        let d = c.add_input("D", Word::from_u64(0, 2));
+        let en = c.add_input("EN", One);
+        let q = c.add_component_with_width("REG", "Q", 2).unwrap();
+        c.connect_named(q, "CLK", clk).unwrap();
+        c.connect_named(q, "D", d).unwrap();
+        c.connect_named(q, "EN", en).unwrap();
+        c.power_on().unwrap();
+        c.reset_states().unwrap();
+        let t = c.gen_state_table(Some(Excitation::T)).unwrap();
+        assert_eq!(t.columns(), &["Q(t)", "D", "EN", "Q(t+1)", "T_Q"]);
+        // every register value is reachable: 4 states x 8 inputs
+        assert_eq!(t.rows.len(), 32);
+        for row in &t.rows {
+            let [q, d, en, next, toggle] = [0, 1, 2, 3, 4].map(|i| row[i].to_u64().unwrap());
+            assert_eq!(next, if en == 1 { d } else { q });
+            assert_eq!(toggle, q ^ next);
+        }
+    }
+}
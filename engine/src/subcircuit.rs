@@ -0,0 +1,556 @@
+// Hierarchical circuits.
+//
+// `BCircuit::define_subcircuit` turns a circuit into a component type.
+// Its inputs, sorted by label, become the data pins of the new type.
+// Its tracked outputs, sorted by label, are packed into the component
+// output from the least significant bit up, so a single port is read
+// through a sliced connection, see `BCircuit::connect_port`.
+//
+// Instances are black boxes that evaluate a levelized copy of the
+// definition, which therefore has to be combinational. Any instance,
+// including ones of definitions with flip-flops, can instead be
+// flattened into the enclosing circuit, see `BCircuit::flatten`.
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::{
+    circuit::BCircuit,
+    components::{reducer, set_expressions, update_component_state, ComponentDefParams, Gate},
+    levelized::LevelizedCircuit,
+    signal::{Word, MAX_WIDTH},
+    types::{CompType, ID, NULL, PIN},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Port {
+    pub name: String,
+    pub width: u8,
+    // the input or output component in the definition
+    id: ID,
+}
+
+pub struct Subcircuit {
+    name: String,
+    inputs: Vec<Port>,
+    outputs: Vec<Port>,
+    circuit: BCircuit,
+    // None if the definition has clocked components or loops
+    compiled: Option<RefCell<LevelizedCircuit>>,
+}
+
+impl Subcircuit {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    // input ports, port i is read from pin i + 1
+    pub fn inputs(&self) -> &[Port] {
+        &self.inputs
+    }
+    pub fn outputs(&self) -> &[Port] {
+        &self.outputs
+    }
+    // instances can be simulated without flattening them
+    pub fn is_black_box(&self) -> bool {
+        self.compiled.is_some()
+    }
+    pub fn input_pin(&self, port: &str) -> Option<PIN> {
+        self.inputs
+            .iter()
+            .position(|p| p.name == port)
+            .map(|i| i + 1)
+    }
+    // (lsb, width) of an output port within the instance output
+    pub fn output_slice(&self, port: &str) -> Option<(u8, u8)> {
+        let mut lsb = 0;
+        for p in &self.outputs {
+            if p.name == port {
+                return Some((lsb, p.width));
+            }
+            lsb += p.width;
+        }
+        None
+    }
+    pub(crate) fn pin_width(&self, pin: PIN) -> u8 {
+        match pin {
+            0 => 1,
+            p => self.inputs[p - 1].width,
+        }
+    }
+    pub(crate) fn eval(&self, pins: &[Word]) -> Word {
+        let mut lc = self
+            .compiled
+            .as_ref()
+            .expect("only black box subcircuits are evaluated")
+            .borrow_mut();
+        for (port, val) in self.inputs.iter().zip(&pins[1..]) {
+            lc.set(port.id, *val).unwrap();
+        }
+        lc.eval();
+        let outs = self
+            .outputs
+            .iter()
+            .map(|p| lc.state(p.id).unwrap())
+            .collect::<Vec<Word>>();
+        Word::concat(&outs)
+    }
+}
+
+impl BCircuit {
+    // registers `sub` as the component type `name`. Inputs and outputs
+    // must have distinct, non empty labels, which name the ports.
+    pub fn define_subcircuit(&mut self, name: &str, sub: BCircuit) -> Result<(), String> {
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(format!("'{}' is not a valid component type name", name));
+        }
+        if self.component_definitions.contains_key(name) {
+            return Err(format!("Component type {} is already defined", name));
+        }
+        let port = |id: &ID| {
+            let c = sub.components[id].borrow();
+            Port {
+                name: c.label.clone(),
+                width: c.width(),
+                id: *id,
+            }
+        };
+        let mut inputs = sub
+            .components
+            .iter()
+            .filter(|(_, c)| c.borrow().comp_type == CompType::Input)
+            .map(|(id, _)| port(id))
+            .collect::<Vec<Port>>();
+        let mut outputs = sub.outputs.iter().map(port).collect::<Vec<Port>>();
+        for ports in [&mut inputs, &mut outputs] {
+            ports.sort_by(|a, b| a.name.cmp(&b.name));
+            if let Some(p) = ports.iter().find(|p| p.name.is_empty()) {
+                return Err(format!(
+                    "{} has no label to name its port",
+                    sub.describe(p.id)
+                ));
+            }
+            if let Some(w) = ports.windows(2).find(|w| w[0].name == w[1].name) {
+                return Err(format!("Port name {} is used twice", w[0].name));
+            }
+        }
+        let width = outputs.iter().map(|p| p.width as u32).sum::<u32>();
+        if width == 0 || width > MAX_WIDTH as u32 {
+            return Err(format!(
+                "Outputs of {} must be between 1 and {} bits wide in total, got {}",
+                name, MAX_WIDTH, width
+            ));
+        }
+        // instances of a black box share its circuit, so a writable
+        // memory, even an asynchronous one, makes them flattened
+        let writable = sub.components.values().any(|c| {
+            c.borrow()
+                .memory()
+                .is_some_and(|m| !m.borrow().is_read_only())
+        });
+        let compiled = match sub.levelize() {
+            Ok(lc) if lc.seq.is_empty() && !writable => Some(RefCell::new(lc)),
+            _ => None,
+        };
+        let def = Rc::new(Subcircuit {
+            name: name.to_string(),
+            inputs,
+            outputs,
+            circuit: sub,
+            compiled,
+        });
+        let sub = def.clone();
+        self.define_gate(ComponentDefParams {
+            name: name.to_string(),
+            label: String::new(),
+            comp_type: CompType::Combinational,
+            eval: reducer(move |v, _| sub.eval(v)),
+            default_inputs: def.inputs.len() as u16,
+            symbol: name.to_string(),
+            width: width as u8,
+            subcircuit: Some(def),
+            ..Default::default()
+        });
+        Ok(())
+    }
+
+    fn subcircuit_of(&self, id: ID) -> Result<Rc<Subcircuit>, String> {
+        match self.components.get(&id) {
+            Some(c) => c.borrow().subcircuit.clone().ok_or(format!(
+                "{} is not a subcircuit instance",
+                self.describe(id)
+            )),
+            None => Err(format!("No element with id_{}", id)),
+        }
+    }
+
+    // the pin of `instance` reading the input port `port`. Named pins
+    // of other components work too, e.g. "EN" of a counter.
+    pub fn port_pin(&self, instance: ID, port: &str) -> Result<PIN, String> {
+        match self.components.get(&instance) {
+            Some(c) => c.borrow().pin_named(port).ok_or(format!(
+                "{} has no input port {}",
+                c.borrow().name,
+                port
+            )),
+            None => Err(format!("No element with id_{}", instance)),
+        }
+    }
+
+    // connects `pin` of `receiver` to the output port `port` of `instance`
+    pub fn connect_port(
+        &mut self,
+        receiver: ID,
+        pin: PIN,
+        instance: ID,
+        port: &str,
+    ) -> Result<(), String> {
+        let sub = self.subcircuit_of(instance)?;
+        let (lsb, width) = sub
+            .output_slice(port)
+            .ok_or(format!("{} has no output port {}", sub.name, port))?;
+        match self.components.get(&receiver) {
+            Some(r) if pin < r.borrow().num_inputs() && r.borrow().pin_width(pin) != width => {
+                return Err(format!(
+                    "pin_{} of id_{} is {} bit(s) wide, port {} is {}",
+                    pin,
+                    receiver,
+                    r.borrow().pin_width(pin),
+                    port,
+                    width
+                ));
+            }
+            _ => {}
+        }
+        if sub.outputs.len() == 1 {
+            self.connect(receiver, pin, instance)
+        } else {
+            self.connect_slice(receiver, pin, instance, lsb)
+        }
+    }
+
+    // adds an instance of the subcircuit type `typ` and flattens it,
+    // returning its id and the buffers standing for its input ports
+    pub fn add_flattened(&mut self, typ: &str, label: &str) -> Result<(ID, Vec<ID>), String> {
+        let def = match self.component_definitions.get(typ) {
+            Some(d) if d.subcircuit.is_some() => d.clone(),
+            Some(_) => return Err(format!("{} is not a subcircuit", typ)),
+            None => return Err(format!("Component type not defined {}", typ)),
+        };
+        let mut c = Gate::from_params(ComponentDefParams {
+            label: label.to_string(),
+            ..def
+        });
+        let id = self.new_id();
+        c.id = id;
+        self.components.insert(id, RefCell::new(c));
+        let bufs = self.flatten(id)?;
+        Ok((id, bufs))
+    }
+
+    // replaces the subcircuit instance `id` by a copy of its definition.
+    // Inner components are labelled `<instance label>.<label>` and every
+    // input port becomes a buffer, returned in port order. `id` itself
+    // becomes a CONCAT of the output ports, so connections reading from
+    // the instance keep working.
+    pub fn flatten(&mut self, id: ID) -> Result<Vec<ID>, String> {
+        let sub = self.subcircuit_of(id)?;
+        let (label, srcs, recvs, state) = {
+            let c = self.components[&id].borrow();
+            let srcs = (1..c.num_inputs())
+                .map(|p| (c.input_pin_sources[p], c.pin_slice(p)))
+                .collect::<Vec<(ID, Option<u8>)>>();
+            (c.label.clone(), srcs, c.output_recvlist.clone(), c.state)
+        };
+        let prefix = if label.is_empty() {
+            format!("{}{}", sub.name, id)
+        } else {
+            label.clone()
+        };
+        for (pin, (src, _)) in srcs.iter().enumerate() {
+            if *src != NULL {
+                self.components[src]
+                    .borrow_mut()
+                    .unlink_output_receiver(id, pin + 1)?;
+            }
+        }
+
+        let mut inner = sub.circuit.components.keys().cloned().collect::<Vec<ID>>();
+        inner.sort();
+        let mut map = HashMap::new();
+        for old in &inner {
+            let g = sub.circuit.components[old].borrow();
+            let mut c = if g.comp_type == CompType::Input {
+                let mut b = self.make_component("BFR", &format!("{}.{}", prefix, g.label))?;
+                b.set_width(g.width());
+                b
+            } else {
+                let mut c = g.clone();
+                c.detach();
+                if !c.label.is_empty() {
+                    c.label = format!("{}.{}", prefix, c.label);
+                }
+                if c.comp_type == CompType::Sequential {
+                    c.state_expr = format!("{}(t)", c.label);
+                }
+                c
+            };
+            c.id = self.new_id();
+            map.insert(*old, c.id);
+            self.components.insert(c.id, RefCell::new(c));
+        }
+        for old in &inner {
+            let conns = {
+                let g = sub.circuit.components[old].borrow();
+                (0..g.num_inputs())
+                    .filter(|p| g.input_pin_sources[*p] != NULL)
+                    .map(|p| (p, g.input_pin_sources[p], g.pin_slice(p)))
+                    .collect::<Vec<(PIN, ID, Option<u8>)>>()
+            };
+            for (pin, src, slice) in conns {
+                self.do_connect(map[old], pin, map[&src], slice)?;
+            }
+        }
+        // the drivers of the instance now drive the input buffers
+        for (port, (src, slice)) in sub.inputs.iter().zip(&srcs) {
+            if *src != NULL {
+                self.do_connect(map[&port.id], 1, *src, *slice)?;
+            }
+        }
+
+        let mut cat = self.make_component("CONCAT", &label)?;
+        cat.set_width(state.width());
+        cat.add_input_pins(sub.outputs.len() - 1);
+        let mut widths = vec![1];
+        widths.extend(sub.outputs.iter().map(|p| p.width));
+        cat.set_pin_widths(&widths);
+        cat.id = id;
+        cat.state = state;
+        cat.output_recvlist = recvs;
+        self.components.insert(id, RefCell::new(cat));
+        for (i, port) in sub.outputs.iter().enumerate() {
+            self.do_connect(id, i + 1, map[&port.id], None)?;
+        }
+
+        let mut ids = map.values().cloned().collect::<Vec<ID>>();
+        ids.sort();
+        ids.push(id);
+        self.graph_act(update_component_state, &ids)?;
+        self.graph_act(set_expressions, &ids)?;
+        self.check_buses()?;
+        Ok(sub.inputs.iter().map(|p| map[&p.id]).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        circuit::BCircuit,
+        signal::{
+            Signal::{One, Zero},
+            Word,
+        },
+        types::{CLOCK_PIN, ID},
+    };
+
+    // ports A, B, Cin -> Cout, S
+    fn full_adder() -> BCircuit {
+        let mut c = BCircuit::new();
+        let a = c.add_input("A", Zero);
+        let b = c.add_input("B", Zero);
+        let cin = c.add_input("Cin", Zero);
+        let x = c.add_component("XOR", "").unwrap();
+        let s = c.add_component_with_inputs("XOR", "S", 3).unwrap();
+        let maj = c.add_component_with_inputs("OR", "Cout", 3).unwrap();
+        for (i, (p, q)) in [(a, b), (b, cin), (a, cin)].iter().enumerate() {
+            let g = c.add_component("AND", "").unwrap();
+            c.connect(g, 1, *p).unwrap();
+            c.connect(g, 2, *q).unwrap();
+            c.connect(maj, i + 1, g).unwrap();
+        }
+        c.connect(x, 1, a).unwrap();
+        c.connect(x, 2, b).unwrap();
+        c.connect(s, 1, a).unwrap();
+        c.connect(s, 2, b).unwrap();
+        c.connect(s, 3, cin).unwrap();
+        c.track_output(s);
+        c.track_output(maj);
+        c
+    }
+
+    // 4 bit ripple carry adder of full adder instances, returns the
+    // instances and the sum bits
+    fn adder(c: &mut BCircuit) -> (Vec<ID>, Vec<ID>) {
+        c.define_subcircuit("FA", full_adder()).unwrap();
+        let mut carry = c.add_input("C", Zero);
+        let (mut fas, mut sums) = (Vec::new(), Vec::new());
+        for i in 0..4 {
+            let a = c.add_input(&format!("A{}", i), Zero);
+            let b = c.add_input(&format!("B{}", i), Zero);
+            let fa = c.add_component("FA", "").unwrap();
+            c.connect(fa, c.port_pin(fa, "A").unwrap(), a).unwrap();
+            c.connect(fa, c.port_pin(fa, "B").unwrap(), b).unwrap();
+            c.connect(fa, c.port_pin(fa, "Cin").unwrap(), carry)
+                .unwrap();
+            let s = c.add_component("BFR", &format!("S{}", i)).unwrap();
+            c.connect_port(s, 1, fa, "S").unwrap();
+            c.track_output(s);
+            carry = c.add_component("BFR", "").unwrap();
+            c.connect_port(carry, 1, fa, "Cout").unwrap();
+            fas.push(fa);
+            sums.push(s);
+        }
+        c.set_component_label(carry, "S4").unwrap();
+        c.track_output(carry);
+        sums.push(carry);
+        c.power_on().unwrap();
+        (fas, sums)
+    }
+
+    fn check_sums(c: &mut BCircuit) {
+        // columns: A0 A1 A2 A3 B0 B1 B2 B3 C | S0 .. S4
+        let t = c.gen_bus_table().unwrap();
+        assert_eq!(t.rows.len(), 512);
+        for row in &t.rows {
+            let bits = |r: &[Word]| {
+                r.iter()
+                    .enumerate()
+                    .map(|(i, w)| (w.to_u64().unwrap()) << i)
+                    .sum::<u64>()
+            };
+            let (a, b, cin) = (bits(&row[0..4]), bits(&row[4..8]), bits(&row[8..9]));
+            assert_eq!(bits(&row[9..14]), a + b + cin);
+        }
+    }
+
+    #[test]
+    fn black_box_instances() {
+        let mut c = BCircuit::new();
+        let (fas, _) = adder(&mut c);
+        assert_eq!(c.state(fas[0]).unwrap().width(), 2);
+        check_sums(&mut c);
+        let expr = c
+            .get_component(&fas[0])
+            .unwrap()
+            .borrow()
+            .state_expr
+            .clone();
+        assert_eq!(expr, "FA(A0, B0, C)");
+
+        // instances round trip through netlists of circuits defining FA
+        let mut d = BCircuit::new();
+        d.define_subcircuit("FA", full_adder()).unwrap();
+        d.load_netlist(&c.to_netlist().unwrap()).unwrap();
+        assert_eq!(d.to_netlist(), c.to_netlist());
+        d.power_on().unwrap();
+        check_sums(&mut d);
+
+        assert!(c.define_subcircuit("FA", full_adder()).is_err());
+        assert!(c.port_pin(fas[0], "D").is_err());
+        let wide = c.add_input("W", Word::from_u64(0, 2));
+        assert!(c.connect_port(wide, 1, fas[0], "S").is_err());
+    }
+
+    #[test]
+    fn flattening() {
+        let mut c = BCircuit::new();
+        let (fas, sums) = adder(&mut c);
+        let reference = {
+            let mut r = BCircuit::new();
+            adder(&mut r);
+            r
+        };
+        let before = c.components().len();
+        let bufs = c.flatten(fas[1]).unwrap();
+        // 3 input buffers and the 6 gates of a full adder
+        assert_eq!(c.components().len(), before + 9);
+        assert_eq!(
+            c.get_component(&bufs[2]).unwrap().borrow().label,
+            "FA".to_string() + &fas[1].to_string() + ".Cin"
+        );
+        assert!(c.flatten(fas[1]).is_err());
+        for fa in &fas[2..] {
+            c.flatten(*fa).unwrap();
+        }
+        assert_eq!(c.check_equivalence(&reference), Ok(None));
+        check_sums(&mut c);
+        assert!(c.state(sums[4]).is_some());
+    }
+
+    #[test]
+    fn sequential_subcircuits() {
+        // a toggle flip-flop: T, clk -> Q
+        let mut t = BCircuit::new();
+        let en = t.add_input("T", Zero);
+        let clk = t.add_input("clk", Zero);
+        let q = t.add_component("JK", "Q").unwrap();
+        t.connect(q, CLOCK_PIN, clk).unwrap();
+        t.connect(q, 1, en).unwrap();
+        t.connect(q, 2, en).unwrap();
+        t.track_output(q);
+
+        let mut c = BCircuit::new();
+        c.define_subcircuit("TFF", t).unwrap();
+        assert!(c
+            .add_component("TFF", "")
+            .unwrap_err()
+            .contains("add_flattened"));
+        let one = c.add_input("1", One);
+        let clk = c.add_input("clk", Zero);
+        let (ff, bufs) = c.add_flattened("TFF", "T0").unwrap();
+        c.connect(bufs[0], 1, one).unwrap();
+        c.connect(bufs[1], 1, clk).unwrap();
+        c.clock(clk);
+        c.power_on().unwrap();
+        c.reset_states().unwrap();
+        for n in 1..=4 {
+            c.pulse_clock().unwrap();
+            assert_eq!(c.state(ff).unwrap(), Word::from(n % 2 == 1));
+        }
+        assert!(c.get_component(&ff).unwrap().borrow().name == "CONCAT");
+
+        let mut bad = BCircuit::new();
+        bad.add_input("", Zero);
+        assert!(c
+            .define_subcircuit("BAD", bad)
+            .unwrap_err()
+            .contains("label"));
+    }
+
+    #[test]
+    fn memories_are_not_shared() {
+        let mut m = BCircuit::new();
+        let a = m.add_input("A", Word::from_u64(0, 2));
+        let d = m.add_input("D", Word::from_u64(0, 4));
+        let we = m.add_input("WE", Zero);
+        let q = m.add_memory("ARAM", "Q", 2, 4).unwrap();
+        m.connect_named(q, "ADDR", a).unwrap();
+        m.connect_named(q, "DIN", d).unwrap();
+        m.connect_named(q, "WE", we).unwrap();
+        m.track_output(q);
+
+        let mut c = BCircuit::new();
+        c.define_subcircuit("MEM", m).unwrap();
+        assert!(c.add_component("MEM", "").is_err());
+        let addr = c.add_input("addr", Word::from_u64(1, 2));
+        let din = c.add_input("din", Word::from_u64(0, 4));
+        let we1 = c.add_input("we1", Zero);
+        let we2 = c.add_input("we2", Zero);
+        let (m1, bufs1) = c.add_flattened("MEM", "m1").unwrap();
+        let (m2, bufs2) = c.add_flattened("MEM", "m2").unwrap();
+        for (bufs, we) in [(bufs1, we1), (bufs2, we2)] {
+            c.connect(bufs[0], 1, addr).unwrap();
+            c.connect(bufs[1], 1, din).unwrap();
+            c.connect(bufs[2], 1, we).unwrap();
+        }
+        c.power_on().unwrap();
+        // write 3 through both, then 9 through m1 only
+        c.set_component_state(din, Word::from_u64(3, 4)).unwrap();
+        for we in [we1, we2] {
+            c.set_component_state(we, One).unwrap();
+            c.set_component_state(we, Zero).unwrap();
+        }
+        c.set_component_state(din, Word::from_u64(9, 4)).unwrap();
+        c.set_component_state(we1, One).unwrap();
+        assert_eq!(c.state(m1).unwrap(), Word::from_u64(9, 4));
+        assert_eq!(c.state(m2).unwrap(), Word::from_u64(3, 4));
+    }
+}
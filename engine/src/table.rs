@@ -1,5 +1,7 @@
 use std::{collections::HashMap, fmt};
 
+use crate::signal::Word;
+
 // a rudimentary implementation
 pub struct Table<T> {
     cols: Vec<String>,
@@ -24,6 +26,9 @@ impl<T: Default + Clone> Table<T> {
         }
         Ok(())
     }
+    pub fn columns(&self) -> &Vec<String> {
+        &self.cols
+    }
     pub fn set_rows(&mut self, rows: Vec<Vec<T>>) -> Result<(), String> {
         self.rows = rows;
         Ok(())
@@ -81,3 +86,15 @@ pub fn bitwise_counter(bits: usize) -> impl Iterator<Item = Vec<bool>> {
             .collect::<Vec<bool>>()
     })
 }
+
+// the n-th combination of words of the given widths, the last word
+// taking the least significant bits of `n`
+pub fn counter_row(n: u64, widths: &[u8]) -> Vec<Word> {
+    let mut rest = n;
+    let mut row = vec![Word::default(); widths.len()];
+    for i in (0..widths.len()).rev() {
+        row[i] = Word::from_u64(rest, widths[i]);
+        rest = rest.checked_shr(widths[i] as u32).unwrap_or(0);
+    }
+    row
+}
@@ -0,0 +1,446 @@
+// Event driven simulation with propagation delays.
+//
+// In timed mode every change is an event scheduled at an absolute
+// simulation time. When an event changes a component's output, the
+// receivers are re-evaluated and their new outputs are scheduled after
+// their rise or fall delay (see `Gate::delay`). Events at the same time
+// are applied together before any receiver is evaluated, so the result
+// does not depend on the order components are stored in.
+//
+// Delays are transport delays: a pulse shorter than a gate's delay still
+// shows up at its output. A new output change cancels the ones the gate
+// had scheduled at the same time or later.
+//
+// Structural edits (connect, disconnect, remove) and `power_on` still
+// settle in zero time, as in untimed mode.
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, BinaryHeap, HashMap, HashSet},
+};
+
+use crate::{
+    circuit::BCircuit,
+    signal::Word,
+    types::{CompType, Time, ID},
+};
+
+pub(crate) struct Timeline {
+    pub(crate) timed: bool,
+    // how long `run_until_quiescent` may run before the circuit is
+    // reported as oscillating
+    time_limit: Time,
+    pub(crate) now: Time,
+    seq: u64,
+    // (time, seq, component), earliest first
+    queue: BinaryHeap<Reverse<(Time, u64, ID)>>,
+    // value of every event that has not been cancelled, by seq
+    values: HashMap<u64, Word>,
+    // (time, seq) of the output changes each component has scheduled
+    projected: HashMap<ID, Vec<(Time, u64)>>,
+    // times of the pending `schedule_state` events
+    stimuli: BTreeSet<Time>,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Timeline {
+            timed: false,
+            time_limit: 10_000,
+            now: 0,
+            seq: 0,
+            queue: BinaryHeap::new(),
+            values: HashMap::new(),
+            projected: HashMap::new(),
+            stimuli: BTreeSet::new(),
+        }
+    }
+}
+
+impl Timeline {
+    fn push(&mut self, at: Time, id: ID, val: Word) -> u64 {
+        self.seq += 1;
+        self.queue.push(Reverse((at, self.seq, id)));
+        self.values.insert(self.seq, val);
+        self.seq
+    }
+    fn next_time(&self) -> Option<Time> {
+        self.queue.peek().map(|Reverse((t, ..))| *t)
+    }
+    // true if a stimulus is due by `t`, dropping the ones that are
+    fn take_stimuli(&mut self, t: Time) -> bool {
+        let later = self.stimuli.split_off(&(t + 1));
+        let due = !self.stimuli.is_empty();
+        self.stimuli = later;
+        due
+    }
+}
+
+impl BCircuit {
+    // switches between event driven simulation with delays and the
+    // default zero delay propagation.
+    pub fn set_timed(&mut self, timed: bool) {
+        self.timeline.timed = timed;
+    }
+    pub fn is_timed(&self) -> bool {
+        self.timeline.timed
+    }
+    pub fn set_time_limit(&mut self, limit: Time) {
+        self.timeline.time_limit = limit;
+    }
+    pub fn now(&self) -> Time {
+        self.timeline.now
+    }
+    // true if no events are scheduled
+    pub fn is_quiescent(&self) -> bool {
+        self.timeline.values.is_empty()
+    }
+    pub fn set_component_delay(&mut self, id: ID, rise: Time, fall: Time) -> Result<(), String> {
+        match self.components.get(&id) {
+            Some(c) => {
+                let mut c = c.borrow_mut();
+                c.rise_delay = rise;
+                c.fall_delay = fall;
+                Ok(())
+            }
+            None => Err(format!("Component with id_{} not found", id)),
+        }
+    }
+
+    // schedules `id` to be set to `val` at time `at`, e.g. an input
+    // stimulus. Scheduled states are not cancelled by later ones.
+    pub fn schedule_state(&mut self, id: ID, val: impl Into<Word>, at: Time) -> Result<(), String> {
+        let val = val.into();
+        let width = match self.components.get(&id) {
+            Some(c) => c.borrow().width(),
+            None => return Err(format!("No element with id_{}", id)),
+        };
+        if val.width() != width {
+            return Err(format!(
+                "id_{} is {} bit(s) wide, can't set it to the {} bit value {}",
+                id,
+                width,
+                val.width(),
+                val
+            ));
+        }
+        if at < self.timeline.now {
+            return Err(format!(
+                "Can't schedule at t={}, simulation is already at t={}",
+                at, self.timeline.now
+            ));
+        }
+        self.timeline.push(at, id, val);
+        self.timeline.stimuli.insert(at);
+        Ok(())
+    }
+
+    // processes every event up to and including time `t`
+    pub fn advance_to(&mut self, t: Time) -> Result<(), String> {
+        while let Some(next) = self.timeline.next_time() {
+            if next > t {
+                break;
+            }
+            self.timeline.now = next;
+            self.timeline.take_stimuli(next);
+            self.run_time_step(next)?;
+            self.record_changes();
+        }
+        self.timeline.now = self.timeline.now.max(t);
+        self.check_buses()
+    }
+
+    // processes events until none are left, returning the time the
+    // circuit settled at. The time limit applies from the last stimulus,
+    // so scheduled inputs far apart don't count as oscillation.
+    pub fn run_until_quiescent(&mut self) -> Result<Time, String> {
+        let mut since = self.timeline.now;
+        // past the deadline, watch a few more time steps to see which
+        // components keep changing
+        let window = 2 * self.components.len() + 16;
+        let mut left = window;
+        let mut unstable = HashSet::new();
+        while let Some(next) = self.timeline.next_time() {
+            self.timeline.now = next;
+            if self.timeline.take_stimuli(next) {
+                since = next;
+                left = window;
+                unstable.clear();
+            }
+            let changed = self.run_time_step(next)?;
+            self.record_changes();
+            if next > since + self.timeline.time_limit {
+                unstable.extend(changed);
+                left -= 1;
+                if left == 0 {
+                    return Err(format!(
+                        "Circuit still active {} time units after t={}, oscillating: {}",
+                        self.timeline.time_limit,
+                        since,
+                        self.describe_loop(&unstable)
+                    ));
+                }
+            }
+        }
+        self.check_buses()?;
+        Ok(self.timeline.now)
+    }
+
+    // drops every scheduled event, keeping the time and settings
+    pub(crate) fn clear_timeline(&mut self) {
+        let tl = &mut self.timeline;
+        tl.queue.clear();
+        tl.values.clear();
+        tl.projected.clear();
+        tl.stimuli.clear();
+    }
+
+    // applies the events at `t` and evaluates their receivers, repeating
+    // while zero delay changes keep arriving. Returns the components
+    // whose output changed.
+    fn run_time_step(&mut self, t: Time) -> Result<HashSet<ID>, String> {
+        let mut applied = HashSet::new();
+        let window = 2 * self.components.len() + 16;
+        let mut unstable = HashSet::new();
+        for round in 0..self.max_iters + window {
+            if self.timeline.next_time() != Some(t) {
+                return Ok(applied);
+            }
+            let mut touched = BTreeSet::new();
+            while self.timeline.next_time() == Some(t) {
+                let Reverse((_, seq, id)) = self.timeline.queue.pop().unwrap();
+                let val = match self.timeline.values.remove(&seq) {
+                    Some(v) => v,
+                    None => continue, // cancelled
+                };
+                if let Some(p) = self.timeline.projected.get_mut(&id) {
+                    p.retain(|e| e.1 != seq);
+                }
+                let changed = match self.components.get(&id) {
+                    Some(c) => c.borrow().state != val,
+                    None => false, // removed meanwhile
+                };
+                if changed {
+                    touched.extend(self.drive(id, val)?);
+                    applied.insert(id);
+                    if round >= self.max_iters {
+                        unstable.insert(id);
+                    }
+                }
+            }
+            for id in touched {
+                let c = self.components.get(&id).unwrap();
+                let idle = match &c.borrow().clock_manager {
+                    // a clocked component holds its scheduled output
+                    // until the next edge or asynchronous set/reset
+                    Some(_) => c.borrow().holds(),
+                    None => c.borrow().comp_type == CompType::Input,
+                };
+                if idle {
+                    continue;
+                }
+                let new_state = c.borrow_mut().next_state();
+                self.schedule_output(id, new_state, t);
+            }
+        }
+        self.clear_timeline();
+        Err(format!(
+            "Circuit did not settle within {} zero delay rounds at t={}, oscillating: {}",
+            self.max_iters,
+            t,
+            self.describe_loop(&unstable)
+        ))
+    }
+
+    // schedules the output of `id` to become `val`, `id` having been
+    // evaluated at time `t`.
+    fn schedule_output(&mut self, id: ID, val: Word, t: Time) {
+        let c = self.components.get(&id).unwrap().borrow();
+        let projected = self.projected_state(id).unwrap_or(c.state);
+        let at = t + c.delay(projected, val);
+
+        // the new change supersedes the ones scheduled at `at` or later
+        let tl = &mut self.timeline;
+        let pending = tl.projected.entry(id).or_default();
+        pending.retain(|(time, seq)| {
+            if *time >= at {
+                tl.values.remove(seq);
+            }
+            *time < at
+        });
+        let before = pending.last().map_or(c.state, |(_, seq)| tl.values[seq]);
+        if before == val {
+            return;
+        }
+        let seq = tl.push(at, id, val);
+        tl.projected.entry(id).or_default().push((at, seq));
+    }
+
+    // the last output value `id` has scheduled, if any
+    fn projected_state(&self, id: ID) -> Option<Word> {
+        let (_, seq) = self.timeline.projected.get(&id)?.last()?;
+        self.timeline.values.get(seq).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        circuit::BCircuit,
+        signal::Signal::{One, Zero},
+        types::CLOCK_PIN,
+    };
+
+    #[test]
+    fn glitch() {
+        // A . !A has a static hazard when A rises
+        let mut c = BCircuit::new();
+        let a = c.add_input("A", Zero);
+        let n = c.add_component("NOT", "").unwrap();
+        let and = c.add_component("AND", "").unwrap();
+        c.connect(n, 1, a).unwrap();
+        c.connect(and, 1, a).unwrap();
+        c.connect(and, 2, n).unwrap();
+        c.set_component_delay(n, 2, 2).unwrap();
+        c.power_on().unwrap();
+        c.set_timed(true);
+
+        c.schedule_state(a, One, 10).unwrap();
+        let mut trace = Vec::new();
+        for t in 9..15 {
+            c.advance_to(t).unwrap();
+            trace.push(c.state(and).unwrap().bit(0));
+        }
+        assert_eq!(trace, vec![Zero, Zero, One, One, Zero, Zero]);
+        assert!(c.is_quiescent());
+        assert!(c.schedule_state(a, Zero, 3).is_err());
+    }
+
+    #[test]
+    fn rise_and_fall_delays() {
+        let mut c = BCircuit::new();
+        let a = c.add_input("A", Zero);
+        let b = c.add_component("BFR", "").unwrap();
+        c.connect(b, 1, a).unwrap();
+        c.set_component_delay(b, 3, 5).unwrap();
+        c.power_on().unwrap();
+        c.set_timed(true);
+
+        c.set_component_state(a, One).unwrap();
+        assert_eq!(c.state(b).unwrap(), Zero);
+        assert_eq!(c.run_until_quiescent(), Ok(3));
+        assert_eq!(c.state(b).unwrap(), One);
+        c.set_component_state(a, Zero).unwrap();
+        assert_eq!(c.run_until_quiescent(), Ok(8));
+
+        // a pulse shorter than the delay still comes through
+        c.schedule_state(a, One, 10).unwrap();
+        c.schedule_state(a, Zero, 11).unwrap();
+        c.advance_to(13).unwrap();
+        assert_eq!(c.state(b).unwrap(), One);
+        c.advance_to(16).unwrap();
+        assert_eq!(c.state(b).unwrap(), Zero);
+    }
+
+    #[test]
+    fn ripple_counter() {
+        let mut c = BCircuit::new();
+        let one = c.add_input("1", One);
+        let clk = c.add_input("clk", Zero);
+        let q = c.add_component("JK", "Q1").unwrap();
+        let qq = c.add_component("JK", "Q2").unwrap();
+        let n = c.add_component("NOT", "!Q1").unwrap();
+        for ff in [q, qq] {
+            c.connect(ff, 1, one).unwrap();
+            c.connect(ff, 2, one).unwrap();
+        }
+        c.connect(q, CLOCK_PIN, clk).unwrap();
+        c.connect(n, 1, q).unwrap();
+        c.connect(qq, CLOCK_PIN, n).unwrap();
+        c.clock(clk);
+        c.power_on().unwrap();
+        c.reset_states().unwrap();
+        c.set_timed(true);
+
+        let mut counts = Vec::new();
+        for _ in 0..4 {
+            let start = c.now();
+            c.pulse_clock().unwrap();
+            c.advance_to(start + 1).unwrap();
+            let q1 = c.state(q).unwrap().bit(0);
+            // the second stage follows after the inverter and its own delay
+            let settled = c.run_until_quiescent().unwrap();
+            assert!(settled <= start + 3);
+            counts.push((q1, c.state(q).unwrap().bit(0), c.state(qq).unwrap().bit(0)));
+            c.advance_to(settled + 10).unwrap();
+        }
+        assert_eq!(
+            counts,
+            vec![
+                (One, One, Zero),
+                (Zero, Zero, One),
+                (One, One, One),
+                (Zero, Zero, Zero)
+            ]
+        );
+    }
+
+    fn ring(delay: u64) -> (BCircuit, i32) {
+        let mut c = BCircuit::new();
+        let en = c.add_input("EN", Zero);
+        let and = c.add_component("AND", "").unwrap();
+        let n = c.add_component("NOT", "").unwrap();
+        c.connect(and, 1, en).unwrap();
+        c.connect(n, 1, and).unwrap();
+        c.connect(and, 2, n).unwrap();
+        c.set_component_delay(and, delay, delay).unwrap();
+        c.set_component_delay(n, delay, delay).unwrap();
+        c.power_on().unwrap();
+        c.set_timed(true);
+        (c, en)
+    }
+
+    #[test]
+    fn oscillator_never_settles() {
+        let (mut c, en) = ring(1);
+        c.set_component_state(en, One).unwrap();
+        c.advance_to(10).unwrap();
+        assert!(!c.is_quiescent());
+        c.set_time_limit(100);
+        let err = c.run_until_quiescent().unwrap_err();
+        assert!(err.contains("id_2 AND, id_3 NOT"));
+        assert!(c.now() > 110 && c.now() < 200);
+
+        // without delays the loop can't make progress in time
+        let (mut c, en) = ring(0);
+        let err = c.set_component_state(en, One).unwrap_err();
+        assert!(err.contains("zero delay") && err.contains("id_2 AND, id_3 NOT"));
+        assert!(c.is_quiescent());
+    }
+
+    #[test]
+    fn sparse_stimuli_settle() {
+        // inputs scheduled far past the time limit are not oscillation
+        let mut c = BCircuit::new();
+        let a = c.add_input("A", Zero);
+        let b = c.add_component("BFR", "").unwrap();
+        c.connect(b, 1, a).unwrap();
+        c.power_on().unwrap();
+        c.set_timed(true);
+        c.set_time_limit(100);
+        for i in 1..=50 {
+            let val = if i % 2 == 1 { One } else { Zero };
+            c.schedule_state(a, val, 1000 * i).unwrap();
+        }
+        assert_eq!(c.run_until_quiescent(), Ok(50_001));
+        assert_eq!(c.state(b).unwrap(), Zero);
+
+        // the limit still applies after the last one
+        let (mut c, en) = ring(1);
+        c.set_time_limit(100);
+        c.schedule_state(en, One, 5000).unwrap();
+        let err = c.run_until_quiescent().unwrap_err();
+        assert!(err.contains("after t=5000"));
+        assert!(c.now() > 5100 && c.now() < 5200);
+    }
+}
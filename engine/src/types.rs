@@ -1,16 +1,23 @@
 use std::{
     cell::RefCell,
     collections::{HashMap, VecDeque},
+    rc::Rc,
 };
 
-use crate::components::Gate;
+use crate::{components::Gate, pattern::Lane, signal::Word};
 
-// logic reducer receives a vector of inputs and current state
-pub type BinaryLogicReducer = fn(&Vec<bool>, bool) -> bool;
+// logic reducer receives a vector of inputs and current state. It may
+// capture data, e.g. a lookup table, see `components::reducer`.
+pub type BinaryLogicReducer = Rc<dyn Fn(&[Word], Word) -> Word>;
+// bitwise counterpart of `BinaryLogicReducer`: receives the lanes of
+// every input pin and writes the lanes of the output
+pub type PatternReducer = fn(&[&[Lane]], &mut [Lane]);
 pub type ComponentActor = fn(&mut Gate, &HashMap<i32, RefCell<Gate>>, &mut VecDeque<ID>);
 
 pub type ID = i32;
 pub type PIN = usize;
+// simulation time, in arbitrary units
+pub type Time = u64;
 pub const NULL: ID = -1;
 pub const UNASSIGNED: ID = -2;
 
@@ -1,9 +1,22 @@
 pub fn form_expr(inex: &Vec<String>, sym: &String) -> String {
-    // some operators would be infix, some prefix.
-    if inex.len() == 2 {
-        return format!("{}{}", sym, inex[1]);
+    // named gates, e.g. subcircuits, read like function calls
+    if sym.starts_with(char::is_alphanumeric) {
+        return form_call(inex, sym);
     }
-    //todo: extend for more
-    return format!("({})", inex[1..].join(sym));
-    // return format!("{}{}{}", inex[1], sym, inex[2]);
+    // pin 0 is the clock, operands start at pin 1
+    let args = &inex[1..];
+    // single operand operators are prefix, e.g. NOT
+    if args.len() == 1 {
+        return format!("{}{}", sym, args[0]);
+    }
+    // negated operators, e.g. NAND gives !(A.B.C)
+    if let Some(op) = sym.strip_prefix('!').filter(|op| !op.is_empty()) {
+        return format!("!({})", args.join(op));
+    }
+    format!("({})", args.join(sym))
+}
+
+// black boxes read like function calls: FA(A, B, C)
+pub fn form_call(inex: &[String], name: &str) -> String {
+    format!("{}({})", name, inex[1..].join(", "))
 }
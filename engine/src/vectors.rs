@@ -0,0 +1,386 @@
+// Test vectors.
+//
+// `BCircuit::run_vectors` replays a stimulus file against the circuit and
+// checks the expected values of every step. The first non-comment line
+// names the columns, inputs left of a `|`, checked components right of
+// it, all by label:
+//
+//   # 2 bit ripple counter
+//   clk rst | Q1 Q2
+//   0   1   | 0  0
+//   C   0   | 1  0
+//   C   -   | 0  -
+//
+// Every following line is one step: the inputs are set, then the clocks
+// marked `C` are pulsed together (see `pulse_clock`), then the checked
+// components are compared. Values are written like
+// - `0`, `1`, `X`, `Z` for single bits,
+// - bits, most significant first, e.g. `01X0`, or `<width>'b<bits>`,
+// - decimal or `0x` prefixed hex numbers for buses.
+// `-` is a don't-care: the input keeps its value, or the output is not
+// checked. Single bits of a bus can also be `-`, e.g. `1--0`.
+// In timed mode each step runs until the circuit is quiescent.
+
+use std::{fmt, fs};
+
+use crate::{
+    circuit::BCircuit,
+    signal::{Signal, Word},
+    types::{CompType, ID},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mismatch {
+    pub column: String,
+    pub expected: String,
+    pub actual: Word,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StepResult {
+    // line of the step in the vector file, starting from 1
+    pub line: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl StepResult {
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VectorReport {
+    pub steps: Vec<StepResult>,
+}
+
+impl VectorReport {
+    pub fn passed(&self) -> bool {
+        self.steps.iter().all(|s| s.passed())
+    }
+    pub fn failures(&self) -> usize {
+        self.steps.iter().filter(|s| !s.passed()).count()
+    }
+    pub fn first_failure(&self) -> Option<&StepResult> {
+        self.steps.iter().find(|s| !s.passed())
+    }
+}
+
+impl fmt::Display for VectorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let first = self.first_failure().map(|s| s.line);
+        for (i, step) in self.steps.iter().enumerate() {
+            write!(f, "step {} (line {}): ", i + 1, step.line)?;
+            if step.passed() {
+                writeln!(f, "pass")?;
+                continue;
+            }
+            let found = step
+                .mismatches
+                .iter()
+                .map(|m| format!("{} expected {}, got {}", m.column, m.expected, m.actual))
+                .collect::<Vec<String>>();
+            write!(f, "FAIL {}", found.join("; "))?;
+            if first == Some(step.line) {
+                write!(f, "  <<< first mismatch")?;
+            }
+            writeln!(f)?;
+        }
+        write!(
+            f,
+            "{} of {} steps passed",
+            self.steps.len() - self.failures(),
+            self.steps.len()
+        )
+    }
+}
+
+#[derive(Clone)]
+struct Columns {
+    inputs: Vec<ID>,
+    // checked components with their column names
+    outputs: Vec<(String, ID)>,
+}
+
+enum Stimulus {
+    Keep,
+    Pulse,
+    Set(Word),
+}
+
+// a value and the mask of its bits that are checked
+fn parse_value(s: &str, width: u8) -> Result<(Word, u64), String> {
+    let all = if width == 64 {
+        u64::MAX
+    } else {
+        (1 << width) - 1
+    };
+    if s == "-" {
+        return Ok((Word::unknown(width), 0));
+    }
+    let bits = match s.split_once("'b") {
+        Some((w, b)) if w.parse::<u8>().ok() == Some(width) => b,
+        Some(_) => return Err(format!("'{}' is not {} bit(s) wide", s, width)),
+        None => s,
+    };
+    if bits.len() == width as usize && bits.chars().all(|c| "01XxZz-".contains(c)) {
+        let mut mask = 0;
+        let mut sigs = Vec::new();
+        for (i, ch) in bits.chars().rev().enumerate() {
+            match ch {
+                '-' => sigs.push(Signal::X),
+                _ => {
+                    mask |= 1 << i;
+                    sigs.push(Signal::from_char(ch.to_ascii_uppercase()).unwrap());
+                }
+            }
+        }
+        return Ok((Word::from_bits(&sigs), mask));
+    }
+    let val = match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse::<u64>(),
+    }
+    .map_err(|_| format!("bad value '{}'", s))?;
+    if val & !all != 0 {
+        return Err(format!("{} doesn't fit in {} bit(s)", s, width));
+    }
+    Ok((Word::from_u64(val, width), all))
+}
+
+fn matches(actual: Word, expected: Word, mask: u64) -> bool {
+    (0..actual.width() as usize)
+        .filter(|i| mask >> i & 1 == 1)
+        .all(|i| actual.bit(i) == expected.bit(i))
+}
+
+impl BCircuit {
+    // the component labelled `label`, inputs first
+    fn labelled(&self, label: &str) -> Result<ID, String> {
+        if let Some(id) = self.inputs.get(label) {
+            return Ok(*id);
+        }
+        let found = self
+            .components
+            .iter()
+            .filter(|(_, c)| c.borrow().label == label)
+            .map(|(id, _)| *id)
+            .collect::<Vec<ID>>();
+        match found[..] {
+            [id] => Ok(id),
+            [] => Err(format!("no component labelled '{}'", label)),
+            _ => Err(format!("more than one component labelled '{}'", label)),
+        }
+    }
+
+    // replays the test vectors of `text`, powering the circuit on if
+    // needed. Errors are for malformed vectors or a failing simulation,
+    // not for mismatches, which are in the report.
+    pub fn run_vectors(&mut self, text: &str) -> Result<VectorReport, String> {
+        let mut columns: Option<Columns> = None;
+        let mut steps = Vec::new();
+        if !self.active {
+            self.power_on()?;
+        }
+        for (i, line) in text.lines().enumerate() {
+            let lno = i + 1;
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let res = match &columns {
+                None => self.parse_columns(trimmed).map(|c| columns = Some(c)),
+                Some(cols) => {
+                    let cols = cols.clone();
+                    self.run_step(trimmed, &cols).map(|mismatches| {
+                        steps.push(StepResult {
+                            line: lno,
+                            mismatches,
+                        })
+                    })
+                }
+            };
+            res.map_err(|e| format!("line {}: {}", lno, e))?;
+        }
+        if columns.is_none() {
+            return Err("No test vectors".into());
+        }
+        Ok(VectorReport { steps })
+    }
+    pub fn run_vector_file(&mut self, path: &str) -> Result<VectorReport, String> {
+        let text =
+            fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+        self.run_vectors(&text)
+    }
+
+    fn parse_columns(&self, line: &str) -> Result<Columns, String> {
+        let (inps, outs) = line
+            .split_once('|')
+            .ok_or("expected '<inputs> | <outputs>' columns")?;
+        let inputs = inps
+            .split_whitespace()
+            .map(|l| {
+                let id = self.labelled(l)?;
+                match self.components[&id].borrow().comp_type {
+                    CompType::Input => Ok(id),
+                    _ => Err(format!("'{}' is not an input", l)),
+                }
+            })
+            .collect::<Result<Vec<ID>, String>>()?;
+        let outputs = outs
+            .split_whitespace()
+            .map(|l| Ok((l.to_string(), self.labelled(l)?)))
+            .collect::<Result<Vec<(String, ID)>, String>>()?;
+        Ok(Columns { inputs, outputs })
+    }
+
+    fn run_step(&mut self, line: &str, cols: &Columns) -> Result<Vec<Mismatch>, String> {
+        let (inps, outs) = (&cols.inputs, &cols.outputs);
+        let (set, check) = line.split_once('|').ok_or("missing '|'")?;
+        let set = set.split_whitespace().collect::<Vec<&str>>();
+        let check = check.split_whitespace().collect::<Vec<&str>>();
+        if set.len() != inps.len() || check.len() != outs.len() {
+            return Err(format!(
+                "expected {} input and {} output values",
+                inps.len(),
+                outs.len()
+            ));
+        }
+        let mut stimuli = Vec::new();
+        for (id, s) in inps.iter().zip(&set) {
+            let width = self.components[id].borrow().width();
+            stimuli.push(match *s {
+                "-" => Stimulus::Keep,
+                "C" if self.is_clock(*id) => Stimulus::Pulse,
+                _ => match parse_value(s, width)? {
+                    (w, mask) if mask.count_ones() == width as u32 => Stimulus::Set(w),
+                    _ => return Err(format!("input values can't have don't-cares: '{}'", s)),
+                },
+            });
+        }
+        let mut pulsed = Vec::new();
+        for (id, s) in inps.iter().zip(stimuli) {
+            match s {
+                Stimulus::Keep => {}
+                Stimulus::Pulse => pulsed.push(*id),
+                Stimulus::Set(w) => {
+                    if self.state(*id) != Some(w) {
+                        self.set_component_state(*id, w)?;
+                    }
+                }
+            }
+        }
+        if !pulsed.is_empty() {
+            let levels = pulsed
+                .iter()
+                .map(|id| (*id, self.state(*id).unwrap()))
+                .collect::<Vec<(ID, Word)>>();
+            let toggled = levels
+                .iter()
+                .map(|(id, v)| (*id, !*v))
+                .collect::<Vec<(ID, Word)>>();
+            self.drive_together(&toggled)?;
+            self.drive_together(&levels)?;
+        }
+        if self.is_timed() {
+            self.run_until_quiescent()?;
+        }
+        let mut mismatches = Vec::new();
+        for ((column, id), s) in outs.iter().zip(check) {
+            let actual = self.state(*id).unwrap();
+            let (expected, mask) = parse_value(s, actual.width())?;
+            if !matches(actual, expected, mask) {
+                mismatches.push(Mismatch {
+                    column: column.clone(),
+                    expected: s.to_string(),
+                    actual,
+                });
+            }
+        }
+        Ok(mismatches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        circuit::BCircuit,
+        signal::{Signal::Zero, Word},
+    };
+
+    #[test]
+    fn values() {
+        use super::parse_value;
+        assert_eq!(parse_value("1", 1).unwrap(), (Word::from(true), 1));
+        assert_eq!(parse_value("-", 4).unwrap().1, 0);
+        assert_eq!(parse_value("1-0x", 4).unwrap().1, 0b1011);
+        assert_eq!(
+            parse_value("4'b0110", 4).unwrap(),
+            (Word::from_u64(6, 4), 0b1111)
+        );
+        assert_eq!(parse_value("0xf", 4).unwrap().0, Word::from_u64(15, 4));
+        assert_eq!(parse_value("12", 4).unwrap().0, Word::from_u64(12, 4));
+        assert!(parse_value("16", 4).is_err());
+        assert!(parse_value("3'b010", 4).is_err());
+        assert!(parse_value("2", 1).is_err());
+    }
+
+    #[test]
+    fn adder_vectors() {
+        let mut c = BCircuit::new();
+        let a = c.add_input("A", Word::from_u64(0, 4));
+        let b = c.add_input("B", Word::from_u64(0, 4));
+        let s = c.add_component_with_width("XOR", "S", 4).unwrap();
+        c.connect(s, 1, a).unwrap();
+        c.connect(s, 2, b).unwrap();
+        let text = "# xor
+            A B | S
+            3 5 | 6
+            0xf - | 1010
+            - 4'b1111 | 0
+            1 1 | 1--1
+            2 - | 0101
+        ";
+        let report = c.run_vectors(text).unwrap();
+        assert_eq!(report.steps.len(), 5);
+        assert!(!report.passed());
+        assert_eq!(report.failures(), 2);
+        let first = report.first_failure().unwrap();
+        assert_eq!(first.line, 6);
+        assert_eq!(first.mismatches[0].actual, Word::from_u64(0, 4));
+        let text = report.to_string();
+        assert!(text.contains("step 1 (line 3): pass"));
+        assert!(text.contains("step 4 (line 6): FAIL S expected 1--1, got 0  <<< first mismatch"));
+        assert!(text.contains("step 5 (line 7): FAIL S expected 0101, got 3\n"));
+        assert!(text.ends_with("3 of 5 steps passed"));
+
+        assert!(c.run_vectors("A | T\n").unwrap_err().contains("line 1"));
+        assert!(c.run_vectors("S | S\n").is_err());
+        assert!(c.run_vectors("A | S\n1 1 | 1\n").is_err());
+        assert!(c.run_vectors("A | S\n1-1- | 1\n").is_err());
+        assert!(c.run_vectors("A B | S\nC 1 | 1\n").is_err());
+        assert!(c.run_vectors("# nothing\n").is_err());
+    }
+
+    #[test]
+    fn clocked_vectors() {
+        let mut c = BCircuit::new();
+        let clk = c.add_input("clk", Zero);
+        let d = c.add_input("d", Zero);
+        let q = c.add_component("D", "q").unwrap();
+        c.connect_named(q, "CLK", clk).unwrap();
+        c.connect_named(q, "D", d).unwrap();
+        c.clock(clk);
+        let report = c
+            .run_vectors(
+                "clk d | q
+                0 1 | X
+                C - | 1
+                - 0 | 1
+                C - | 0
+                1 - | 0",
+            )
+            .unwrap();
+        assert!(report.passed(), "{}", report);
+    }
+}
@@ -0,0 +1,355 @@
+// Waveform recording.
+//
+// `BCircuit::start_recording` logs every value change of the chosen
+// components, or of all labelled ones, until `stop_recording`. Changes
+// are sampled once the circuit has settled: in timed mode at the circuit
+// time, otherwise at a step number counting the updates that changed a
+// recorded value. Changes within a single time or step only keep the
+// final value.
+//
+// A recording is written as a Value Change Dump (IEEE 1364), one time
+// unit or step being written as 1 ns. The dump has no date, so runs can
+// be diffed.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write,
+    fs,
+};
+
+use crate::{
+    circuit::BCircuit,
+    signal::{Signal, Word},
+    types::{Time, ID},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trace {
+    pub id: ID,
+    pub name: String,
+    // (time or step, value), the first entry being the value when the
+    // recording started
+    pub changes: Vec<(Time, Word)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recording {
+    // whether the times are circuit times rather than step numbers
+    pub timed: bool,
+    pub traces: Vec<Trace>,
+    step: Time,
+}
+
+impl Recording {
+    // the value of trace `i` at time `t`, None before the recording
+    pub fn value_at(&self, i: usize, t: Time) -> Option<Word> {
+        let changes = &self.traces[i].changes;
+        let n = changes.partition_point(|(at, _)| *at <= t);
+        changes[..n].last().map(|(_, v)| *v)
+    }
+    pub fn to_vcd(&self) -> String {
+        let mut out = String::new();
+        out.push_str("$version bsim_engine $end\n");
+        out.push_str("$timescale 1ns $end\n");
+        out.push_str("$scope module circuit $end\n");
+        let mut times = BTreeMap::<Time, Vec<(String, Word)>>::new();
+        for (i, trace) in self.traces.iter().enumerate() {
+            let code = vcd_code(i);
+            let width = trace.changes.first().map_or(1, |(_, v)| v.width());
+            let range = match width {
+                1 => String::new(),
+                w => format!(" [{}:0]", w - 1),
+            };
+            writeln!(
+                out,
+                "$var wire {} {} {}{} $end",
+                width, code, trace.name, range
+            )
+            .unwrap();
+            for (t, v) in &trace.changes {
+                times.entry(*t).or_default().push((code.clone(), *v));
+            }
+        }
+        out.push_str("$upscope $end\n$enddefinitions $end\n");
+        for (i, (t, changes)) in times.iter().enumerate() {
+            writeln!(out, "#{}", t).unwrap();
+            if i == 0 {
+                out.push_str("$dumpvars\n");
+            }
+            for (code, v) in changes {
+                match v.width() {
+                    1 => writeln!(out, "{}{}", vcd_bit(v.bit(0)), code),
+                    _ => {
+                        let bits = v
+                            .bits()
+                            .iter()
+                            .rev()
+                            .map(|b| vcd_bit(*b))
+                            .collect::<String>();
+                        writeln!(out, "b{} {}", bits, code)
+                    }
+                }
+                .unwrap();
+            }
+            if i == 0 {
+                out.push_str("$end\n");
+            }
+        }
+        out
+    }
+}
+
+// identifiers are strings of the printable characters '!' to '~'
+fn vcd_code(mut i: usize) -> String {
+    let mut code = String::new();
+    loop {
+        code.push((b'!' + (i % 94) as u8) as char);
+        i /= 94;
+        if i == 0 {
+            return code;
+        }
+        i -= 1;
+    }
+}
+
+fn vcd_bit(s: Signal) -> char {
+    s.to_char().to_ascii_lowercase()
+}
+
+impl BCircuit {
+    // records the components `ids`, or every labelled component if
+    // `ids` is empty. Replaces the current recording, if any.
+    pub fn start_recording(&mut self, ids: &[ID]) -> Result<(), String> {
+        let mut ids = ids.to_vec();
+        if ids.is_empty() {
+            ids = self
+                .components
+                .iter()
+                .filter(|(_, c)| !c.borrow().label.is_empty())
+                .map(|(id, _)| *id)
+                .collect();
+            ids.sort_by_key(|id| (self.components[id].borrow().label.clone(), *id));
+        }
+        let timed = self.is_timed();
+        let start = if timed { self.now() } else { 0 };
+        let mut names = HashSet::new();
+        let mut traces = Vec::new();
+        for id in ids {
+            let c = match self.components.get(&id) {
+                Some(c) => c.borrow(),
+                None => return Err(format!("No element with id_{}", id)),
+            };
+            let mut name = c.label.split_whitespace().collect::<Vec<&str>>().join("_");
+            if name.is_empty() || !names.insert(name.clone()) {
+                name = format!("{}_{}", if name.is_empty() { "id" } else { &name }, id);
+                names.insert(name.clone());
+            }
+            traces.push(Trace {
+                id,
+                name,
+                changes: vec![(start, c.state)],
+            });
+        }
+        self.recording = Some(Recording {
+            timed,
+            traces,
+            step: 0,
+        });
+        Ok(())
+    }
+    pub fn stop_recording(&mut self) -> Option<Recording> {
+        self.recording.take()
+    }
+    pub fn recording(&self) -> Option<&Recording> {
+        self.recording.as_ref()
+    }
+    pub fn save_vcd(&self, path: &str) -> Result<(), String> {
+        let rec = self.recording.as_ref().ok_or("Nothing is being recorded")?;
+        fs::write(path, rec.to_vcd()).map_err(|e| format!("Could not write {}: {}", path, e))
+    }
+
+    // logs the recorded components whose state changed, called once
+    // the circuit has settled
+    pub(crate) fn record_changes(&mut self) {
+        let now = self.now();
+        let rec = match &mut self.recording {
+            Some(rec) => rec,
+            None => return,
+        };
+        let changed = rec
+            .traces
+            .iter()
+            .enumerate()
+            .filter_map(|(i, trace)| {
+                let state = self.components.get(&trace.id)?.borrow().state;
+                let (_, last) = trace.changes.last().unwrap();
+                (state != *last).then_some((i, state))
+            })
+            .collect::<Vec<(usize, Word)>>();
+        if changed.is_empty() {
+            return;
+        }
+        let t = if rec.timed {
+            now
+        } else {
+            rec.step += 1;
+            rec.step
+        };
+        for (i, state) in changed {
+            let changes = &mut rec.traces[i].changes;
+            if changes.last().unwrap().0 == t {
+                // settled again within the same time step
+                changes.pop();
+                if changes.last().map(|(_, v)| *v) == Some(state) {
+                    continue;
+                }
+            }
+            changes.push((t, state));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        circuit::BCircuit,
+        signal::{
+            Signal::{One, Zero},
+            Word,
+        },
+        types::CLOCK_PIN,
+    };
+
+    #[test]
+    fn record_steps() {
+        let mut c = BCircuit::new();
+        let clk = c.add_input("clk", Zero);
+        let one = c.add_input("1", One);
+        let q = c.add_component_with_width("COUNTER", "Q", 2).unwrap();
+        c.connect_named(q, "CLK", clk).unwrap();
+        c.connect_named(q, "EN", one).unwrap();
+        c.connect_named(q, "UP", one).unwrap();
+        let zero = c.add_input("load", Zero);
+        c.connect_named(q, "LOAD", zero).unwrap();
+        let n = c.add_component("NOT", "not clk").unwrap();
+        c.connect(n, 1, clk).unwrap();
+        c.clock(clk);
+        c.power_on().unwrap();
+        c.reset_states().unwrap();
+
+        c.start_recording(&[q, clk, n]).unwrap();
+        for _ in 0..2 {
+            c.pulse_clock().unwrap();
+        }
+        // unchanged values are not logged
+        c.set_component_state(one, One).unwrap();
+        let rec = c.stop_recording().unwrap();
+        assert!(c.recording().is_none());
+        assert!(!rec.timed);
+        assert_eq!(rec.traces[2].name, "not_clk");
+        assert_eq!(
+            rec.traces[0].changes,
+            [
+                (0, Word::from_u64(0, 2)),
+                (1, Word::from_u64(1, 2)),
+                (3, Word::from_u64(2, 2))
+            ]
+        );
+        assert_eq!(rec.traces[1].changes.len(), 5);
+        assert_eq!(rec.value_at(2, 2), Some(One.into()));
+        assert_eq!(
+            rec.to_vcd(),
+            "$version bsim_engine $end
+$timescale 1ns $end
+$scope module circuit $end
+$var wire 2 ! Q [1:0] $end
+$var wire 1 \" clk $end
+$var wire 1 # not_clk $end
+$upscope $end
+$enddefinitions $end
+#0
+$dumpvars
+b00 !
+0\"
+1#
+$end
+#1
+b01 !
+1\"
+0#
+#2
+0\"
+1#
+#3
+b10 !
+1\"
+0#
+#4
+0\"
+1#
+"
+        );
+    }
+
+    #[test]
+    fn record_labelled() {
+        let mut c = BCircuit::new();
+        let a = c.add_input("a", Zero);
+        let n1 = c.add_component("NOT", "n").unwrap();
+        let n2 = c.add_component("NOT", "n").unwrap();
+        let hidden = c.add_component("NOT", "").unwrap();
+        c.connect(n1, 1, a).unwrap();
+        c.connect(n2, 1, n1).unwrap();
+        c.connect(hidden, 1, n2).unwrap();
+        c.power_on().unwrap();
+        c.start_recording(&[]).unwrap();
+        let names = c
+            .recording()
+            .unwrap()
+            .traces
+            .iter()
+            .map(|t| t.name.clone())
+            .collect::<Vec<String>>();
+        assert_eq!(names, ["a", "n", format!("n_{}", n2).as_str()]);
+        assert!(c.start_recording(&[99]).is_err());
+        assert!(c.save_vcd("/nonexistent/dir/x.vcd").is_err());
+    }
+
+    #[test]
+    fn record_timed() {
+        let mut c = BCircuit::new();
+        let clk = c.add_clock("clk", 10, 5, 50).unwrap();
+        let d = c.add_input("d", One);
+        let q = c.add_component("D", "q").unwrap();
+        c.connect(q, CLOCK_PIN, clk).unwrap();
+        c.connect(q, 1, d).unwrap();
+        c.set_component_delay(q, 2, 2).unwrap();
+        let n = c.add_component("NOT", "n").unwrap();
+        c.connect(n, 1, q).unwrap();
+        c.set_component_delay(n, 1, 1).unwrap();
+        c.power_on().unwrap();
+        c.reset_states().unwrap();
+        c.set_timed(true);
+        c.start_recording(&[]).unwrap();
+        c.run_clocks(20).unwrap();
+        let rec = c.recording().unwrap();
+        assert!(rec.timed);
+        let names = rec
+            .traces
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(names, ["clk", "d", "n", "q"]);
+        assert_eq!(rec.traces[3].changes, [(0, Zero.into()), (7, One.into())]);
+        assert_eq!(rec.traces[2].changes, [(0, One.into()), (8, Zero.into())]);
+        assert_eq!(
+            rec.traces[0]
+                .changes
+                .iter()
+                .map(|c| c.0)
+                .collect::<Vec<u64>>(),
+            [0, 5, 10, 15, 20]
+        );
+        assert!(rec.to_vcd().contains("#7\n1$\n#8\n0#\n"));
+    }
+}
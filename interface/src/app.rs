@@ -3,7 +3,7 @@ use std::{
     ops::RangeInclusive,
 };
 
-use bsim_engine::circuit::BCircuit;
+use bsim_engine::{circuit::BCircuit, signal::Signal};
 use egui::{
     pos2, scroll_area::ScrollBarVisibility, Align, Button, Color32, Context, FontId, Label, Layout,
     Painter, ScrollArea, Sense, Slider, Stroke, Ui,
@@ -44,8 +44,8 @@ impl SimulatorUI {
     pub fn new(ctx: Context) -> Self {
         let mut ckt = BCircuit::new();
         ckt.compile();
-        ckt.power_on();
-        let clk_id = ckt.add_input("CLK", false);
+        ckt.power_on().unwrap();
+        let clk_id = ckt.add_input("CLK", Signal::Zero);
         ckt.clock(clk_id);
 
         let mut display_state = DisplayState::init_display_state(clk_id, ctx);
@@ -165,6 +165,7 @@ impl SimulatorUI {
                     .unwrap()
                     .borrow()
                     .state
+                    .is_high()
             );
             pt.line(
                 wire.pts
@@ -73,10 +73,10 @@ pub fn paint_component(
             container,
             match gate {
                 Some(g) => {
-                    if g.borrow().active {
-                        Some(g.borrow().state)
+                    if g.borrow().state.is_known() {
+                        Some(g.borrow().state.is_high())
                     } else {
-                        None // grey it out if inactive
+                        None // grey it out if unknown or floating
                     }
                 }
                 None => None, // grey it out if no state indicator ref
@@ -379,7 +379,7 @@ fn draw_component_shape(
             ];
             let mut i = 0;
             for out in &disp_data.outputs_rel {
-                let state = ckt_comps.get(&out.id).unwrap().borrow().state;
+                let state = ckt_comps.get(&out.id).unwrap().borrow().state.is_high();
                 painter.line(
                     segs[i]
                         .iter()
@@ -1,6 +1,6 @@
 use std::collections::VecDeque;
 
-use bsim_engine::circuit::BCircuit;
+use bsim_engine::{circuit::BCircuit, types::ID};
 use egui::{pos2, vec2, Pos2};
 
 use crate::{
@@ -34,22 +34,32 @@ pub fn ckt_communicate(
         let result = match rec {
             CircuitUpdateOps::SetState(id, val) => ckt.set_component_state(id, val),
             CircuitUpdateOps::Connect(er_pair) => {
-                let res = ckt.connect(
-                    er_pair.receiver.1.id,
-                    er_pair.receiver.1.pin,
-                    er_pair.emitter.1.id,
-                );
+                let res = if is_multi_driver(ckt, er_pair.receiver.1.id) {
+                    // a bus takes any number of drivers on its input port
+                    ckt.connect_driver(er_pair.receiver.1.id, er_pair.emitter.1.id)
+                        .map(|_| ())
+                } else {
+                    ckt.connect(
+                        er_pair.receiver.1.id,
+                        er_pair.receiver.1.pin,
+                        er_pair.emitter.1.id,
+                    )
+                };
                 if res.is_ok() {
                     ui_sender.push_back(UiUpdateOps::Connect(er_pair));
                 }
                 res
             }
             CircuitUpdateOps::Disconnect(er_pair) => {
-                let res = ckt.disconnect(
-                    er_pair.receiver.1.id,
-                    er_pair.receiver.1.pin,
-                    er_pair.emitter.1.id,
-                );
+                let res = if is_multi_driver(ckt, er_pair.receiver.1.id) {
+                    ckt.disconnect_driver(er_pair.receiver.1.id, er_pair.emitter.1.id)
+                } else {
+                    ckt.disconnect(
+                        er_pair.receiver.1.id,
+                        er_pair.receiver.1.pin,
+                        er_pair.emitter.1.id,
+                    )
+                };
                 if res.is_ok() {
                     ui_sender.push_back(UiUpdateOps::Disconnect(er_pair));
                 }
@@ -72,6 +82,13 @@ pub fn ckt_communicate(
     }
 }
 
+fn is_multi_driver(ckt: &BCircuit, id: ID) -> bool {
+    match ckt.get_component(&id) {
+        Some(g) => g.borrow().multi_driver,
+        None => false,
+    }
+}
+
 pub fn ui_update(
     receiver: &mut VecDeque<UiUpdateOps>,
     ds: &mut DisplayState,
@@ -3,6 +3,7 @@ use std::{borrow::Cow, cmp::max, collections::HashSet};
 use bsim_engine::{
     circuit::BCircuit,
     components::Gate,
+    signal::Signal,
     types::{ID, PIN},
 };
 use egui::{vec2, Button, ComboBox, Id, TextEdit, Ui, Vec2, Widget};
@@ -107,7 +108,7 @@ impl TopBarOption {
                 let response = button.ui(ui);
                 if response.clicked() {
                     let id = match name.as_str() {
-                        "Input" => ckt.add_input("", false),
+                        "Input" => ckt.add_input("", Signal::Zero),
                         _ => ckt.add_component(name, "").unwrap(),
                     };
                     let data = compose_comp_data(&ckt.get_component(&id).unwrap().borrow(), *scale);
@@ -175,7 +176,9 @@ impl TopBarOption {
 
 pub fn compose_comp_data(gate: &Gate, scale: f32) -> CompDisplayData {
     let loc = egui::pos2(40.0 + 80.0, 100.0) / GRID_UNIT_SIZE;
-    let size: Vec2 = (8.0, 8.0).into();
+    // data pins are spread over the left edge, gates with many inputs
+    // grow taller to keep them 2 units apart
+    let size: Vec2 = (8.0, f32::max(8.0, 2.0 * gate.num_inputs() as f32)).into();
     let spc = size.y / (gate.num_inputs()) as f32;
     let inputs_rel = (0..gate.num_inputs())
         .map(|i| {
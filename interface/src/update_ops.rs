@@ -1,9 +1,12 @@
-use bsim_engine::types::{ID, PIN};
+use bsim_engine::{
+    signal::Word,
+    types::{ID, PIN},
+};
 
 use crate::{display_elems::{CompDisplayData}, utils::EmitterReceiverPair};
 
 pub enum CircuitUpdateOps {
-    SetState(ID, bool),
+    SetState(ID, Word),
     Connect(EmitterReceiverPair),
     Disconnect(EmitterReceiverPair),
     Remove(ID),